@@ -3,6 +3,7 @@ use godot::engine::{
 };
 use godot::prelude::*;
 
+use crate::scene::dialog::core_dialog::SqoreDialog;
 use crate::scene::game_globals::Sqore;
 use crate::scene::serialization::SqoreSerialized;
 
@@ -49,6 +50,31 @@ impl IEditorPlugin for SqoreEditorUtils {
                 Ok(Variant::nil())
             }),
             &mut cmd,
+        );
+        self.register_tool_item(
+            "lint_dialog_tracks",
+            "Checks every dialog track in the configured tracks folder (addons/sqore/dialog_tracks_folder) for mistakes and prints the results",
+            Callable::from_fn("lint_dialog_tracks", |_| {
+                Self::lint_dialog_tracks();
+                Ok(Variant::nil())
+            }),
+            &mut cmd,
+        );
+        self.register_tool_item(
+            "extract_dialog_translations",
+            "Writes the translatable text of every dialog track in the configured tracks folder to a translation file (addons/sqore/dialog_translations_output). Use a .csv or .pot extension",
+            Callable::from_fn("extract_dialog_translations", |_| {
+                let output = Sqore::get_or_init_default(
+                    Self::DIALOG_TRANSLATIONS_OUTPUT_SETTING,
+                    Self::DIALOG_TRANSLATIONS_OUTPUT_DEFAULT.to_godot(),
+                )
+                .unwrap_or(Self::DIALOG_TRANSLATIONS_OUTPUT_DEFAULT.to_godot());
+                SqoreDialog::singleton()
+                    .bind()
+                    .extract_translations(Self::get_dialog_tracks_folder(), output);
+                Ok(Variant::nil())
+            }),
+            &mut cmd,
         )
     }
 
@@ -57,6 +83,56 @@ impl IEditorPlugin for SqoreEditorUtils {
 
 impl SqoreEditorUtils {
     const DOC_ENTRY_INDEX: &'static str = "res://addons/sqore/doc/sqore/index.html";
+    const DIALOG_TRACKS_FOLDER_SETTING: &'static str = "dialog_tracks_folder";
+    const DIALOG_TRACKS_FOLDER_DEFAULT: &'static str = "res://dialog/";
+    const DIALOG_TRANSLATIONS_OUTPUT_SETTING: &'static str = "dialog_translations_output";
+    const DIALOG_TRANSLATIONS_OUTPUT_DEFAULT: &'static str = "res://dialog_translations.csv";
+
+    fn get_dialog_tracks_folder() -> GString {
+        Sqore::get_or_init_default(
+            Self::DIALOG_TRACKS_FOLDER_SETTING,
+            Self::DIALOG_TRACKS_FOLDER_DEFAULT.to_godot(),
+        )
+        .unwrap_or(Self::DIALOG_TRACKS_FOLDER_DEFAULT.to_godot())
+    }
+
+    fn lint_dialog_tracks() {
+        let folder = Self::get_dialog_tracks_folder();
+        let diagnostics = SqoreDialog::singleton()
+            .bind()
+            .lint_track_folder(folder.clone());
+        for diagnostic in diagnostics.iter_shared() {
+            // nested nodes are printed as a path, e.g. "3/1/0" for the first line of the second option of the choice at 3
+            let position = diagnostic
+                .get("position")
+                .and_then(|position| Array::<i64>::try_from_variant(&position).ok())
+                .filter(|position| !position.is_empty())
+                .map(|position| {
+                    position
+                        .iter_shared()
+                        .map(|index| index.to_string())
+                        .collect::<Vec<_>>()
+                        .join("/")
+                })
+                .unwrap_or(diagnostic.get_or_nil("line").to_string());
+            let message = format!(
+                "{}:{} {}",
+                diagnostic.get_or_nil("file"),
+                position,
+                diagnostic.get_or_nil("message")
+            );
+            if diagnostic.get_or_nil("severity").to_string() == "error" {
+                godot_error!("{}", message);
+            } else {
+                godot_warn!("{}", message);
+            }
+        }
+        godot_print!(
+            "Linted dialog tracks in \"{}\": {} problem(s) found",
+            folder,
+            diagnostics.len()
+        );
+    }
 
     /// registers a callable command in both the tools dropdown pane of the editor and the command palette for quick access
     fn register_tool_item(
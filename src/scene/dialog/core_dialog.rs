@@ -1,16 +1,24 @@
-use std::collections::VecDeque;
+use std::collections::{HashMap, HashSet};
 
 use godot::{
-    engine::{self, Engine, Json},
+    engine::{self, file_access::ModeFlags, Engine, FileAccess, Json, ProjectSettings, Time},
     prelude::*,
 };
 
-use crate::util::SqoreUtil;
+use crate::{
+    scene::{game_globals::Sqore, serialization::SaveDataBuilder},
+    util::SqoreUtil,
+};
 
 use super::{
-    dialog_blackboard::{Blackboard, Entry},
+    dialog_bark::DialogBark,
+    dialog_blackboard::{Blackboard, CommandCall, Entry, Scope},
     dialog_events::DialogEvents,
     dialog_gui::DialogGUI,
+    dialog_history::DialogHistoryEntry,
+    dialog_lint::{lint_track, DialogDiagnostic, KnownCommands},
+    dialog_localization::TranslationCatalog,
+    dialog_runner::{AvailableOption, DialogEvent, DialogRunner},
     dialog_settings::DialogSettings,
     dialog_track::{DialogError, DialogTrack, Line},
 };
@@ -18,7 +26,8 @@ use super::{
 #[derive(GodotClass)]
 #[class(init, base=Object)]
 pub struct SqoreDialog {
-    current_track: Option<DialogTrack>,
+    /// plays the current track, `None` until a track is loaded
+    runner: Option<DialogRunner>,
 
     #[var]
     override_settings: Option<Gd<DialogSettings>>,
@@ -26,6 +35,25 @@ pub struct SqoreDialog {
     pub event_bus: Option<Gd<DialogEvents>>,
     pub gui: Option<Gd<DialogGUI>>,
     pub blackboard: Blackboard,
+    /// Every line displayed and choice made, in order. Kept until [SqoreDialog::clear_history] is called
+    pub history: Vec<DialogHistoryEntry>,
+    /// When enabled, lines advance on their own once revealed, after a delay based on their word count
+    #[var]
+    pub auto_mode: bool,
+    /// When enabled, lines the player has already seen (in this or a previous session) are fast-forwarded
+    #[var]
+    pub skip_mode: bool,
+    /// keys of every line seen so far, loaded from [SqoreDialog::SEEN_LINES_FILE] on first use
+    seen_lines: Option<HashSet<String>>,
+    seen_lines_dirty: bool,
+    /// the last bark of each speaker, with the time (in msec) the speaker can bark again. The time is `None` while the bark is showing, the cooldown starts once it is hidden
+    barks: HashMap<InstanceId, (Gd<DialogBark>, Option<u64>)>,
+    /// callables notified of blackboard changes to keys matching their pattern, see [SqoreDialog::watch]
+    watchers: Vec<(String, Callable)>,
+    /// callables of the custom commands, by name and argument count, see [SqoreDialog::register_command]
+    commands: HashMap<(String, usize), Callable>,
+    /// the seconds left of the timed choice resumed by [SqoreDialog::restore_snapshot], taken by the dialog GUI once it shows the choice
+    resumed_choice_time_left: Option<f64>,
 
     base: Base<Object>,
 }
@@ -36,6 +64,24 @@ impl IObject for SqoreDialog {}
 #[godot_api]
 impl SqoreDialog {
     pub const SINGLETON_NAME: &'static str = "SqoreDialog";
+    const BLACKBOARD_SAVE_KEY: &'static str = "dialog_blackboard";
+    const BLACKBOARD_ENTRIES_KEY: &'static str = "entries";
+    const SNAPSHOT_SAVE_KEY: &'static str = "dialog_snapshot";
+    const SNAPSHOT_TRACK_FILE: &'static str = "track_file";
+    const SNAPSHOT_TRACK_DICT: &'static str = "track_dict";
+    const SNAPSHOT_TRACK_ID: &'static str = "track_id";
+    const SNAPSHOT_LINE: &'static str = "line";
+    const SNAPSHOT_CURSOR: &'static str = "cursor";
+    const SNAPSHOT_CHOICE_PENDING: &'static str = "choice_pending";
+    const SNAPSHOT_CHOICE_TIME_LEFT: &'static str = "choice_time_left";
+    const SNAPSHOT_BLACKBOARD: &'static str = "blackboard";
+    /// project setting listing the names of commands the game registers itself, so linting in the editor knows them
+    const KNOWN_COMMANDS_SETTING: &'static str = "dialog_known_commands";
+    /// stand-in file paths of tracks loaded from text or a dictionary without an id
+    const INTERNAL_TEXT_TRACK: &'static str = "<internal text>";
+    const INTERNAL_DICT_TRACK: &'static str = "<internal dict>";
+    const SEEN_LINES_FILE: &'static str = "user://dialog_seen_lines.json";
+    const SEEN_LINES_KEY: &'static str = "seen_lines";
 
     #[func]
     pub fn init_event_bus(&mut self) {
@@ -57,38 +103,150 @@ impl SqoreDialog {
 
     #[func]
     pub fn load_track_file(&mut self, file_path: GString) {
-        let result = DialogTrack::load_from_json(file_path.clone());
+        let result = DialogTrack::load_from_file(file_path.clone());
         if let Err(err) = result.clone() {
             Self::handle_dialog_error(err);
         } else {
-            self.current_track = Some(result.unwrap());
-            self.load_track();
+            self.load_track(result.unwrap());
         }
     }
 
+    /// Plays a track from text. Seen lines are not remembered for it, see [SqoreDialog::load_track_text_with_id]
     #[func]
     pub fn load_track_text(&mut self, track_text: GString) {
-        let result = DialogTrack::load_from_text(track_text, "<internal text>".to_godot());
+        self.load_track_text_with_id(track_text, Self::INTERNAL_TEXT_TRACK.to_godot());
+    }
+
+    /// Plays a track from text, using `id` in place of a file path. Seen lines are remembered by `id`, so use a different `id` for each track
+    #[func]
+    pub fn load_track_text_with_id(&mut self, track_text: GString, id: GString) {
+        let result = DialogTrack::load_from_text(track_text, id);
         if let Err(err) = result.clone() {
             Self::handle_dialog_error(err);
         } else {
-            self.current_track = Some(result.unwrap());
-            self.load_track();
+            self.load_track(result.unwrap());
         }
     }
 
+    /// Plays a track from a dictionary. Seen lines are not remembered for it, see [SqoreDialog::load_track_dict_with_id]
     #[func]
     pub fn load_track_dict(&mut self, track_dict: Dictionary) {
-        let result = DialogTrack::load_from_dict(track_dict, "<internal dict>".to_godot());
+        self.load_track_dict_with_id(track_dict, Self::INTERNAL_DICT_TRACK.to_godot());
+    }
+
+    /// Plays a track from a dictionary, using `id` in place of a file path. Seen lines are remembered by `id`, so use a different `id` for each track
+    #[func]
+    pub fn load_track_dict_with_id(&mut self, track_dict: Dictionary, id: GString) {
+        let result = DialogTrack::load_from_dict(track_dict, id);
         if let Err(err) = result.clone() {
             Self::handle_dialog_error(err);
         } else {
-            self.current_track = Some(result.unwrap());
-            self.load_track();
+            self.load_track(result.unwrap());
+        }
+    }
+
+    /// Plays a track as a bark above `speaker`, without interrupting the main dialog. Returns false if the track failed to load or the speaker is still barking or on cooldown
+    #[func]
+    pub fn play_bark_file(&mut self, file_path: GString, speaker: Gd<Node3D>) -> bool {
+        match DialogTrack::load_from_file(file_path) {
+            Ok(track) => self.play_bark(track, speaker),
+            Err(err) => {
+                Self::handle_dialog_error(err);
+                false
+            }
+        }
+    }
+
+    /// Plays a track dictionary as a bark above `speaker`, see [SqoreDialog::play_bark_file]
+    #[func]
+    pub fn play_bark_dict(&mut self, track_dict: Dictionary, speaker: Gd<Node3D>) -> bool {
+        match DialogTrack::load_from_dict(track_dict, "<internal bark>".to_godot()) {
+            Ok(track) => self.play_bark(track, speaker),
+            Err(err) => {
+                Self::handle_dialog_error(err);
+                false
+            }
         }
     }
 
-    pub fn load_track(&mut self) {
+    /// Whether `speaker` is not barking and off cooldown, so it can bark
+    #[func]
+    pub fn is_bark_ready(&self, speaker: Gd<Node3D>) -> bool {
+        let now = Time::singleton().get_ticks_msec();
+        self.barks
+            .get(&speaker.instance_id())
+            .map_or(true, |(bark, ready_at)| match ready_at {
+                Some(ready_at) => now >= *ready_at,
+                // freed without being hidden first
+                None => !bark.is_instance_valid(),
+            })
+    }
+
+    /// Stops the bark currently playing above `speaker`, if any. The cooldown starts once it is gone
+    #[func]
+    pub fn stop_bark(&mut self, speaker: Gd<Node3D>) {
+        if let Some((bark, _)) = self.barks.get_mut(&speaker.instance_id()) {
+            if bark.is_instance_valid() {
+                bark.queue_free();
+            }
+        }
+    }
+
+    fn play_bark(&mut self, track: DialogTrack, mut speaker: Gd<Node3D>) -> bool {
+        if !self.is_bark_ready(speaker.clone()) {
+            return false;
+        }
+        // a speaker only says one thing at a time
+        self.stop_bark(speaker.clone());
+        let now = Time::singleton().get_ticks_msec();
+        self.barks.retain(|_, (bark, ready_at)| {
+            bark.is_instance_valid() || ready_at.is_some_and(|ready_at| ready_at > now)
+        });
+        let settings = self.get_settings();
+        let settings = settings.bind();
+        let bark = DialogBark::create(
+            track.lines,
+            settings.bark_height_offset,
+            settings.words_per_minute,
+            settings.bark_linger_duration,
+        );
+        speaker.add_child(bark.clone().upcast());
+        self.barks.insert(speaker.instance_id(), (bark, None));
+        true
+    }
+
+    /// Starts the cooldown of the speaker of `bark` once the bark is hidden
+    pub fn on_bark_hidden(&mut self, bark: &Gd<DialogBark>) {
+        let cooldown = (self.get_settings().bind().bark_cooldown.max(0.0) * 1000.0) as u64;
+        let now = Time::singleton().get_ticks_msec();
+        for (current, ready_at) in self.barks.values_mut() {
+            if current.instance_id() == bark.instance_id() && ready_at.is_none() {
+                *ready_at = Some(now + cooldown);
+            }
+        }
+    }
+
+    /// The dialog settings in use, either the override settings or the ones from the Sqore config
+    pub fn get_settings(&self) -> Gd<DialogSettings> {
+        self.get_override_settings().unwrap_or(
+            Sqore::singleton()
+                .bind()
+                .get_config()
+                .bind()
+                .get_dialog()
+                .unwrap_or(DialogSettings::new_gd()),
+        )
+    }
+
+    /// Plays `track` from its first line. Entries of the `local.` scope are reset
+    pub fn load_track(&mut self, track: DialogTrack) {
+        self.blackboard.clear_scope(Scope::Local);
+        self.emit_pending_events();
+        self.load_track_at(DialogRunner::new(track, 0));
+    }
+
+    /// Shows a new dialog GUI playing `runner`
+    fn load_track_at(&mut self, runner: DialogRunner) {
         if self.event_bus.is_none() {
             self.init_event_bus();
         }
@@ -101,12 +259,6 @@ impl SqoreDialog {
             return;
         };
 
-        // load track data
-        let Some(track) = &self.current_track else {
-            godot_warn!("Failed to load a dialog track");
-            return;
-        };
-
         // kill old GUI
         if let Some(g) = self.gui.as_mut() {
             if g.is_instance_valid() {
@@ -127,10 +279,412 @@ impl SqoreDialog {
         let mut gui = DialogGUI::new_alloc();
 
         SqoreUtil::add_child_deferred(&mut root.upcast(), &gui.clone().upcast());
-        gui.bind_mut().track = Some(VecDeque::from_iter(track.lines.clone()));
+        self.start_runner(runner);
         self.gui = Some(gui);
     }
 
+    /// Replaces the track being played, ending the previous one
+    fn start_runner(&mut self, runner: DialogRunner) {
+        self.end_runner();
+        let file = runner.track().file.clone();
+        self.runner = Some(runner);
+        self.emit_event(DialogEvents::SIGNAL_TRACK_STARTED, &[file.to_variant()]);
+    }
+
+    fn end_runner(&mut self) {
+        if let Some(runner) = self.runner.take() {
+            let file = runner.track().file.clone();
+            self.emit_event(DialogEvents::SIGNAL_TRACK_ENDED, &[file.to_variant()]);
+        }
+    }
+
+    /// Ends the track of `gui` if it is freed before the track is done
+    pub fn on_gui_exited(&mut self, gui: &Gd<DialogGUI>) {
+        if self
+            .gui
+            .as_ref()
+            .is_some_and(|current| current.instance_id() == gui.instance_id())
+        {
+            self.end_runner();
+        }
+    }
+
+    /// Emits a signal of the event bus. Deferred so listeners can safely call back into the dialog
+    pub fn emit_event(&mut self, signal: &str, args: &[Variant]) {
+        let mut call_args = vec![signal.to_variant()];
+        call_args.extend_from_slice(args);
+        self.call_bus_deferred("emit_signal", &call_args);
+    }
+
+    fn call_bus_deferred(&mut self, method: &str, args: &[Variant]) {
+        if self.event_bus.is_none() {
+            self.init_event_bus();
+        }
+        let Some(bus) = &mut self.event_bus else {
+            return;
+        };
+        if !bus.is_instance_valid() {
+            return;
+        }
+        bus.call_deferred(StringName::from(method), args);
+    }
+
+    /// Calls `callable` with the key, old value, and new value whenever a blackboard entry whose key matches `pattern` changes, whether from dialog actions or from GDScript. `*` in the pattern matches any characters and `?` matches one, e.g. `quest.*`. A missing value is null.
+    /// Calls are deferred, so `callable` can safely change the blackboard or start a new dialog
+    #[func]
+    pub fn watch(&mut self, pattern: GString, callable: Callable) {
+        if !callable.is_valid() {
+            godot_warn!("Cannot watch \"{}\" with an invalid callable", pattern);
+            return;
+        }
+        self.watchers.push((pattern.to_string(), callable));
+    }
+
+    /// Stops calling `callable` for changes matching `pattern`, see [SqoreDialog::watch]. Returns false if it wasn't watching `pattern`
+    #[func]
+    pub fn unwatch(&mut self, pattern: GString, callable: Callable) -> bool {
+        let count = self.watchers.len();
+        let pattern = pattern.to_string();
+        self.watchers
+            .retain(|(watched, watcher)| !(*watched == pattern && *watcher == callable));
+        count != self.watchers.len()
+    }
+
+    /// The index of the top level node of the line being played
+    pub fn current_line_index(&self) -> usize {
+        self.runner
+            .as_ref()
+            .and_then(|runner| runner.position().first().copied())
+            .unwrap_or(0)
+    }
+
+    /// Captures the state of the active dialog so it can be resumed later with [SqoreDialog::restore_snapshot]. Includes the track (as a file path, or the full track for internal tracks), the current line and its position inside nested branches, whether a choice is pending along with the time left on a timed choice, and the blackboard entries of the save and `local.` scopes. Returns an empty dictionary if no dialog is active
+    #[func]
+    pub fn snapshot(&self) -> Dictionary {
+        let mut dict = Dictionary::new();
+        let (Some(runner), Some(gui)) = (&self.runner, &self.gui) else {
+            return dict;
+        };
+        if !gui.is_instance_valid() {
+            return dict;
+        }
+        let track = runner.track();
+        let position = runner.position();
+        if track.is_from_file() {
+            dict.set(Self::SNAPSHOT_TRACK_FILE, track.file.clone());
+        } else if let Some(source) = &track.source {
+            dict.set(Self::SNAPSHOT_TRACK_DICT, source.clone());
+            dict.set(Self::SNAPSHOT_TRACK_ID, track.file.clone());
+        }
+        // the top level line is kept for snapshots taken before branches existed
+        dict.set(
+            Self::SNAPSHOT_LINE,
+            position.first().copied().unwrap_or(0) as i64,
+        );
+        dict.set(
+            Self::SNAPSHOT_CURSOR,
+            Array::from_iter(position.iter().map(|index| *index as i64)),
+        );
+        dict.set(
+            Self::SNAPSHOT_CHOICE_PENDING,
+            runner.pending_choice().is_some(),
+        );
+        if let Some(time_left) = gui.bind().choice_time_left() {
+            dict.set(Self::SNAPSHOT_CHOICE_TIME_LEFT, time_left);
+        }
+        dict.set(
+            Self::SNAPSHOT_BLACKBOARD,
+            self.blackboard
+                .to_scoped_dictionary(&[Scope::Save, Scope::Local]),
+        );
+        dict
+    }
+
+    /// Resumes a dialog captured with [SqoreDialog::snapshot], rebuilding the dialog GUI at the captured line. A pending choice is shown again with the countdown of a timed choice continuing where it was. Returns false if the snapshot is empty or its track fails to load
+    #[func]
+    pub fn restore_snapshot(&mut self, snapshot: Dictionary) -> bool {
+        let result = if let Some(file) = snapshot.get(Self::SNAPSHOT_TRACK_FILE) {
+            DialogTrack::load_from_file(file.to_string().to_godot())
+        } else if let Some(Ok(dict)) = snapshot
+            .get(Self::SNAPSHOT_TRACK_DICT)
+            .map(|var| Dictionary::try_from_variant(&var))
+        {
+            let id = snapshot
+                .get(Self::SNAPSHOT_TRACK_ID)
+                .map(|id| id.to_string())
+                .unwrap_or(Self::INTERNAL_DICT_TRACK.to_string());
+            DialogTrack::load_from_dict(dict, id.to_godot())
+        } else {
+            return false;
+        };
+        let track = match result {
+            Ok(track) => track,
+            Err(err) => {
+                Self::handle_dialog_error(err);
+                return false;
+            }
+        };
+        if let Some(Ok(blackboard)) = snapshot
+            .get(Self::SNAPSHOT_BLACKBOARD)
+            .map(|var| Dictionary::try_from_variant(&var))
+        {
+            self.blackboard
+                .load_scoped_dictionary(&blackboard, &[Scope::Save, Scope::Local]);
+            self.emit_pending_events();
+        }
+        // saved files turn every number into a float, so read the line through an entry
+        let line = snapshot
+            .get(Self::SNAPSHOT_LINE)
+            .and_then(|var| Entry::from_variant(&var).as_number())
+            .unwrap_or(0f32)
+            .max(0f32) as usize;
+        let position: Option<Vec<usize>> = snapshot
+            .get(Self::SNAPSHOT_CURSOR)
+            .and_then(|var| Array::<Variant>::try_from_variant(&var).ok())
+            .map(|array| {
+                array
+                    .iter_shared()
+                    .filter_map(|var| Entry::from_variant(&var).as_number())
+                    .map(|index| index.max(0f32) as usize)
+                    .collect()
+            });
+        let mut runner = position
+            .and_then(|position| DialogRunner::at(track.clone(), &position))
+            .unwrap_or_else(|| DialogRunner::new(track, line));
+        let choice_pending = snapshot
+            .get(Self::SNAPSHOT_CHOICE_PENDING)
+            .is_some_and(|var| Entry::from_variant(&var) == Entry::Bool(true));
+        self.resumed_choice_time_left = None;
+        if choice_pending {
+            // shown again as it was, the lines before it already ran when the snapshot was taken
+            if runner.resume_choice(&self.blackboard) {
+                self.resumed_choice_time_left = snapshot
+                    .get(Self::SNAPSHOT_CHOICE_TIME_LEFT)
+                    .and_then(|var| Entry::from_variant(&var).as_number())
+                    .map(|time_left| time_left as f64);
+            } else {
+                godot_warn!(
+                    "The choice pending in the dialog snapshot is no longer in {}, resuming from its line instead",
+                    runner.track().file
+                );
+            }
+        }
+        self.load_track_at(runner);
+        true
+    }
+
+    /// The seconds left of the timed choice resumed by [SqoreDialog::restore_snapshot], only returned once
+    pub fn take_resumed_choice_time_left(&mut self) -> Option<f64> {
+        self.resumed_choice_time_left.take()
+    }
+
+    /// Stores a [SqoreDialog::snapshot] into a child builder of `builder`
+    #[func]
+    pub fn snapshot_to(&self, mut builder: Gd<SaveDataBuilder>) {
+        let mut child = builder
+            .bind_mut()
+            .get_child_builder(Self::SNAPSHOT_SAVE_KEY.to_godot());
+        child.bind_mut().set_value(
+            Self::SNAPSHOT_SAVE_KEY.to_godot(),
+            self.snapshot().to_variant(),
+        );
+    }
+
+    /// Resumes a dialog stored with [SqoreDialog::snapshot_to]. Returns false if `builder` holds no dialog in progress
+    #[func]
+    pub fn restore_snapshot_from(&mut self, mut builder: Gd<SaveDataBuilder>) -> bool {
+        let mut child = builder
+            .bind_mut()
+            .get_child_builder(Self::SNAPSHOT_SAVE_KEY.to_godot());
+        let value = child
+            .bind_mut()
+            .get_value(Self::SNAPSHOT_SAVE_KEY.to_godot());
+        let Ok(snapshot) = Dictionary::try_from_variant(&value) else {
+            return false;
+        };
+        self.restore_snapshot(snapshot)
+    }
+
+    /// Checks a track file for mistakes without playing it. Returns an array of diagnostics, each a dictionary with the keys `file`, `line` (the index of the top level node, -1 for the whole track), `position` (the path to the node through nested branches and choice options, as in [SqoreDialog::snapshot]), `severity` ("error" or "warning") and `message`. Commands that are not registered yet are errors, or warnings in the editor where game code has not registered them. List such commands in the `addons/sqore/dialog_known_commands` project setting to accept them
+    #[func]
+    pub fn lint_track_file(&self, file_path: GString) -> Array<Dictionary> {
+        let result = DialogTrack::load_from_file(file_path.clone());
+        self.lint_result(result, file_path.to_string().as_str())
+    }
+
+    /// Same as [SqoreDialog::lint_track_file] but for a track dictionary
+    #[func]
+    pub fn lint_track_dict(&self, track_dict: Dictionary) -> Array<Dictionary> {
+        let result = DialogTrack::load_from_dict(track_dict, Self::INTERNAL_DICT_TRACK.to_godot());
+        self.lint_result(result, Self::INTERNAL_DICT_TRACK)
+    }
+
+    /// Lints every track file found (recursively) inside of `folder`
+    #[func]
+    pub fn lint_track_folder(&self, folder: GString) -> Array<Dictionary> {
+        let mut diagnostics = Array::new();
+        for file in DialogTrack::find_track_files(folder) {
+            diagnostics.extend_array(self.lint_track_file(file));
+        }
+        diagnostics
+    }
+
+    /// Collects the translatable text of every track inside of `folder` (recursively) and writes it to `output_path`. A `.pot` extension writes a gettext template, anything else writes a Godot translation CSV with a column for the project's fallback locale. Returns whether the file was written
+    #[func]
+    pub fn extract_translations(&self, folder: GString, output_path: GString) -> bool {
+        let mut catalog = TranslationCatalog::default();
+        let settings = self.get_settings();
+        let character_name = |reference: &str| match settings.bind().find_character(reference) {
+            Some((character, _)) => character.bind().get_name().to_string(),
+            None => reference.to_string(),
+        };
+        for file in DialogTrack::find_track_files(folder) {
+            match DialogTrack::load_from_file(file) {
+                Ok(track) => {
+                    for warning in catalog.add_track(&track, &character_name) {
+                        godot_warn!("{}", warning);
+                    }
+                }
+                Err(err) => Self::handle_dialog_error(err),
+            }
+        }
+        let text = if output_path.to_string().ends_with(".pot") {
+            catalog.to_pot()
+        } else {
+            let locale = ProjectSettings::singleton()
+                .get_setting("internationalization/locale/fallback".into())
+                .try_to::<GString>()
+                .map(|locale| locale.to_string())
+                .unwrap_or("en".to_string());
+            catalog.to_csv(&locale)
+        };
+        let Some(mut file) = FileAccess::open(output_path.clone(), ModeFlags::WRITE) else {
+            godot_warn!("Failed to access file {}", output_path);
+            return false;
+        };
+        file.store_string(text.to_godot());
+        godot_print!(
+            "Extracted {} translation entries to \"{}\"",
+            catalog.entries.len(),
+            output_path
+        );
+        true
+    }
+
+    fn lint_result(
+        &self,
+        result: Result<DialogTrack, DialogError>,
+        file: &str,
+    ) -> Array<Dictionary> {
+        let declared =
+            Sqore::get_or_init_default(Self::KNOWN_COMMANDS_SETTING, PackedStringArray::new())
+                .unwrap_or_default();
+        let commands = KnownCommands::new(
+            &self.blackboard,
+            declared
+                .as_slice()
+                .iter()
+                .map(|name| name.to_string())
+                .collect(),
+            Engine::singleton().is_editor_hint(),
+        );
+        let diagnostics = match result {
+            Ok(track) => lint_track(&track, &commands),
+            Err(err) => vec![DialogDiagnostic::from_load_error(&err)],
+        };
+        Array::from_iter(
+            diagnostics
+                .iter()
+                .map(|diagnostic| diagnostic.to_dict(file)),
+        )
+    }
+
+    /// Gets every line displayed and choice made so far, oldest first. Lines are dictionaries with the keys `type` ("line"), `character` and `text`, choices have the keys `type` ("choice"), `index` and `text`. Character names and text are stored after templating and translation
+    #[func]
+    pub fn get_history(&self) -> Array<Dictionary> {
+        Array::from_iter(self.history.iter().map(|entry| entry.to_dict()))
+    }
+
+    #[func]
+    pub fn clear_history(&mut self) {
+        self.history.clear();
+    }
+
+    pub fn record_history(&mut self, entry: DialogHistoryEntry) {
+        self.history.push(entry);
+    }
+
+    /// Marks a line of the current track as seen, returning whether it had already been seen before. Lines of internal tracks loaded without an id are never remembered, they can't be told apart from the lines of other internal tracks
+    pub fn mark_line_seen(&mut self, line: &Line) -> bool {
+        let Some(identity) = line.identity() else {
+            return false;
+        };
+        let Some(runner) = &self.runner else {
+            return false;
+        };
+        let file = runner.track().file.clone();
+        if file.is_empty() || file == Self::INTERNAL_TEXT_TRACK || file == Self::INTERNAL_DICT_TRACK
+        {
+            return false;
+        }
+        let key = format!("{}#{}", file, identity);
+        let newly_seen = self.get_seen_lines().insert(key);
+        self.seen_lines_dirty |= newly_seen;
+        !newly_seen
+    }
+
+    fn get_seen_lines(&mut self) -> &mut HashSet<String> {
+        self.seen_lines.get_or_insert_with(|| {
+            let mut seen = HashSet::new();
+            if !FileAccess::file_exists(Self::SEEN_LINES_FILE.into()) {
+                return seen;
+            }
+            let Some(mut builder) = SaveDataBuilder::try_load_file(Self::SEEN_LINES_FILE.into())
+            else {
+                return seen;
+            };
+            let keys = builder.bind_mut().get_value_or_default(
+                Self::SEEN_LINES_KEY.into(),
+                Array::<Variant>::new().to_variant(),
+            );
+            if let Ok(keys) = Array::<Variant>::try_from_variant(&keys) {
+                seen.extend(keys.iter_shared().map(|key| key.to_string()));
+            }
+            builder.free();
+            seen
+        })
+    }
+
+    /// Writes the seen lines to disk if any were added since the last save. Called automatically when a track ends and when the game window is closed
+    #[func]
+    pub fn save_seen_lines(&mut self) -> bool {
+        if !self.seen_lines_dirty {
+            return true;
+        }
+        let Some(seen) = &self.seen_lines else {
+            return true;
+        };
+        let mut keys: Vec<&String> = seen.iter().collect();
+        keys.sort();
+        let mut builder = SaveDataBuilder::new_alloc();
+        builder.bind_mut().set_value(
+            Self::SEEN_LINES_KEY.into(),
+            PackedStringArray::from_iter(keys.into_iter().map(|key| key.to_godot())).to_variant(),
+        );
+        let result = builder.bind_mut().save(Self::SEEN_LINES_FILE.into());
+        builder.free();
+        self.seen_lines_dirty = !result;
+        result
+    }
+
+    /// Forgets every seen line, including the ones saved to disk
+    #[func]
+    pub fn clear_seen_lines(&mut self) {
+        self.seen_lines = Some(HashSet::new());
+        self.seen_lines_dirty = true;
+        self.save_seen_lines();
+    }
+
     fn handle_dialog_error(err: DialogError) {
         godot_error!("DialogError: {:#?}", err);
     }
@@ -142,80 +696,217 @@ impl SqoreDialog {
         gui.bind_mut().make_dialog_choice(selection)
     }
 
-    pub fn handle_dialog_signal(&mut self, line: &Line) {
-        if self.event_bus.is_none() {
-            self.init_event_bus();
+    fn emit_track_signal(&mut self, name: &str, args: &[String]) {
+        let args = Array::from_iter(args.iter().map(|s| {
+            let mut json = Json::new_gd();
+            if json.parse(s.to_godot()) != engine::global::Error::OK {
+                // handle invalid types as a simple string value
+                return s.to_variant();
+            }
+            json.get_data()
+        }));
+        self.emit_event(
+            DialogEvents::SIGNAL_TRACK_SIGNAL,
+            &[name.to_variant(), args.to_variant()],
+        );
+    }
+
+    /// Runs the current track up to the next text line or choice to display, emitting track signals and switching tracks along the way. Custom commands used by actions are returned as they are reached, run them with [SqoreDialog::run_commands] before asking for the next event
+    pub fn next_dialog_event(&mut self) -> DialogEvent {
+        loop {
+            let Some(runner) = &mut self.runner else {
+                return DialogEvent::Ended;
+            };
+            let event = runner.next(&mut self.blackboard);
+            self.emit_pending_events();
+            match event {
+                DialogEvent::Signal { name, args } => self.emit_track_signal(&name, &args),
+                DialogEvent::TrackRequested { file, label } => {
+                    if !self.load_requested_track(&file, label.as_deref()) {
+                        self.end_runner();
+                        return DialogEvent::Ended;
+                    }
+                }
+                DialogEvent::Ended => {
+                    self.end_runner();
+                    return event;
+                }
+                _ => return event,
+            }
         }
-        if let Line::Signal { name, args } = line {
-            let Some(bus) = &mut self.event_bus else {
-                return;
+    }
+
+    /// The callable registered for a custom command, with the arguments to call it with
+    pub fn command_callable(&self, call: &CommandCall) -> Option<(Callable, VariantArray)> {
+        let callable = self.commands.get(&(call.name.clone(), call.args.len()))?;
+        let args = VariantArray::from_iter(
+            call.args
+                .iter()
+                .map(|arg| self.blackboard.get_command_arg(arg).to_variant()),
+        );
+        Some((callable.clone(), args))
+    }
+
+    /// Runs custom commands reached by [SqoreDialog::next_dialog_event] and stores their results. Takes no `self`, so SqoreDialog is not borrowed while the commands run and they can use it freely
+    pub fn run_commands(calls: Vec<CommandCall>) {
+        let mut dialog = Self::singleton();
+        for call in calls {
+            let command = dialog.bind().command_callable(&call);
+            let Some((callable, args)) = command else {
+                continue;
             };
-            bus.emit_signal(
-                StringName::from(DialogEvents::SIGNAL_TRACK_SIGNAL),
-                &[
-                    name.to_variant(),
-                    Array::from_iter(args.iter().map(|s| {
-                        let mut json = Json::new_gd();
-                        if json.parse(s.to_godot()) != engine::global::Error::OK {
-                            // handle invalid types as a simple string value
-                            return s.to_variant();
-                        }
-                        json.get_data()
-                    }))
-                    .to_variant(),
-                ],
-            );
+            let result = callable.callv(args);
+            let mut zelf = dialog.bind_mut();
+            zelf.blackboard
+                .store_command_result(&call, Entry::from_variant(&result));
+            zelf.emit_pending_events();
+        }
+    }
+
+    /// Chooses an option of the pending choice by its position among the available options
+    pub fn choose_option(&mut self, position: usize) -> Option<AvailableOption> {
+        let runner = self.runner.as_mut()?;
+        let result = runner.choose(&mut self.blackboard, position);
+        self.emit_pending_events();
+        match result {
+            Ok(chosen) => Some(chosen),
+            Err(err) => {
+                godot_warn!("Cannot choose dialog option {}: {:?}", position, err);
+                None
+            }
+        }
+    }
+
+    /// Logs the runner's and blackboard's warnings and emits the actions run and blackboard entries changed since the last call
+    fn emit_pending_events(&mut self) {
+        let (mut warnings, actions) = match &mut self.runner {
+            Some(runner) => (runner.take_warnings(), runner.take_executed_actions()),
+            None => (Vec::new(), Vec::new()),
+        };
+        warnings.extend(self.blackboard.take_warnings());
+        for warning in warnings {
+            godot_warn!("{}", warning);
+        }
+        for code in actions {
+            self.emit_event(DialogEvents::SIGNAL_ACTION_EXECUTED, &[code.to_variant()]);
+        }
+        // watchers whose object was freed
+        self.watchers.retain(|(_, watcher)| watcher.is_valid());
+        for change in self.blackboard.take_changes() {
+            let args = [
+                change.key.to_variant(),
+                change.old.to_variant(),
+                change.new.to_variant(),
+            ];
+            self.emit_event(DialogEvents::SIGNAL_BLACKBOARD_CHANGED, &args);
+            let watchers: Vec<Callable> = self
+                .watchers
+                .iter()
+                .filter(|(pattern, _)| Blackboard::key_matches(pattern, &change.key))
+                .map(|(_, watcher)| watcher.clone())
+                .collect();
+            for watcher in watchers {
+                let mut call_args = vec![watcher.to_variant()];
+                call_args.extend_from_slice(&args);
+                self.call_bus_deferred(DialogEvents::METHOD_NOTIFY_WATCHER, &call_args);
+            }
+        }
+    }
+
+    /// Continues the dialog in another track, after a `jump` to it
+    fn load_requested_track(&mut self, file: &str, label: Option<&str>) -> bool {
+        let runner = DialogTrack::load_from_file(file.to_godot())
+            .and_then(|track| DialogRunner::from_label(track, label));
+        match runner {
+            Ok(runner) => {
+                // like load_track, the `local.` scope belongs to the track being left
+                self.blackboard.clear_scope(Scope::Local);
+                self.emit_pending_events();
+                self.start_runner(runner);
+                true
+            }
+            Err(err) => {
+                Self::handle_dialog_error(err);
+                false
+            }
         }
     }
 
     pub fn handle_line_query(&mut self, query: String) -> bool {
-        self.blackboard.parse_query(query)
+        self.blackboard_query(query.to_godot())
     }
 
+    /// Runs action code the same way as action lines do. A `jump` or `end` applies to the track being played
     #[func]
     pub fn blackboard_action(&mut self, action: GString) {
-        self.blackboard.parse_action(action.to_string());
-        let Some((event_name, event_arg)) = self.blackboard.get_event() else {
-            if let Some(gui) = &mut self.gui {
-                gui.bind_mut().mark_event_handled();
-            }
-            return;
-        };
-        godot_print!("Processing event: {}({:#?})", event_name, event_arg);
-        match event_name.as_str() {
-            // TODO handle events with pub const value
-            "end" => {
-                let Some(gui) = &mut self.gui else {
-                    return;
-                };
-                gui.bind_mut().update_track(VecDeque::new());
-            }
-            "jump" => {
-                let Entry::Number(index) = event_arg else {
-                    return;
-                };
-                let index = (index.floor()) as usize;
-                let Some(gui) = &mut self.gui else {
-                    return;
-                };
-                let n_track = self.current_track.clone().unwrap();
-                let mut lines = VecDeque::from_iter(n_track.lines.iter().cloned());
-                for _ in 0..index {
-                    let _ = lines.pop_front();
+        match &mut self.runner {
+            Some(runner) => runner.run_action(&mut self.blackboard, action.to_string().as_str()),
+            None => {
+                self.blackboard.parse_action(action.to_string());
+                if let Some((event_name, _)) = self.blackboard.get_event() {
+                    godot_warn!("Cannot {} without an active dialog track", event_name);
+                    self.blackboard.mark_event_handled();
+                }
+                if !action.to_string().trim().is_empty() {
+                    self.emit_event(DialogEvents::SIGNAL_ACTION_EXECUTED, &[action.to_variant()]);
                 }
-                gui.bind_mut().update_track(lines);
             }
-            _ => godot_error!("Unhandled internal event! event: \"{}\"", event_name),
         }
-        self.blackboard.mark_event_handled();
-        if let Some(gui) = &mut self.gui {
-            gui.bind_mut().mark_event_handled();
+        // the caller holds SqoreDialog, so custom commands run once it is done
+        for call in self.blackboard.take_command_calls() {
+            let args = [
+                call.name.to_variant(),
+                call.args
+                    .iter()
+                    .map(|arg| arg.to_godot())
+                    .collect::<PackedStringArray>()
+                    .to_variant(),
+                call.store.unwrap_or_default().to_variant(),
+            ];
+            self.call_bus_deferred(DialogEvents::METHOD_RUN_COMMAND, &args);
+        }
+        self.emit_pending_events();
+    }
+
+    /// Registers a custom command usable in dialog actions, e.g. `give_item sword`. Arguments are parsed into bools, numbers, or strings (bare words are passed as strings) before `callable` is called with them.
+    /// If the command is used as `give_item sword -> has_sword`, the value returned by `callable` is stored in the blackboard under `has_sword`.
+    /// `callable` runs once the action is done and before the next line is played, so the lines after the action see the stored value. SqoreDialog is not in use while it runs, so `callable` can use it freely. Commands used through [SqoreDialog::blackboard_action] run deferred.
+    /// Returns false if `name` is a built-in command
+    #[func]
+    pub fn register_command(&mut self, name: GString, arg_count: i32, callable: Callable) -> bool {
+        if arg_count < 0 {
+            godot_warn!("Command \"{}\" cannot have a negative argument count", name);
+            return false;
+        }
+        let name = name.to_string();
+        if !self
+            .blackboard
+            .register_queued_command(name.as_str(), arg_count as usize)
+        {
+            godot_warn!("Cannot replace built-in dialog command \"{}\"", name);
+            return false;
         }
+        self.commands.insert((name, arg_count as usize), callable);
+        true
+    }
+
+    /// Removes a custom command registered with [SqoreDialog::register_command]
+    #[func]
+    pub fn unregister_command(&mut self, name: GString) -> bool {
+        let name = name.to_string();
+        self.commands.retain(|(command, _), _| *command != name);
+        self.blackboard.unregister_command(name.as_str())
     }
 
     #[func]
     pub fn blackboard_query(&mut self, query: GString) -> bool {
-        self.blackboard.parse_query(query.to_string())
+        match self.blackboard.parse_query(query.to_string().as_str()) {
+            Ok(value) => value,
+            Err(err) => {
+                godot_error!("Malformed query \"{}\": {}", query, err);
+                false
+            }
+        }
     }
 
     #[func]
@@ -224,6 +915,99 @@ impl SqoreDialog {
         // self.blackboard.debug_print();
     }
 
+    /// Stores the blackboard entries of the save scope (keys without a `local.` or `session.` prefix) into a child builder of `builder`. Use this to include dialog state in your own save files
+    #[func]
+    pub fn blackboard_save_to(&self, mut builder: Gd<SaveDataBuilder>) {
+        let mut child = builder
+            .bind_mut()
+            .get_child_builder(Self::BLACKBOARD_SAVE_KEY.to_godot());
+        child.bind_mut().set_value(
+            Self::BLACKBOARD_ENTRIES_KEY.to_godot(),
+            self.blackboard.to_dictionary().to_variant(),
+        );
+    }
+
+    /// Restores blackboard entries previously stored with [SqoreDialog::blackboard_save_to], replacing the save scope. Returns false if `builder` holds no blackboard data
+    #[func]
+    pub fn blackboard_load_from(&mut self, mut builder: Gd<SaveDataBuilder>) -> bool {
+        let mut child = builder
+            .bind_mut()
+            .get_child_builder(Self::BLACKBOARD_SAVE_KEY.to_godot());
+        let entries = child
+            .bind_mut()
+            .get_value(Self::BLACKBOARD_ENTRIES_KEY.to_godot());
+        let Ok(dict) = Dictionary::try_from_variant(&entries) else {
+            return false;
+        };
+        self.blackboard.load_dictionary(&dict);
+        self.emit_pending_events();
+        true
+    }
+
+    /// Saves the blackboard as a standalone file
+    #[func]
+    pub fn blackboard_save_file(&self, file_path: GString) -> bool {
+        let mut builder = SaveDataBuilder::new_alloc();
+        self.blackboard_save_to(builder.clone());
+        let result = builder.bind_mut().save(file_path);
+        builder.free();
+        result
+    }
+
+    /// Loads the blackboard from a file written by [SqoreDialog::blackboard_save_file]
+    #[func]
+    pub fn blackboard_load_file(&mut self, file_path: GString) -> bool {
+        let Some(builder) = SaveDataBuilder::try_load_file(file_path) else {
+            return false;
+        };
+        let result = self.blackboard_load_from(builder.clone());
+        builder.free();
+        result
+    }
+
+    /// Lists the keys of all blackboard entries
+    #[func]
+    pub fn blackboard_list(&self) -> PackedStringArray {
+        PackedStringArray::from_iter(self.blackboard.keys().iter().map(|key| key.to_godot()))
+    }
+
+    #[func]
+    pub fn blackboard_has(&self, key: GString) -> bool {
+        self.blackboard.has_entry(key.to_string().as_str())
+    }
+
+    /// Gets the value of a blackboard entry, or null if it doesn't exist
+    #[func]
+    pub fn blackboard_get(&self, key: GString) -> Variant {
+        let Some(entry) = self.blackboard.get(key.to_string().as_str()) else {
+            return Variant::nil();
+        };
+        entry.to_variant()
+    }
+
+    /// Sets a blackboard entry. Only bool, int, float, and string values are supported, along with arrays and dictionaries of them. Returns false if the value can't be stored or the key is read-only
+    #[func]
+    pub fn blackboard_set(&mut self, key: GString, value: Variant) -> bool {
+        let entry = Entry::from_variant(&value);
+        if entry == Entry::None {
+            godot_warn!(
+                "Cannot store value of type {:?} in the blackboard (key \"{}\")",
+                value.get_type(),
+                key
+            );
+            return false;
+        }
+        let stored = self.blackboard.set_entry(key.to_string().as_str(), entry);
+        self.emit_pending_events();
+        stored
+    }
+
+    #[func]
+    pub fn blackboard_unset(&mut self, key: GString) {
+        self.blackboard.unset(key.to_string().as_str());
+        self.emit_pending_events();
+    }
+
     pub fn blackboard_parse(&self, text: String) -> String {
         self.blackboard.format_text(text)
     }
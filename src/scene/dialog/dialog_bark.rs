@@ -0,0 +1,108 @@
+use godot::{
+    engine::{base_material_3d::BillboardMode, label_3d::DrawFlags, ILabel3D, Label3D},
+    prelude::*,
+};
+
+use super::{
+    core_dialog::SqoreDialog, dialog_cursor::DialogCursor, dialog_track::Line,
+    dialog_typewriter::TypewriterScript,
+};
+
+/// A short, non-blocking line of dialog floating above a speaker. Created through [SqoreDialog::play_bark_file] and [SqoreDialog::play_bark_dict], it never captures input and frees itself once its track is done.
+///
+/// Only text lines are displayed (respecting their requirements and templating) and branches are followed, every other kind of line is skipped so barks cannot affect the main conversation.
+#[derive(GodotClass)]
+#[class(init, base=Label3D)]
+pub struct DialogBark {
+    lines: DialogCursor,
+    /// seconds left before the next line is shown
+    time_left: f64,
+    words_per_minute: f32,
+    linger_duration: f32,
+    base: Base<Label3D>,
+}
+
+#[godot_api]
+impl ILabel3D for DialogBark {
+    fn ready(&mut self) {
+        self.base_mut().set_billboard_mode(BillboardMode::ENABLED);
+        self.base_mut()
+            .set_draw_flag(DrawFlags::DISABLE_DEPTH_TEST, true);
+        self.base_mut().set_text(GString::new());
+    }
+
+    fn process(&mut self, delta: f64) {
+        // lines are advanced here rather than in `ready` as barks are created while SqoreDialog is bound
+        self.time_left -= delta;
+        if self.time_left > 0.0 {
+            return;
+        }
+        match self.next_text() {
+            Some(text) => {
+                let script = TypewriterScript::parse(&text, self.words_per_minute);
+                self.time_left = script.duration() + self.linger_duration as f64;
+                self.base_mut().set_text(script.text.to_godot());
+            }
+            None => self.base_mut().queue_free(),
+        }
+    }
+
+    fn exit_tree(&mut self) {
+        // whether the track is done, the bark was stopped or the speaker was freed
+        let bark = self.to_gd();
+        SqoreDialog::singleton().bind_mut().on_bark_hidden(&bark);
+    }
+}
+
+impl DialogBark {
+    pub fn create(
+        lines: Vec<Line>,
+        height_offset: f32,
+        words_per_minute: f32,
+        linger_duration: f32,
+    ) -> Gd<Self> {
+        let mut bark = Self::new_alloc();
+        {
+            let mut zelf = bark.bind_mut();
+            zelf.lines = DialogCursor::new(lines, 0);
+            zelf.words_per_minute = words_per_minute;
+            zelf.linger_duration = linger_duration;
+        }
+        bark.set_position(Vector3::UP * height_offset);
+        bark
+    }
+
+    fn next_text(&mut self) -> Option<String> {
+        while let Some(line) = self.lines.next_line() {
+            let mut dialog = SqoreDialog::singleton();
+            if let Line::Branch { branches } = &line {
+                let taken = branches.iter().position(|branch| {
+                    branch.is_else()
+                        || dialog
+                            .bind_mut()
+                            .blackboard_query(branch.requires.to_godot())
+                });
+                if let Some(taken) = taken {
+                    self.lines.enter(taken);
+                }
+                continue;
+            }
+            let Line::Text {
+                text, requires, id, ..
+            } = line
+            else {
+                continue;
+            };
+            if !requires.is_empty() && !dialog.bind_mut().blackboard_query(requires.to_godot()) {
+                continue;
+            }
+            // translated by id when a translation exists, the same as the main dialog
+            let mut translated: String = self.base().tr(id.to_godot()).into();
+            if id.is_empty() || translated == id {
+                translated = self.base().tr(text.to_godot()).into();
+            }
+            return Some(dialog.bind().blackboard_parse(translated));
+        }
+        None
+    }
+}
@@ -1,21 +1,33 @@
 use core::fmt;
 use std::{
-    collections::{hash_map::DefaultHasher, HashMap, VecDeque},
+    collections::{BTreeMap, HashMap, VecDeque},
     fmt::Display,
-    hash::{Hash, Hasher},
     rc::Rc,
 };
 
 use godot::{
-    engine::{global::Error, Json},
+    engine::{Json, ProjectSettings, TranslationServer},
     prelude::*,
 };
 
+use crate::scene::game_globals::Sqore;
+
+use super::{
+    dialog_expression::{Expression, QueryError},
+    dialog_literal::parse_literal,
+    dialog_template::Template,
+};
+
 #[derive(Debug, PartialEq, Clone)]
 pub enum Entry {
     Number(f32),
+    /// whole numbers, kept exact
+    Int(i64),
     String(String),
     Bool(bool),
+    List(Vec<Entry>),
+    /// sorted by key so it always displays the same way
+    Map(BTreeMap<String, Entry>),
     None,
 }
 impl Default for Entry {
@@ -23,238 +35,884 @@ impl Default for Entry {
         Self::None
     }
 }
+impl Entry {
+    /// Whether the entry counts as "true" when used as a condition on its own
+    pub fn is_truthy(&self) -> bool {
+        match self {
+            Entry::Number(val) => *val != 0f32,
+            Entry::Int(val) => *val != 0,
+            Entry::String(val) => !val.is_empty(),
+            Entry::Bool(val) => *val,
+            Entry::List(items) => !items.is_empty(),
+            Entry::Map(entries) => !entries.is_empty(),
+            Entry::None => false,
+        }
+    }
+
+    /// Gets the numeric value of the entry, booleans count as 1 or 0
+    pub fn as_number(&self) -> Option<f32> {
+        match self {
+            Entry::Number(val) => Some(*val),
+            Entry::Int(val) => Some(*val as f32),
+            Entry::Bool(val) => Some(if *val { 1f32 } else { 0f32 }),
+            _ => None,
+        }
+    }
+
+    /// Gets the value of the entry as a whole number, booleans count as 1 or 0
+    pub fn as_int(&self) -> Option<i64> {
+        match self {
+            Entry::Int(val) => Some(*val),
+            Entry::Bool(val) => Some(*val as i64),
+            _ => None,
+        }
+    }
+
+    /// The number of items in a list or map, or characters in a string
+    pub fn len(&self) -> Option<usize> {
+        match self {
+            Entry::String(val) => Some(val.chars().count()),
+            Entry::List(items) => Some(items.len()),
+            Entry::Map(entries) => Some(entries.len()),
+            _ => None,
+        }
+    }
+
+    /// Whether a list holds `item`, a map has the key `item`, or a string contains the text of `item`
+    pub fn contains(&self, item: &Entry) -> Option<bool> {
+        match (self, item) {
+            (Entry::List(items), item) => Some(items.iter().any(|other| other.loosely_eq(item))),
+            (Entry::Map(entries), Entry::String(key)) => Some(entries.contains_key(key)),
+            (Entry::String(val), Entry::String(part)) => Some(val.contains(part.as_str())),
+            _ => None,
+        }
+    }
+
+    /// Equality that compares numbers by value, whatever their type
+    pub fn loosely_eq(&self, other: &Entry) -> bool {
+        match (self, other) {
+            (Entry::Int(a), Entry::Int(b)) => a == b,
+            (
+                Entry::Number(_) | Entry::Int(_) | Entry::Bool(_),
+                Entry::Number(_) | Entry::Int(_),
+            )
+            | (Entry::Number(_) | Entry::Int(_), Entry::Bool(_)) => {
+                self.as_number() == other.as_number()
+            }
+            _ => self == other,
+        }
+    }
+
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Entry::Number(_) => "number",
+            Entry::Int(_) => "int",
+            Entry::String(_) => "string",
+            Entry::Bool(_) => "bool",
+            Entry::List(_) => "list",
+            Entry::Map(_) => "map",
+            Entry::None => "nil",
+        }
+    }
+
+    pub fn to_variant(&self) -> Variant {
+        match self {
+            Entry::Number(val) => val.to_variant(),
+            Entry::Int(val) => val.to_variant(),
+            Entry::String(val) => val.to_variant(),
+            Entry::Bool(val) => val.to_variant(),
+            Entry::List(items) => {
+                Array::<Variant>::from_iter(items.iter().map(|item| item.to_variant())).to_variant()
+            }
+            Entry::Map(entries) => {
+                let mut dict = Dictionary::new();
+                for (key, value) in entries {
+                    dict.set(key.clone(), value.to_variant());
+                }
+                dict.to_variant()
+            }
+            Entry::None => Variant::nil(),
+        }
+    }
+
+    /// Converts a value inside a list or map read by the engine's JSON parser, see [Entry::from_json_number]
+    fn from_json_item(var: &Variant) -> Self {
+        match var.get_type() {
+            VariantType::Float => Self::from_json_number(f64::from_variant(var)),
+            VariantType::Array => Array::<Variant>::try_from_variant(var)
+                .ok()
+                .and_then(|array| {
+                    array
+                        .iter_shared()
+                        .map(|item| Some(Self::from_json_item(&item)).filter(|e| *e != Entry::None))
+                        .collect()
+                })
+                .map_or(Entry::None, Entry::List),
+            VariantType::Dictionary => Dictionary::from_variant(var)
+                .iter_shared()
+                .map(|(key, value)| {
+                    Some((key.to_string(), Self::from_json_item(&value)))
+                        .filter(|(_, entry)| *entry != Entry::None)
+                })
+                .collect::<Option<BTreeMap<_, _>>>()
+                .map_or(Entry::None, Entry::Map),
+            _ => Self::from_variant(var),
+        }
+    }
+
+    /// JSON numbers are floats in the engine. Whole numbers that fit are ints, as [parse_literal] reads `2` in `["rope", 2]`
+    fn from_json_number(value: f64) -> Self {
+        // i64::MAX rounds up to 2^63 as a float, which no longer fits
+        if value.fract() == 0.0 && value >= i64::MIN as f64 && value < i64::MAX as f64 {
+            Entry::Int(value as i64)
+        } else {
+            Entry::Number(value as f32)
+        }
+    }
+
+    /// Converts a variant into an entry. Unsupported types become [Entry::None], as do arrays and dictionaries holding them
+    pub fn from_variant(var: &Variant) -> Self {
+        match var.get_type() {
+            VariantType::Bool => Entry::Bool(var.booleanize()),
+            VariantType::Int => Entry::Int(i64::from_variant(var)),
+            VariantType::Float => Entry::Number(f64::from_variant(var) as f32),
+            VariantType::String | VariantType::StringName => Entry::String(var.to_string()),
+            VariantType::PackedStringArray => Entry::List(
+                PackedStringArray::from_variant(var)
+                    .as_slice()
+                    .iter()
+                    .map(|item| Entry::String(item.to_string()))
+                    .collect(),
+            ),
+            VariantType::Array => Array::<Variant>::try_from_variant(var)
+                .ok()
+                .and_then(|array| {
+                    array
+                        .iter_shared()
+                        .map(|item| Some(Self::from_variant(&item)).filter(|e| *e != Entry::None))
+                        .collect()
+                })
+                .map_or(Entry::None, Entry::List),
+            VariantType::Dictionary => Dictionary::from_variant(var)
+                .iter_shared()
+                .map(|(key, value)| {
+                    Some((key.to_string(), Self::from_variant(&value)))
+                        .filter(|(_, entry)| *entry != Entry::None)
+                })
+                .collect::<Option<BTreeMap<_, _>>>()
+                .map_or(Entry::None, Entry::Map),
+            _ => Entry::None,
+        }
+    }
+
+    /// Stores the entry with its type so it survives a round trip through JSON
+    fn to_typed_dict(&self) -> Dictionary {
+        let mut dict = Dictionary::new();
+        dict.set("type", self.type_name());
+        match self {
+            // JSON numbers are floats, which can't hold every int
+            Entry::Int(val) => dict.set("value", val.to_string()),
+            Entry::List(items) => dict.set(
+                "value",
+                Array::from_iter(items.iter().map(|item| item.to_typed_dict())),
+            ),
+            Entry::Map(entries) => {
+                let mut values = Dictionary::new();
+                for (key, value) in entries {
+                    values.set(key.clone(), value.to_typed_dict());
+                }
+                dict.set("value", values);
+            }
+            _ => dict.set("value", self.to_variant()),
+        }
+        dict
+    }
+
+    fn from_typed_dict(dict: &Dictionary) -> Self {
+        let value = dict.get_or_nil("value");
+        let typed = |var: &Variant| {
+            Dictionary::try_from_variant(var)
+                .ok()
+                .map(|dict| Self::from_typed_dict(&dict))
+                .filter(|entry| *entry != Entry::None)
+        };
+        match dict.get_or_nil("type").to_string().as_str() {
+            "number" => match value.get_type() {
+                VariantType::Int => Entry::Number(i64::from_variant(&value) as f32),
+                VariantType::Float => Entry::Number(f64::from_variant(&value) as f32),
+                _ => Entry::None,
+            },
+            "int" => match value.get_type() {
+                VariantType::String => value.to_string().parse().map_or(Entry::None, Entry::Int),
+                VariantType::Int => Entry::Int(i64::from_variant(&value)),
+                VariantType::Float => Entry::Int(f64::from_variant(&value) as i64),
+                _ => Entry::None,
+            },
+            "string" => Entry::String(value.to_string()),
+            "bool" => match value.get_type() {
+                VariantType::Bool => Entry::Bool(value.booleanize()),
+                _ => Entry::None,
+            },
+            "list" => Array::<Variant>::try_from_variant(&value)
+                .ok()
+                .and_then(|items| items.iter_shared().map(|item| typed(&item)).collect())
+                .map_or(Entry::None, Entry::List),
+            "map" => Dictionary::try_from_variant(&value)
+                .ok()
+                .and_then(|entries| {
+                    entries
+                        .iter_shared()
+                        .map(|(key, value)| Some((key.to_string(), typed(&value)?)))
+                        .collect()
+                })
+                .map_or(Entry::None, Entry::Map),
+            _ => Entry::None,
+        }
+    }
+}
+
 impl Display for Entry {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            Entry::Number(val) => f.write_fmt(format_args!("{:.2}", val)),
+            // at most two decimals, without trailing zeros ("3" rather than "3.00")
+            Entry::Number(val) => {
+                let text = format!("{:.2}", val);
+                f.write_str(text.trim_end_matches('0').trim_end_matches('.'))
+            }
+            Entry::Int(val) => f.write_fmt(format_args!("{}", val)),
             Entry::String(val) => f.write_fmt(format_args!("{}", val)),
             Entry::Bool(val) => f.write_fmt(format_args!("{}", val)),
+            // lists display as their items joined for use in dialog text, e.g. "docks, lighthouse"
+            Entry::List(items) => {
+                let items: Vec<String> = items.iter().map(|item| item.to_string()).collect();
+                f.write_str(items.join(", ").as_str())
+            }
+            Entry::Map(entries) => {
+                let entries: Vec<String> = entries
+                    .iter()
+                    .map(|(key, value)| format!("{}: {}", key, value))
+                    .collect();
+                f.write_str(entries.join(", ").as_str())
+            }
             Entry::None => f.write_str("nil"),
         }
     }
 }
 
+#[derive(Clone)]
 pub struct Blackboard {
     entries: HashMap<String, Entry>,
     commands: Vec<Command>,
+    /// changes to non-internal entries since the last [Blackboard::take_changes]
+    changes: Vec<EntryChange>,
+    /// uses of queued commands since the last [Blackboard::take_command_calls]
+    command_calls: Vec<CommandCall>,
+    /// problems running actions since the last [Blackboard::take_warnings]
+    warnings: Vec<String>,
+    host: BlackboardHost,
+}
+
+/// The engine services a [Blackboard] relies on, replaced to use a blackboard without the engine running
+#[derive(Clone, Copy)]
+pub struct BlackboardHost {
+    /// parses a literal value of action code, such as `3`, `"text"` or `[1, 2]`
+    pub parse_literal: fn(&str) -> Option<Entry>,
+    /// resolves a read-only `settings.` or `project.` key, see [Blackboard::is_read_only_key]
+    pub read_only_entry: fn(&str) -> Option<Entry>,
+}
+
+impl BlackboardHost {
+    /// Parses literals with Godot's JSON parser and reads the Sqore config and project settings
+    pub const ENGINE: Self = Self {
+        parse_literal: Blackboard::parse_json_literal,
+        read_only_entry: Blackboard::read_only_entry,
+    };
+    /// Parses literals with [parse_literal], without read-only entries
+    pub const HEADLESS: Self = Self {
+        parse_literal,
+        read_only_entry: |_| None,
+    };
 }
 
 impl Default for Blackboard {
     fn default() -> Self {
+        Self::with_host(BlackboardHost::ENGINE)
+    }
+}
+
+impl Blackboard {
+    /// A blackboard that never calls into the engine, for running tracks in plain Rust tests
+    pub fn headless() -> Self {
+        Self::with_host(BlackboardHost::HEADLESS)
+    }
+
+    pub fn with_host(host: BlackboardHost) -> Self {
         let mut zelf = Self {
             entries: HashMap::new(),
             commands: Vec::new(),
+            changes: Vec::new(),
+            command_calls: Vec::new(),
+            warnings: Vec::new(),
+            host,
         };
-        zelf.commands.push(Command {
-            name: "set".into(),
-            args: 2,
-            callback: Rc::new(|bb, args| bb.set(args[0].as_str(), args[1].as_str())),
+        zelf.push_builtin("set", 2, |bb, args| {
+            bb.set(args[0].as_str(), args[1].as_str())
         });
-        zelf.commands.push(Command {
-            name: "add".into(),
-            args: 2,
-            callback: Rc::new(|bb, args| bb.add(args[0].as_str(), args[1].as_str())),
+        zelf.push_builtin("add", 2, |bb, args| {
+            bb.add(args[0].as_str(), args[1].as_str())
         });
-        zelf.commands.push(Command {
-            name: "sub".into(),
-            args: 2,
-            callback: Rc::new(|bb, args| bb.sub(args[0].as_str(), args[1].as_str())),
+        zelf.push_builtin("sub", 2, |bb, args| {
+            bb.sub(args[0].as_str(), args[1].as_str())
         });
-        zelf.commands.push(Command {
-            name: "jump".into(),
-            args: 1,
-            callback: Rc::new(|bb, args| bb.jump(args[0].as_str())),
+        zelf.push_builtin("jump", 1, |bb, args| bb.jump(args[0].as_str()));
+        zelf.push_builtin("end", 0, |bb, _| bb.end());
+        zelf.push_builtin("push", 2, |bb, args| {
+            bb.push(args[0].as_str(), args[1].as_str())
         });
-        zelf.commands.push(Command {
-            name: "end".into(),
-            args: 0,
-            callback: Rc::new(|bb, _| bb.end()),
+        zelf.push_builtin("remove", 2, |bb, args| {
+            bb.remove(args[0].as_str(), args[1].as_str())
         });
+        zelf.push_builtin("clear", 1, |bb, args| bb.clear_entry(args[0].as_str()));
+        zelf.push_builtin("toggle", 1, |bb, args| bb.toggle(args[0].as_str()));
         zelf
     }
-}
 
-impl Blackboard {
     /// Parses the action string
     pub fn parse_action(&mut self, code: String) {
         if code.is_empty() {
             return;
         }
-        for action in code.split(';') {
-            let mut parts = VecDeque::from_iter(action.trim().split(' ').map(|dirty| dirty.trim()));
-            let command = parts.pop_front().unwrap_or("");
-            let mut callback: Option<_> = None;
-            for cmd in self.commands.clone().iter() {
-                if cmd.name == command {
-                    if parts.len() == cmd.args {
-                        callback = Some(cmd.callback.clone());
-                        break;
-                    } else {
-                        godot_warn!(
-                            "Command \"{}\" requires \"{}\" arguments. Found {}. Code: {}",
-                            command,
-                            cmd.args,
-                            parts.len(),
-                            action
-                        );
-                    }
-                }
-            }
+        for call in Self::split_commands(code.as_str()) {
+            let overloads: Vec<&Command> = self
+                .commands
+                .iter()
+                .filter(|cmd| cmd.name == call.name)
+                .collect();
+            let Some(callable) = overloads
+                .iter()
+                .find(|cmd| cmd.args == call.args.len())
+                .map(|cmd| cmd.callback.clone())
+            else {
+                let warning = if overloads.is_empty() {
+                    format!(
+                        "Unrecognized command! \"{}\" in line \"{}\"",
+                        call.name, code
+                    )
+                } else {
+                    format!(
+                        "Command \"{}\" requires {} arguments. Found {}. Code: {}",
+                        call.name,
+                        overloads
+                            .iter()
+                            .map(|cmd| cmd.args.to_string())
+                            .collect::<Vec<_>>()
+                            .join(" or "),
+                        call.args.len(),
+                        code
+                    )
+                };
+                self.warn(warning);
+                continue;
+            };
+            let Some(callable) = callable else {
+                self.command_calls.push(call);
+                continue;
+            };
+            let result = (callable)(self, VecDeque::from(call.args.clone()));
+            self.store_command_result(&call, result);
+        }
+    }
 
-            if let Some(callable) = callback {
-                let parts = parts.iter().map(|v| v.to_string()).collect();
-                (callable)(self, parts);
-            } else {
-                godot_warn!(
-                    "Unrecognized command! \"{}\" in line \"{}\"",
-                    command,
-                    action
-                );
-            }
+    /// Stores the result of a command used as `name args -> key` under `key`. Does nothing for commands used without `->`
+    pub fn store_command_result(&mut self, call: &CommandCall, result: Entry) {
+        let Some(key) = &call.store else {
+            return;
+        };
+        if result == Entry::None {
+            self.warn(format!(
+                "Command \"{}\" returned no value that can be stored in \"{}\"",
+                call.name, key
+            ));
+            return;
+        }
+        self.set_entry(key, result);
+    }
+
+    fn push_builtin(
+        &mut self,
+        name: &str,
+        args: usize,
+        callback: fn(&mut Blackboard, VecDeque<String>),
+    ) {
+        self.commands.push(Command {
+            name: name.into(),
+            args,
+            callback: Some(Rc::new(move |bb, args| {
+                callback(bb, args);
+                Entry::None
+            })),
+            builtin: true,
+        });
+    }
+
+    /// Registers a custom command that is not run by the blackboard. Its uses are collected instead, to be run by the caller of [Blackboard::take_command_calls], which stores their results with [Blackboard::store_command_result].
+    /// Built-in commands cannot be replaced, false is returned for them. Registering an existing custom command with the same argument count replaces it
+    pub fn register_queued_command(&mut self, name: &str, args: usize) -> bool {
+        if self
+            .commands
+            .iter()
+            .any(|cmd| cmd.builtin && cmd.name == name)
+        {
+            return false;
         }
+        self.commands
+            .retain(|cmd| !(cmd.name == name && cmd.args == args));
+        self.commands.push(Command {
+            name: name.into(),
+            args,
+            callback: None,
+            builtin: false,
+        });
+        true
+    }
+
+    /// Removes all custom commands with this name. Returns false if none were registered
+    pub fn unregister_command(&mut self, name: &str) -> bool {
+        let count = self.commands.len();
+        self.commands.retain(|cmd| cmd.builtin || cmd.name != name);
+        count != self.commands.len()
     }
 
-    pub fn parse_query(&mut self, code: String) -> bool {
-        for query in code.split("and") {
-            let mut chunk_val = false;
-            for options in query.split("or") {
-                let parts = Vec::from_iter(options.split_whitespace());
-                if parts.len() != 3 {
-                    if query.contains('\"') {
-                        // if someone want's to make this support space strings, go right ahead, I'll accept the PR. But I'm not writing it myself lol
-                        godot_error!("Strings with spaces are not supported for queries! Only used for storage!")
+    /// Lists the name and argument count of every registered command
+    pub fn command_signatures(&self) -> Vec<(String, usize)> {
+        self.commands
+            .iter()
+            .map(|cmd| (cmd.name.clone(), cmd.args))
+            .collect()
+    }
+
+    /// Splits action code into its commands and their arguments without executing anything
+    pub fn split_commands(code: &str) -> Vec<CommandCall> {
+        Self::split_words(code)
+            .into_iter()
+            .filter_map(|words| {
+                let mut parts = VecDeque::from(words);
+                let name = parts.pop_front()?;
+                let mut args = Vec::from(parts);
+                // `command args -> key` stores the result of the command in `key`
+                let store = match args.len() {
+                    len if len >= 2 && args[len - 2] == "->" => {
+                        let key = args.pop();
+                        args.pop();
+                        key
                     }
-                    godot_warn!("Malformed query {}, in code {}", options, code);
-                    continue;
+                    _ => None,
+                };
+                Some(CommandCall { name, args, store })
+            })
+            .collect()
+    }
+
+    /// Splits action code into commands at `;` and each command into words at whitespace, keeping quoted strings and JSON arrays and objects in one word
+    fn split_words(code: &str) -> Vec<Vec<String>> {
+        let mut commands = Vec::new();
+        let mut words = Vec::new();
+        let mut word = String::new();
+        let mut quoted = false;
+        let mut escaped = false;
+        let mut depth = 0usize;
+        for c in code.chars() {
+            if quoted {
+                word.push(c);
+                match c {
+                    _ if escaped => escaped = false,
+                    '\\' => escaped = true,
+                    '"' => quoted = false,
+                    _ => (),
                 }
-                chunk_val = chunk_val || self.parse_query_value((parts[0], parts[1], parts[2]));
+                continue;
             }
-            if !chunk_val {
-                return false;
+            match c {
+                '"' => quoted = true,
+                '[' | '{' => depth += 1,
+                ']' | '}' => depth = depth.saturating_sub(1),
+                _ => (),
+            }
+            let splits = depth == 0 && (c == ';' || c.is_whitespace());
+            if !splits {
+                word.push(c);
+                continue;
+            }
+            if !word.is_empty() {
+                words.push(std::mem::take(&mut word));
+            }
+            if c == ';' {
+                commands.push(std::mem::take(&mut words));
             }
         }
-        true
+        if !word.is_empty() {
+            words.push(word);
+        }
+        commands.push(words);
+        commands
     }
 
-    fn parse_query_value(&mut self, query: (&str, &str, &str)) -> bool {
-        let arg1 = self.get_numeric_value(query.0);
-        let arg2 = self.get_numeric_value(query.2);
-        match query.1 {
-            "==" => arg1 == arg2,
-            "!=" => arg1 != arg2,
-            ">=" => arg1 >= arg2,
-            "<=" => arg1 <= arg2,
-            ">" => arg1 > arg2,
-            "<" => arg1 < arg2,
-            _ => {
-                godot_warn!(
-                    "Unrecognized operator in query: {} {} {}",
-                    query.0,
-                    query.1,
-                    query.2
-                );
-                false
-            }
+    /// Evaluates a query expression. An empty query is always true. See [super::dialog_expression] for the syntax
+    pub fn parse_query(&self, code: &str) -> Result<bool, QueryError> {
+        if code.trim().is_empty() {
+            return Ok(true);
         }
+        let expression = Expression::parse(code)?;
+        Ok(expression.evaluate(&|key| self.get(key))?.is_truthy())
     }
 
     pub fn set(&mut self, key: &str, value: &str) {
-        let entry = Self::get_entry_for(value);
+        let entry = self.get_entry_for(value);
         if entry == Entry::None {
-            godot_warn!("Failed to find valid entry for setting to \"{}\"", key);
+            self.warn(format!(
+                "Failed to find valid entry for setting to \"{}\"",
+                key
+            ));
             return;
         };
-        self.entries.insert(key.to_string(), entry.clone());
+        self.write(key, entry);
     }
 
     pub fn unset(&mut self, key: &str) {
-        let _ = self.entries.remove(&key.to_string());
+        self.write(key, Entry::None);
+    }
+
+    pub const SETTINGS_PREFIX: &'static str = "settings.";
+    pub const PROJECT_PREFIX: &'static str = "project.";
+
+    /// Inserts or removes (for [Entry::None]) an entry, recording the change
+    fn write(&mut self, key: &str, entry: Entry) -> bool {
+        if Self::is_read_only_key(key) {
+            self.warn(format!(
+                "Cannot change \"{}\", entries starting with \"{}\" or \"{}\" are read-only",
+                key,
+                Self::SETTINGS_PREFIX,
+                Self::PROJECT_PREFIX
+            ));
+            return false;
+        }
+        let key = Self::canonical_key(key);
+        let old = match &entry {
+            Entry::None => self.entries.remove(key),
+            _ => self.entries.insert(key.to_string(), entry.clone()),
+        }
+        .unwrap_or_default();
+        if old != entry && !Self::is_internal_key(key) {
+            self.changes.push(EntryChange {
+                key: key.to_string(),
+                old,
+                new: entry,
+            });
+        }
+        true
+    }
+
+    fn lookup(&self, key: &str) -> Option<Entry> {
+        if Self::is_read_only_key(key) {
+            return (self.host.read_only_entry)(key);
+        }
+        self.entries.get(Self::canonical_key(key)).cloned()
+    }
+
+    /// Whether the key is in the `settings.` or `project.` namespace, which can be read but not written
+    pub fn is_read_only_key(key: &str) -> bool {
+        key.starts_with(Self::SETTINGS_PREFIX) || key.starts_with(Self::PROJECT_PREFIX)
+    }
+
+    /// Resolves `settings.<key>` to the gameplay option with that key and `project.<path>` to a project setting
+    fn read_only_entry(key: &str) -> Option<Entry> {
+        let entry = if let Some(option_key) = key.strip_prefix(Self::SETTINGS_PREFIX) {
+            let config = Sqore::singleton().bind().get_config();
+            let gameplay = config.bind().gameplay.clone()?;
+            let value = gameplay.bind().get_option_value(option_key.to_godot())?;
+            Entry::from_variant(&value)
+        } else {
+            let path = key.strip_prefix(Self::PROJECT_PREFIX)?.to_godot();
+            let settings = ProjectSettings::singleton();
+            if !settings.has_setting(path.clone()) {
+                return None;
+            }
+            Entry::from_variant(&settings.get_setting(path))
+        };
+        Some(entry).filter(|entry| *entry != Entry::None)
+    }
+
+    /// `save.` is the default scope, so `save.gold` and `gold` are the same entry
+    pub fn canonical_key(key: &str) -> &str {
+        key.strip_prefix(Scope::SAVE_PREFIX).unwrap_or(key)
+    }
+
+    /// Whether `key` matches a watch pattern, where `*` matches any characters and `?` matches one. A `save.` prefix on the pattern only matches keys of the save scope
+    pub fn key_matches(pattern: &str, key: &str) -> bool {
+        let key = Self::canonical_key(key);
+        match pattern.strip_prefix(Scope::SAVE_PREFIX) {
+            Some(pattern) => Scope::of(key) == Scope::Save && Self::glob_matches(pattern, key),
+            None => Self::glob_matches(pattern, key),
+        }
+    }
+
+    fn glob_matches(pattern: &str, text: &str) -> bool {
+        let pattern: Vec<char> = pattern.chars().collect();
+        let text: Vec<char> = text.chars().collect();
+        let (mut p, mut t) = (0, 0);
+        // where to resume after the last `*` when a later part fails to match
+        let mut backtrack: Option<(usize, usize)> = None;
+        while t < text.len() {
+            match pattern.get(p) {
+                Some('*') => {
+                    backtrack = Some((p + 1, t));
+                    p += 1;
+                }
+                Some(c) if *c == '?' || *c == text[t] => {
+                    p += 1;
+                    t += 1;
+                }
+                _ => match backtrack {
+                    Some((star_p, star_t)) => {
+                        p = star_p;
+                        t = star_t + 1;
+                        backtrack = Some((star_p, star_t + 1));
+                    }
+                    None => return false,
+                },
+            }
+        }
+        pattern[p..].iter().all(|c| *c == '*')
+    }
+
+    fn warn(&mut self, warning: String) {
+        self.warnings.push(warning);
+    }
+
+    /// Problems running actions since the last call, such as unknown commands or values that failed to parse
+    pub fn take_warnings(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.warnings)
+    }
+
+    /// Changes made to entries since the last call, oldest first. Internal entries are left out
+    pub fn take_changes(&mut self) -> Vec<EntryChange> {
+        std::mem::take(&mut self.changes)
+    }
+
+    /// Uses of queued commands since the last call, in the order they appeared in actions
+    pub fn take_command_calls(&mut self) -> Vec<CommandCall> {
+        std::mem::take(&mut self.command_calls)
     }
 
     pub fn add(&mut self, key: &str, value: &str) {
-        if !self.entries.contains_key(&key.to_string()) {
-            godot_warn!("Cannot add to \"\"! Does not exist yet!");
+        if self.lookup(key).is_none() {
+            self.warn(format!("Cannot add to \"{}\"! Does not exist yet!", key));
             return;
         }
-        let entry = Self::get_entry_for(value);
+        let entry = self.get_entry_for(value);
         if entry == Entry::None {
-            godot_warn!("Failed to find valid entry for setting to \"{}\"", key);
+            self.warn(format!(
+                "Failed to find valid entry for setting to \"{}\"",
+                key
+            ));
             return;
         };
-        let Some(prev) = self.entries.get(&key.to_string()) else {
+        let Some(prev) = self.lookup(key) else {
             unreachable!()
         };
-        let nval = match prev {
-            Entry::Number(val) => match entry {
-                Entry::Number(entry_val) => Entry::Number(*val + entry_val),
-                Entry::String(_) => Entry::None,
-                Entry::Bool(entry_val) => Entry::Number(
-                    *val + match entry_val {
-                        true => 1f32,
-                        false => 0f32,
-                    },
-                ),
-                Entry::None => Entry::Number(*val),
-            },
-            Entry::String(val) => match entry {
-                Entry::Number(entry_val) => {
-                    Entry::String(val.clone() + entry_val.to_string().as_str())
-                }
-                Entry::String(entry_val) => Entry::String(val.clone() + entry_val.as_str()),
-                Entry::Bool(entry_val) => {
-                    Entry::String(val.clone() + entry_val.to_string().as_str())
-                }
-                Entry::None => Entry::String(val.clone()),
-            },
+        let nval = match (&prev, &entry) {
+            (Entry::Int(val), Entry::Int(_) | Entry::Bool(_)) => {
+                Entry::Int(val.saturating_add(entry.as_int().unwrap_or(0)))
+            }
+            (
+                Entry::Number(_) | Entry::Int(_),
+                Entry::Number(_) | Entry::Int(_) | Entry::Bool(_),
+            ) => {
+                Entry::Number(prev.as_number().unwrap_or(0f32) + entry.as_number().unwrap_or(0f32))
+            }
+            (
+                Entry::String(val),
+                Entry::Number(_) | Entry::Int(_) | Entry::String(_) | Entry::Bool(_),
+            ) => Entry::String(val.clone() + entry.to_string().as_str()),
             _ => Entry::None,
         };
-        self.entries.insert(key.to_string(), nval);
+        if nval == Entry::None {
+            self.warn(format!(
+                "Cannot add {} to the {} \"{}\"",
+                entry.type_name(),
+                prev.type_name(),
+                key
+            ));
+            return;
+        }
+        self.write(key, nval);
     }
     pub fn sub(&mut self, key: &str, value: &str) {
-        if !self.entries.contains_key(&key.to_string()) {
-            godot_warn!("Cannot add to \"\"! Does not exist yet!");
+        if self.lookup(key).is_none() {
+            self.warn(format!(
+                "Cannot subtract from \"{}\"! Does not exist yet!",
+                key
+            ));
             return;
         }
-        let entry = Self::get_entry_for(value);
+        let entry = self.get_entry_for(value);
         if entry == Entry::None {
-            godot_warn!("Failed to find valid entry for setting to \"{}\"", key);
+            self.warn(format!(
+                "Failed to find valid entry for setting to \"{}\"",
+                key
+            ));
             return;
         };
-        let Some(prev) = self.entries.get(&key.to_string()) else {
+        let Some(prev) = self.lookup(key) else {
             unreachable!()
         };
-        let nval = match prev {
-            Entry::Number(val) => match entry {
-                Entry::Number(entry_val) => Entry::Number(*val - entry_val),
-                Entry::String(_) => Entry::None,
-                Entry::Bool(entry_val) => Entry::Number(
-                    *val - match entry_val {
-                        true => 1f32,
-                        false => 0f32,
-                    },
-                ),
-                Entry::None => Entry::Number(*val),
-            },
+        let nval = match (&prev, &entry) {
+            (Entry::Int(val), Entry::Int(_) | Entry::Bool(_)) => {
+                Entry::Int(val.saturating_sub(entry.as_int().unwrap_or(0)))
+            }
+            (
+                Entry::Number(_) | Entry::Int(_),
+                Entry::Number(_) | Entry::Int(_) | Entry::Bool(_),
+            ) => {
+                Entry::Number(prev.as_number().unwrap_or(0f32) - entry.as_number().unwrap_or(0f32))
+            }
             _ => Entry::None,
         };
-        self.entries.insert(key.to_string(), nval);
+        if nval == Entry::None {
+            self.warn(format!(
+                "Cannot subtract {} from the {} \"{}\"",
+                entry.type_name(),
+                prev.type_name(),
+                key
+            ));
+            return;
+        }
+        self.write(key, nval);
+    }
+
+    /// Appends a value to a list, creating the list if the entry doesn't exist yet
+    pub fn push(&mut self, key: &str, value: &str) {
+        let entry = self.get_entry_for(value);
+        if entry == Entry::None {
+            self.warn(format!(
+                "Failed to find valid entry for pushing to \"{}\"",
+                key
+            ));
+            return;
+        }
+        let items = match self.lookup(key) {
+            None => vec![entry],
+            Some(Entry::List(mut items)) => {
+                items.push(entry);
+                items
+            }
+            Some(other) => {
+                self.warn(format!(
+                    "Cannot push to the {} \"{}\", it is not a list",
+                    other.type_name(),
+                    key
+                ));
+                return;
+            }
+        };
+        self.write(key, Entry::List(items));
+    }
+
+    /// Removes every occurrence of a value from a list, or the entry with that key from a map
+    pub fn remove(&mut self, key: &str, value: &str) {
+        let entry = self.get_entry_for(value);
+        if entry == Entry::None {
+            self.warn(format!(
+                "Failed to find valid entry for removing from \"{}\"",
+                key
+            ));
+            return;
+        }
+        let nval = match (self.lookup(key), &entry) {
+            (Some(Entry::List(items)), _) => Entry::List(
+                items
+                    .iter()
+                    .filter(|item| !item.loosely_eq(&entry))
+                    .cloned()
+                    .collect(),
+            ),
+            (Some(Entry::Map(mut entries)), Entry::String(map_key)) => {
+                entries.remove(map_key);
+                Entry::Map(entries)
+            }
+            (Some(other), _) => {
+                self.warn(format!(
+                    "Cannot remove {} from the {} \"{}\"",
+                    entry.type_name(),
+                    other.type_name(),
+                    key
+                ));
+                return;
+            }
+            (None, _) => {
+                self.warn(format!(
+                    "Cannot remove from \"{}\"! Does not exist yet!",
+                    key
+                ));
+                return;
+            }
+        };
+        self.write(key, nval);
+    }
+
+    /// Empties a list or map, keeping its type
+    pub fn clear_entry(&mut self, key: &str) {
+        let nval = match self.lookup(key) {
+            Some(Entry::List(_)) => Entry::List(Vec::new()),
+            Some(Entry::Map(_)) => Entry::Map(BTreeMap::new()),
+            Some(other) => {
+                self.warn(format!(
+                    "Cannot clear the {} \"{}\", only lists and maps can be cleared",
+                    other.type_name(),
+                    key
+                ));
+                return;
+            }
+            None => {
+                self.warn(format!("Cannot clear \"{}\"! Does not exist yet!", key));
+                return;
+            }
+        };
+        self.write(key, nval);
+    }
+
+    /// Flips a bool, a missing entry counts as false
+    pub fn toggle(&mut self, key: &str) {
+        let nval = match self.lookup(key) {
+            None => true,
+            Some(Entry::Bool(val)) => !val,
+            Some(other) => {
+                self.warn(format!(
+                    "Cannot toggle the {} \"{}\", it is not a bool",
+                    other.type_name(),
+                    key
+                ));
+                return;
+            }
+        };
+        self.write(key, Entry::Bool(nval));
     }
 
     pub const EVENT_KEY: &'static str = "__event__";
     pub const EVENT_ARG_KEY: &'static str = "__event_arg__";
-    fn set_event(&mut self, event_name: &str, arg: Option<&str>) {
+    fn set_event(&mut self, event_name: &str, arg: Option<Entry>) {
         self.entries.insert(
             Self::EVENT_KEY.to_string(),
             Entry::String(event_name.to_string()),
         );
         if let Some(arg) = arg {
-            self.entries
-                .insert(Self::EVENT_ARG_KEY.to_string(), Self::get_entry_for(arg));
+            self.entries.insert(Self::EVENT_ARG_KEY.to_string(), arg);
         }
     }
 
+    /// Jump targets are kept as raw strings, they can be an index, a label, or a `file:label` pair. See [super::dialog_track::JumpTarget]
     pub fn jump(&mut self, target: &str) {
-        self.set_event("jump", Some(target));
+        self.set_event("jump", Some(Entry::String(target.to_string())));
     }
 
     pub fn end(&mut self) {
@@ -278,113 +936,169 @@ impl Blackboard {
         }
     }
 
-    fn get_entry_for(value: &str) -> Entry {
-        let var = Json::parse_string(value.to_godot());
-        match var.get_type() {
-            VariantType::Nil => {
-                godot_warn!("Failed to parse \"{}\" into a handled type!", value,);
-                Entry::None
-            }
-            VariantType::Bool => Entry::Bool(var.booleanize()),
-            VariantType::Int => Entry::Number(i32::from_variant(&var) as f32),
-            VariantType::Float => Entry::Number(f32::from_variant(&var)),
-            VariantType::String => Entry::String(String::from_variant(&var)),
-            _ => {
-                godot_warn!("Fail! \"{}\" is not a handled type!", value,);
+    fn get_entry_for(&mut self, value: &str) -> Entry {
+        match (self.host.parse_literal)(value) {
+            Some(entry) => entry,
+            None => {
+                self.warn(format!(
+                    "Failed to parse \"{}\" into a handled type!",
+                    value
+                ));
                 Entry::None
             }
         }
     }
-    fn get_numeric_value(&self, key: &str) -> i32 {
-        // load entry
-        let entry: Entry = if self.entries.contains_key(&key.to_string()) {
-            // from variable name
-            self.entries.get(&key.to_string()).unwrap().clone() // we should be safe to unwrap here???
-        } else {
-            // from constant
-            let mut json = Json::new_gd();
-            let err = json.parse(key.to_godot());
-            if err != Error::OK {
-                // for whatever reason we failed, return a none value
-                Entry::None
-            } else {
-                let var = json.get_data();
-                match var.get_type() {
-                    VariantType::Float => Entry::Number(f32::from_variant(&var)),
-                    VariantType::Int => Entry::Number(i32::from_variant(&var) as f32),
-                    VariantType::Bool => Entry::Bool(bool::from_variant(&var)),
-                    VariantType::String => Entry::String(String::from_variant(&var)),
-                    _ => Entry::None,
-                }
-            }
-        };
-        match entry {
-            Entry::Number(val) => f32::floor(val) as i32, // this does force integer accuracy only, but then again, this system is not designed for complex maths
-            Entry::String(val) => {
-                let mut s = DefaultHasher::new();
-                val.hash(&mut s);
-                s.finish() as i32
-            }
-            Entry::Bool(val) => match val {
-                true => 1,
-                false => 0,
-            },
-            Entry::None => i32::MIN,
+
+    /// Parses a literal with the engine's JSON parser. The parser reads every number as a float, so whole numbers are turned back into ints the way [parse_literal] reads them: a whole number on its own, and every whole number inside a list or map
+    fn parse_json_literal(value: &str) -> Option<Entry> {
+        if let Ok(int) = value.trim().parse::<i64>() {
+            return Some(Entry::Int(int));
         }
+        let var = Json::parse_string(value.to_godot());
+        let entry = match var.get_type() {
+            VariantType::Array | VariantType::Dictionary => Entry::from_json_item(&var),
+            _ => Entry::from_variant(&var),
+        };
+        Some(entry).filter(|entry| *entry != Entry::None)
     }
 
+    /// Parses an argument for a custom command the same way as [Blackboard::get_entry_for], but bare words (`sword`) are passed along as strings
+    pub fn get_command_arg(&self, value: &str) -> Entry {
+        (self.host.parse_literal)(value).unwrap_or_else(|| Entry::String(value.to_string()))
+    }
+    /// Fills in the `{{ key | filter }}` templates of the text, see [Template]. Malformed templates leave the text as it is
     pub fn format_text(&self, text: String) -> String {
-        let mut buffer = String::with_capacity(text.len());
-        let mut remaining = text.as_str();
-        const LOOP_LIMITER: u32 = 9999;
-        const DELIM_OPEN: &str = "{{";
-        const DELIM_CLOS: &str = "}}";
-        for _ in 0..LOOP_LIMITER {
-            if remaining.is_empty() {
-                break;
-            }
-            let next = remaining.split_once(DELIM_OPEN);
-            let Some(next) = next else {
-                buffer += remaining;
-                break;
-            };
-            let (pre, post) = next;
-            buffer += pre;
-            let Some(fin) = post.split_once(DELIM_CLOS) else {
-                buffer += post;
-                break;
-            };
-            let (key, after) = fin;
-            let key = key.trim();
-            let var = self.get_variant_entry(key.trim());
-            if var.is_nil() {
-                godot_warn!("Found null when parsing dialog key: {}", key);
+        let template = match Template::parse(&text) {
+            Ok(template) => template,
+            Err(err) => {
+                godot_warn!("Malformed template in \"{}\": {}", text, err);
+                return text;
             }
-            buffer += var.to_string().as_str();
-            remaining = after;
+        };
+        let locale = match template.uses_plural() {
+            true => TranslationServer::singleton().get_locale().to_string(),
+            false => String::new(),
+        };
+        let mut warnings = Vec::new();
+        let formatted = template.render(|key| self.get(key), &locale, &mut warnings);
+        for warning in warnings {
+            godot_warn!("{}", warning);
         }
-        buffer
+        formatted
     }
 
     pub fn get_variant_entry(&self, key: &str) -> Variant {
-        let Some(entry) = self.entries.get(key) else {
+        let Some(entry) = self.lookup(key) else {
             godot_warn!("Entry not found \"{}\", returning nil", key);
             return Variant::nil();
         };
-        match entry {
-            Entry::Number(val) => val.to_variant(),
-            Entry::String(val) => val.to_variant(),
-            Entry::Bool(val) => val.to_variant(),
-            Entry::None => Variant::nil(),
+        entry.to_variant()
+    }
+
+    /// Sets an entry directly, bypassing the parsing done by [Blackboard::set]. Returns false for read-only keys
+    pub fn set_entry(&mut self, key: &str, entry: Entry) -> bool {
+        self.write(key, entry)
+    }
+
+    /// Lists the keys of all entries, excluding internal entries such as pending events
+    pub fn keys(&self) -> Vec<String> {
+        let mut keys: Vec<String> = self
+            .entries
+            .keys()
+            .filter(|key| !Self::is_internal_key(key))
+            .cloned()
+            .collect();
+        keys.sort();
+        keys
+    }
+
+    fn is_internal_key(key: &str) -> bool {
+        key.starts_with("__")
+    }
+
+    /// Removes all entries
+    pub fn clear(&mut self) {
+        for key in self.entries.keys().cloned().collect::<Vec<_>>() {
+            self.write(&key, Entry::None);
+        }
+    }
+
+    /// Removes all entries of a scope
+    pub fn clear_scope(&mut self, scope: Scope) {
+        for key in self.entries.keys().cloned().collect::<Vec<_>>() {
+            if Scope::of(&key) == scope {
+                self.write(&key, Entry::None);
+            }
+        }
+    }
+
+    /// Serializes all non-internal entries of the save scope, keeping their types
+    pub fn to_dictionary(&self) -> Dictionary {
+        self.to_scoped_dictionary(&[Scope::Save])
+    }
+
+    /// Serializes all non-internal entries of these scopes, keeping their types
+    pub fn to_scoped_dictionary(&self, scopes: &[Scope]) -> Dictionary {
+        let mut dict = Dictionary::new();
+        for key in self.keys() {
+            if !scopes.contains(&Scope::of(&key)) {
+                continue;
+            }
+            if let Some(entry) = self.entries.get(&key) {
+                dict.set(key, entry.to_typed_dict());
+            }
+        }
+        dict
+    }
+
+    /// Replaces the entries of the save scope with the ones serialized by [Blackboard::to_dictionary]
+    pub fn load_dictionary(&mut self, dict: &Dictionary) {
+        self.load_scoped_dictionary(dict, &[Scope::Save]);
+    }
+
+    /// Replaces the entries of these scopes with the ones serialized by [Blackboard::to_scoped_dictionary]. Entries of other scopes are kept, and left out of `dict`
+    pub fn load_scoped_dictionary(&mut self, dict: &Dictionary, scopes: &[Scope]) {
+        let mut loaded = HashMap::new();
+        for (key, value) in dict.iter_shared() {
+            let key = Self::canonical_key(key.to_string().as_str()).to_string();
+            if !scopes.contains(&Scope::of(&key)) {
+                continue;
+            }
+            let Ok(typed) = Dictionary::try_from_variant(&value) else {
+                godot_warn!(
+                    "Blackboard entry \"{}\" is corrupted. Found: {}",
+                    key,
+                    value
+                );
+                continue;
+            };
+            let entry = Entry::from_typed_dict(&typed);
+            if entry == Entry::None {
+                godot_warn!(
+                    "Blackboard entry \"{}\" has an unknown type. Found: {}",
+                    key,
+                    value
+                );
+                continue;
+            }
+            loaded.insert(key, entry);
+        }
+        for key in self.entries.keys().cloned().collect::<Vec<_>>() {
+            if scopes.contains(&Scope::of(&key)) && !loaded.contains_key(&key) {
+                self.write(&key, Entry::None);
+            }
+        }
+        for (key, entry) in loaded {
+            self.write(&key, entry);
         }
     }
 
     pub fn get(&self, key: &str) -> Option<Entry> {
-        Some(self.entries.get(key)?.clone())
+        self.lookup(key)
     }
 
     pub fn has_entry(&self, key: &str) -> bool {
-        self.entries.contains_key(key)
+        self.lookup(key).is_some()
     }
 
     pub fn has_event(&self) -> bool {
@@ -411,11 +1125,310 @@ impl fmt::Debug for Blackboard {
         f.debug_map().entries(self.entries.iter()).finish()
     }
 }
-type CommandFunction = Rc<dyn Fn(&mut Blackboard, VecDeque<String>)>;
+pub type CommandFunction = Rc<dyn Fn(&mut Blackboard, VecDeque<String>) -> Entry>;
 
 #[derive(Clone)]
 struct Command {
     name: String,
     args: usize,
-    callback: CommandFunction,
+    /// `None` for custom commands, whose uses are queued, see [Blackboard::register_queued_command]
+    callback: Option<CommandFunction>,
+    builtin: bool,
+}
+
+/// How long a blackboard entry lasts, picked by the prefix of its key
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scope {
+    /// `local.` entries, reset whenever a new track is loaded
+    Local,
+    /// `session.` entries, kept until the game quits
+    Session,
+    /// `save.` entries and entries without a scope prefix, the only ones saved
+    Save,
+}
+
+impl Scope {
+    pub const LOCAL_PREFIX: &'static str = "local.";
+    pub const SESSION_PREFIX: &'static str = "session.";
+    pub const SAVE_PREFIX: &'static str = "save.";
+
+    pub fn of(key: &str) -> Self {
+        if key.starts_with(Self::LOCAL_PREFIX) {
+            Scope::Local
+        } else if key.starts_with(Self::SESSION_PREFIX) {
+            Scope::Session
+        } else {
+            Scope::Save
+        }
+    }
+}
+
+/// A change to a blackboard entry. A missing entry is [Entry::None]
+#[derive(Debug, Clone, PartialEq)]
+pub struct EntryChange {
+    pub key: String,
+    pub old: Entry,
+    pub new: Entry,
+}
+
+/// A single command parsed from action code, such as `give_item sword -> has_sword`
+#[derive(Debug, Clone, PartialEq)]
+pub struct CommandCall {
+    pub name: String,
+    pub args: Vec<String>,
+    /// the key to store the command's result in, if any
+    pub store: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn string(text: &str) -> Entry {
+        Entry::String(text.to_string())
+    }
+
+    #[test]
+    fn lists_can_be_changed_and_queried() {
+        let mut blackboard = Blackboard::headless();
+        blackboard.parse_action(
+            "push visited \"old docks\"; push visited \"lighthouse\"; push visited 3".to_string(),
+        );
+        blackboard.parse_action("remove visited 3; toggle met_mara".to_string());
+        assert_eq!(
+            blackboard.parse_query("\"lighthouse\" in visited and len(visited) == 2"),
+            Ok(true)
+        );
+        assert_eq!(
+            blackboard.get("visited"),
+            Some(Entry::List(vec![string("old docks"), string("lighthouse")]))
+        );
+        assert_eq!(blackboard.get("met_mara"), Some(Entry::Bool(true)));
+        blackboard.parse_action("clear visited".to_string());
+        assert_eq!(
+            blackboard.parse_query("not (visited contains \"lighthouse\")"),
+            Ok(true)
+        );
+        assert_eq!(blackboard.get("visited"), Some(Entry::List(Vec::new())));
+        assert!(blackboard.take_warnings().is_empty());
+    }
+
+    #[test]
+    fn list_and_map_literals_are_parsed() {
+        let mut blackboard = Blackboard::headless();
+        blackboard.parse_action(
+            "set inventory [\"rope\", 2, [true]]; set mara {\"met\": true, \"gifts\": []}"
+                .to_string(),
+        );
+        assert_eq!(
+            blackboard.get("inventory"),
+            Some(Entry::List(vec![
+                string("rope"),
+                Entry::Int(2),
+                Entry::List(vec![Entry::Bool(true)]),
+            ]))
+        );
+        assert_eq!(
+            blackboard.get("mara"),
+            Some(Entry::Map(BTreeMap::from([
+                ("gifts".to_string(), Entry::List(Vec::new())),
+                ("met".to_string(), Entry::Bool(true)),
+            ])))
+        );
+        blackboard
+            .parse_action("push inventory {\"name\": \"map\"}; remove mara \"met\"".to_string());
+        assert_eq!(
+            blackboard.get("inventory").and_then(|entry| match entry {
+                Entry::List(items) => items.last().cloned(),
+                _ => None,
+            }),
+            Some(Entry::Map(BTreeMap::from([(
+                "name".to_string(),
+                string("map")
+            )])))
+        );
+        assert_eq!(
+            blackboard.get("mara"),
+            Some(Entry::Map(BTreeMap::from([(
+                "gifts".to_string(),
+                Entry::List(Vec::new())
+            )])))
+        );
+        assert!(blackboard.take_warnings().is_empty());
+        blackboard.parse_action("set broken [1, 2".to_string());
+        assert!(!blackboard.has_entry("broken"));
+        assert!(!blackboard.take_warnings().is_empty());
+    }
+
+    #[test]
+    fn json_numbers_in_lists_are_read_like_the_headless_parser() {
+        // the engine's JSON parser gives every number of `[2, -7, 0.25]` as a float
+        assert_eq!(
+            parse_literal("[2, -7, 0.25, 4e20]"),
+            Some(Entry::List(
+                [2.0, -7.0, 0.25, 4e20]
+                    .into_iter()
+                    .map(Entry::from_json_number)
+                    .collect()
+            ))
+        );
+        assert_eq!(Entry::from_json_number(-0.0), Entry::Int(0));
+        assert_eq!(
+            Entry::from_json_number(i64::MAX as f64),
+            Entry::Number(i64::MAX as f32)
+        );
+    }
+
+    #[test]
+    fn push_and_remove_need_a_list() {
+        let mut blackboard = Blackboard::headless();
+        // pushing to a missing entry starts a list, removing from one has nothing to remove from
+        blackboard.parse_action("push visited \"docks\"; remove missing \"docks\"".to_string());
+        assert_eq!(
+            blackboard.get("visited"),
+            Some(Entry::List(vec![string("docks")]))
+        );
+        assert!(!blackboard.has_entry("missing"));
+        assert_eq!(blackboard.take_warnings().len(), 1);
+
+        blackboard.set_entry("gold", Entry::Int(3));
+        blackboard.take_changes();
+        blackboard.parse_action("push gold 1; remove gold 3".to_string());
+        assert_eq!(blackboard.get("gold"), Some(Entry::Int(3)));
+        assert_eq!(blackboard.take_warnings().len(), 2);
+        assert!(blackboard.take_changes().is_empty());
+    }
+
+    #[test]
+    fn commands_are_picked_by_argument_count() {
+        let mut blackboard = Blackboard::headless();
+        assert!(blackboard.register_queued_command("give", 1));
+        assert!(blackboard.register_queued_command("give", 2));
+        assert!(!blackboard.register_queued_command("set", 2));
+        blackboard.parse_action("give sword; give gold 5".to_string());
+        assert!(blackboard.take_warnings().is_empty());
+        assert_eq!(blackboard.take_command_calls().len(), 2);
+
+        // one warning for a known command used with the wrong argument count, one for an unknown command
+        blackboard.parse_action("give; shout loudly".to_string());
+        let warnings = blackboard.take_warnings();
+        assert_eq!(warnings.len(), 2, "{:?}", warnings);
+        assert!(warnings[0].contains("requires 1 or 2 arguments"));
+        assert!(warnings[1].starts_with("Unrecognized command"));
+        assert!(blackboard.take_command_calls().is_empty());
+    }
+
+    #[test]
+    fn scoped_entries_resolve_by_prefix() {
+        let mut blackboard = Blackboard::headless();
+        blackboard.parse_action(
+            "set local.asked true; set session.visits 2; set save.gold 3".to_string(),
+        );
+        assert_eq!(
+            blackboard.parse_query("local.asked and gold == 3 and session.visits == 2"),
+            Ok(true)
+        );
+        // `save.` is the scope of unprefixed keys, so both name the same entry
+        blackboard.parse_action("add gold 1".to_string());
+        assert_eq!(blackboard.get("save.gold"), Some(Entry::Int(4)));
+        assert_eq!(
+            blackboard.keys(),
+            vec!["gold", "local.asked", "session.visits"]
+        );
+        assert_eq!(
+            blackboard
+                .take_changes()
+                .into_iter()
+                .map(|change| change.key)
+                .collect::<Vec<_>>(),
+            vec!["local.asked", "session.visits", "gold", "gold"]
+        );
+        // what loading a track does, see SqoreDialog::load_track
+        blackboard.clear_scope(Scope::Local);
+        assert_eq!(blackboard.get("local.asked"), None);
+        assert_eq!(blackboard.get("session.visits"), Some(Entry::Int(2)));
+        assert_eq!(blackboard.keys(), vec!["gold", "session.visits"]);
+        assert_eq!(
+            blackboard.take_changes(),
+            vec![EntryChange {
+                key: "local.asked".to_string(),
+                old: Entry::Bool(true),
+                new: Entry::None,
+            }]
+        );
+    }
+
+    /// A blackboard whose host has one gameplay option and one project setting
+    fn with_settings() -> Blackboard {
+        Blackboard::with_host(BlackboardHost {
+            read_only_entry: |key| match key {
+                "settings.text_speed" => Some(Entry::Number(1.5)),
+                "project.application/config/name" => Some(string("Lighthouse")),
+                _ => None,
+            },
+            ..BlackboardHost::HEADLESS
+        })
+    }
+
+    #[test]
+    fn read_only_entries_resolve_through_the_host() {
+        let blackboard = with_settings();
+        assert_eq!(
+            blackboard.get("settings.text_speed"),
+            Some(Entry::Number(1.5))
+        );
+        assert_eq!(
+            blackboard.parse_query("project.application/config/name == \"Lighthouse\""),
+            Ok(true)
+        );
+        assert_eq!(blackboard.get("settings.missing"), None);
+        // read-only entries are not stored in the blackboard
+        assert!(blackboard.keys().is_empty());
+    }
+
+    #[test]
+    fn read_only_entries_cannot_be_changed() {
+        let mut blackboard = with_settings();
+        blackboard.parse_action("set settings.text_speed 3; add settings.text_speed 1".to_string());
+        assert_eq!(blackboard.take_warnings().len(), 2);
+        assert!(!blackboard.set_entry("project.application/config/name", string("Docks")));
+        assert_eq!(
+            blackboard.get("settings.text_speed"),
+            Some(Entry::Number(1.5))
+        );
+        assert_eq!(
+            blackboard.get("project.application/config/name"),
+            Some(string("Lighthouse"))
+        );
+        assert!(blackboard.take_changes().is_empty());
+    }
+
+    #[test]
+    fn glob_patterns_match_text() {
+        assert!(Blackboard::glob_matches("met_captain", "met_captain"));
+        assert!(!Blackboard::glob_matches("met_captain", "met_captains"));
+        // `*` does not stop at `.`
+        assert!(Blackboard::glob_matches("quest.*", "quest.lighthouse.done"));
+        assert!(Blackboard::glob_matches("*.done", "quest.lighthouse.done"));
+        assert!(Blackboard::glob_matches(
+            "quest.*.done",
+            "quest.lighthouse.done"
+        ));
+        assert!(Blackboard::glob_matches("*", ""));
+        assert!(!Blackboard::glob_matches("quest.*.done", "quest.started"));
+        assert!(Blackboard::glob_matches("quest.?", "quest.a"));
+        assert!(!Blackboard::glob_matches("quest.?", "quest.ab"));
+        assert!(!Blackboard::glob_matches("quest.?", "quest."));
+    }
+
+    #[test]
+    fn watch_patterns_match_keys() {
+        assert!(Blackboard::key_matches("met_captain", "met_captain"));
+        assert!(Blackboard::key_matches("quest.*", "quest.lighthouse.done"));
+        assert!(Blackboard::key_matches("save.gold", "gold"));
+        assert!(Blackboard::key_matches("gold", "save.gold"));
+        assert!(Blackboard::key_matches("save.*", "gold"));
+        assert!(!Blackboard::key_matches("save.*", "local.asked"));
+        assert!(!Blackboard::key_matches("gold", "gold_total"));
+    }
 }
@@ -4,6 +4,8 @@ use godot::prelude::*;
 #[class(init, base=Object)]
 pub struct DialogBuilder {
     nodes: Array<Dictionary>,
+    /// the node arrays containing the branches currently being built, innermost last
+    parents: Vec<Array<Dictionary>>,
 
     node: Base<Object>,
 }
@@ -57,6 +59,68 @@ impl DialogBuilder {
         prev.set("options", choices_array.to_variant());
     }
 
+    /// Sets the voice-over clip of the previously pushed text or choice node
+    #[func]
+    fn set_voice(&mut self, voice: GString) {
+        let Some(prev) = &mut self.nodes.last() else {
+            godot_warn!("Cannot set the voice of an empty node!");
+            return;
+        };
+        let node_type =
+            String::try_from_variant(&prev.get("type").unwrap_or_default()).unwrap_or_default();
+        if node_type != "text" && node_type != "choice" {
+            godot_warn!(
+                "When setting a voice, the previously pushed node must be a text or choice node!"
+            );
+            return;
+        }
+        prev.set("voice", voice);
+    }
+
+    /// Makes the previously pushed choice node select the option at `default` once `seconds` pass
+    #[func]
+    fn set_choice_timeout(&mut self, seconds: f32, default: i32) {
+        let Some(prev) = &mut self.nodes.last() else {
+            godot_warn!("Cannot set the timeout of an empty node!");
+            return;
+        };
+        if String::try_from_variant(&prev.get("type").unwrap_or_default()).unwrap_or_default()
+            != "choice"
+        {
+            godot_warn!(
+                "When setting a timeout, the previously pushed node must be a choice node!"
+            );
+            return;
+        }
+        prev.set("timeout", seconds);
+        prev.set("default", default);
+    }
+
+    /// Sets the `once`, `sticky` and `fallback` flags of the previously pushed choice option
+    #[func]
+    fn set_option_flags(&mut self, once: bool, sticky: bool, fallback: bool) {
+        let Some(prev) = &mut self.nodes.last() else {
+            godot_warn!("Cannot set option flags on an empty node!");
+            return;
+        };
+        let Ok(options) = Array::<Variant>::try_from_variant(&prev.get_or_nil("options")) else {
+            godot_warn!(
+                "When setting option flags, the previously pushed node must be a choice node!"
+            );
+            return;
+        };
+        let Some(Ok(mut option)) = options
+            .last()
+            .map(|option| Dictionary::try_from_variant(&option))
+        else {
+            godot_warn!("When setting option flags, the previous choice node must have an option!");
+            return;
+        };
+        option.set("once", once);
+        option.set("sticky", sticky);
+        option.set("fallback", fallback);
+    }
+
     #[func]
     fn push_signal(&mut self, text: GString, character: GString, requirements: GString) {
         let mut node = Dictionary::new();
@@ -75,10 +139,109 @@ impl DialogBuilder {
         self.nodes.push(node);
     }
 
+    #[func]
+    fn push_label(&mut self, name: GString) {
+        let mut node = Dictionary::new();
+        node.set("type", "label");
+        node.set("name", name);
+        self.nodes.push(node);
+    }
+
+    /// Pushes an `if` node. Nodes pushed after this go into its branch until [DialogBuilder::end_branch]
+    #[func]
+    fn begin_if(&mut self, requires: GString) {
+        self.push_branch("if", requires);
+    }
+
+    /// Ends the current `if` or `elif` branch and starts an `elif` branch after it
+    #[func]
+    fn begin_elif(&mut self, requires: GString) {
+        self.continue_branch("elif", requires);
+    }
+
+    /// Ends the current `if` or `elif` branch and starts the `else` branch after it
+    #[func]
+    fn begin_else(&mut self) {
+        self.continue_branch("else", GString::new());
+    }
+
+    /// Nodes pushed after this go into the branch of the previously pushed choice option, until [DialogBuilder::end_branch]
+    #[func]
+    fn begin_option_nodes(&mut self) {
+        let Some(prev) = self.nodes.last() else {
+            godot_warn!("Cannot begin option nodes on an empty node!");
+            return;
+        };
+        let Some(Ok(mut option)) = Array::<Variant>::try_from_variant(&prev.get_or_nil("options"))
+            .ok()
+            .and_then(|options| options.last())
+            .map(|option| Dictionary::try_from_variant(&option))
+        else {
+            godot_warn!(
+                "When beginning option nodes, the previously pushed node must be a choice node with an option!"
+            );
+            return;
+        };
+        let nodes = Array::<Dictionary>::new();
+        option.set("nodes", nodes.clone());
+        self.parents.push(std::mem::replace(&mut self.nodes, nodes));
+    }
+
+    /// Ends the branch started by the last `begin_*` call, pushing nodes after the branch again
+    #[func]
+    fn end_branch(&mut self) {
+        let Some(parent) = self.parents.pop() else {
+            godot_warn!("Cannot end a branch, no branch was started!");
+            return;
+        };
+        self.nodes = parent;
+    }
+
+    fn push_branch(&mut self, node_type: &str, requires: GString) {
+        let nodes = Array::<Dictionary>::new();
+        let mut node = Dictionary::new();
+        node.set("type", node_type);
+        if !requires.is_empty() {
+            node.set("requires", requires);
+        }
+        node.set("nodes", nodes.clone());
+        self.nodes.push(node);
+        self.parents.push(std::mem::replace(&mut self.nodes, nodes));
+    }
+
+    fn continue_branch(&mut self, node_type: &str, requires: GString) {
+        let Some(parent) = self.parents.last() else {
+            godot_warn!(
+                "Cannot begin an '{}' branch outside of an 'if' branch!",
+                node_type
+            );
+            return;
+        };
+        let prev_type = parent
+            .last()
+            .map(|prev| prev.get("type").unwrap_or_default().to_string())
+            .unwrap_or_default();
+        if prev_type != "if" && prev_type != "elif" {
+            godot_warn!(
+                "An '{}' branch must directly follow an 'if' or 'elif' branch!",
+                node_type
+            );
+            return;
+        }
+        self.end_branch();
+        self.push_branch(node_type, requires);
+    }
+
     #[func]
     fn get_dialog_track(&self) -> Dictionary {
+        if !self.parents.is_empty() {
+            godot_warn!(
+                "Getting a dialog track while a branch is still open, call `end_branch` first!"
+            );
+        }
         let mut dict = Dictionary::new();
-        dict.set("nodes".to_godot(), self.nodes.clone());
+        let root = self.parents.first().unwrap_or(&self.nodes);
+        dict.set("nodes".to_godot(), root.clone());
         dict
     }
 }
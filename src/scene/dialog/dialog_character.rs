@@ -0,0 +1,82 @@
+use godot::{
+    engine::{AudioStream, LabelSettings, Texture2D},
+    prelude::*,
+};
+
+/// A character that dialog lines can refer to by `id`, or by `id:expression` to pick a portrait. Registered in [super::dialog_settings::DialogSettings::characters]
+#[derive(GodotClass)]
+#[class(tool, base=Resource)]
+pub struct DialogCharacter {
+    /// The name tracks use to refer to this character
+    #[export]
+    pub id: GString,
+
+    /// The name shown in the dialog. Translated and templated like any other character name
+    #[export]
+    pub display_name: GString,
+
+    /// Overrides the name label style from the dialog settings
+    #[export]
+    pub name_label_settings: Option<Gd<LabelSettings>>,
+
+    #[export]
+    pub use_name_color: bool,
+
+    /// The colour of the name label, when `use_name_color` is enabled
+    #[export]
+    pub name_color: Color,
+
+    /// Maps expression names to portrait textures. The "default" expression is used when a line names no expression, or one that is missing
+    #[export]
+    pub portraits: Dictionary,
+
+    /// Played as the text of this character's lines is revealed
+    #[export]
+    pub typing_blip: Option<Gd<AudioStream>>,
+
+    base: Base<Resource>,
+}
+
+#[godot_api]
+impl IResource for DialogCharacter {
+    fn init(base: Base<Resource>) -> Self {
+        Self {
+            id: GString::new(),
+            display_name: GString::new(),
+            name_label_settings: None,
+            use_name_color: false,
+            name_color: Color::WHITE,
+            portraits: Dictionary::new(),
+            typing_blip: None,
+            base,
+        }
+    }
+}
+
+#[godot_api]
+impl DialogCharacter {
+    pub const DEFAULT_EXPRESSION: &'static str = "default";
+
+    /// Gets the portrait for an expression, falling back to the default portrait
+    #[func]
+    pub fn get_portrait(&self, expression: GString) -> Option<Gd<Texture2D>> {
+        let expression = if expression.is_empty() {
+            Self::DEFAULT_EXPRESSION.to_godot()
+        } else {
+            expression
+        };
+        [expression, Self::DEFAULT_EXPRESSION.to_godot()]
+            .into_iter()
+            .filter_map(|key| self.portraits.get(key))
+            .find_map(|portrait| Gd::<Texture2D>::try_from_variant(&portrait).ok())
+    }
+
+    /// The name to display, the id if no display name is set
+    pub fn get_name(&self) -> GString {
+        if self.display_name.is_empty() {
+            self.id.clone()
+        } else {
+            self.display_name.clone()
+        }
+    }
+}
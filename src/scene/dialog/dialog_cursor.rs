@@ -0,0 +1,103 @@
+//! Walks the tree of lines of a dialog track. Branches and choice options hold their own sequences of lines, once such a sequence is done the cursor continues with the line after the branch or choice it was entered from.
+use super::dialog_track::Line;
+
+#[derive(Debug, Clone, Default)]
+pub struct DialogCursor {
+    /// the sequences being played, from the top level of the track down to the innermost branch
+    frames: Vec<Frame>,
+}
+
+#[derive(Debug, Clone)]
+struct Frame {
+    lines: Vec<Line>,
+    /// index of the next line to take from `lines`
+    next: usize,
+    /// which nested sequence of the parent's current line this frame plays. Unused for the top level
+    nested: usize,
+}
+
+impl DialogCursor {
+    /// Starts at the top level line `index` of `lines`
+    pub fn new(lines: Vec<Line>, index: usize) -> Self {
+        Self {
+            frames: vec![Frame {
+                lines,
+                next: index,
+                nested: 0,
+            }],
+        }
+    }
+
+    /// Rebuilds a cursor from a [DialogCursor::position], so that the next line taken is the line the position points at. Returns None if the position does not fit `lines`
+    pub fn at(lines: Vec<Line>, position: &[usize]) -> Option<Self> {
+        let (&index, mut rest) = position.split_first()?;
+        let mut cursor = Self::new(lines, index);
+        loop {
+            let frame = cursor.frames.last()?;
+            if frame.next >= frame.lines.len() {
+                return None;
+            }
+            let [nested, index, remaining @ ..] = rest else {
+                break;
+            };
+            cursor.next_line();
+            if !cursor.enter(*nested) {
+                return None;
+            }
+            cursor.frames.last_mut()?.next = *index;
+            rest = remaining;
+        }
+        rest.is_empty().then_some(cursor)
+    }
+
+    /// Takes the next line, returning to the parent sequence whenever a nested sequence runs out. None once the track is done
+    pub fn next_line(&mut self) -> Option<Line> {
+        loop {
+            let frame = self.frames.last_mut()?;
+            if let Some(line) = frame.lines.get(frame.next) {
+                frame.next += 1;
+                return Some(line.clone());
+            }
+            self.frames.pop();
+        }
+    }
+
+    /// Enters the nested sequence at `nested` of the line last taken, see [Line::nested]. The lines after it are taken once the sequence is done. Returns false if there is no such sequence
+    pub fn enter(&mut self, nested: usize) -> bool {
+        let Some(line) = self.current_line() else {
+            return false;
+        };
+        let Some(lines) = line.nested().get(nested).map(|lines| lines.to_vec()) else {
+            return false;
+        };
+        self.frames.push(Frame {
+            lines,
+            next: 0,
+            nested,
+        });
+        true
+    }
+
+    /// The line last taken with [DialogCursor::next_line], unless the sequence it was in has been left since
+    pub fn current_line(&self) -> Option<&Line> {
+        let frame = self.frames.last()?;
+        frame.lines.get(frame.next.checked_sub(1)?)
+    }
+
+    /// Drops every remaining line, ending the track
+    pub fn clear(&mut self) {
+        self.frames.clear();
+    }
+
+    /// The path to the line last taken: its top level index, followed by the nested sequence and line index for each level entered
+    pub fn position(&self) -> Vec<usize> {
+        let mut position = Vec::new();
+        for (depth, frame) in self.frames.iter().enumerate() {
+            if depth > 0 {
+                position.push(frame.nested);
+            }
+            position.push(frame.next.saturating_sub(1));
+        }
+        position
+    }
+}
@@ -1,4 +1,6 @@
-use godot::prelude::*;
+use godot::{engine::notify::NodeNotification, prelude::*};
+
+use super::{core_dialog::SqoreDialog, dialog_blackboard::CommandCall};
 
 #[derive(GodotClass)]
 #[class(init, base=Node)]
@@ -7,18 +9,92 @@ pub struct DialogEvents {
 }
 
 #[godot_api]
-impl INode for DialogEvents {}
+impl INode for DialogEvents {
+    fn on_notification(&mut self, what: NodeNotification) {
+        // the game can quit in the middle of a track, before the seen lines would be saved
+        if matches!(
+            what,
+            NodeNotification::WmCloseRequest | NodeNotification::ExitTree
+        ) {
+            SqoreDialog::singleton().bind_mut().save_seen_lines();
+        }
+    }
+}
 
 #[godot_api]
 impl DialogEvents {
     pub const SIGNAL_TRACK_STARTED: &'static str = "track_started";
     pub const SIGNAL_TRACK_ENDED: &'static str = "track_ended";
     pub const SIGNAL_TRACK_SIGNAL: &'static str = "track_signal";
+    pub const SIGNAL_TEXT_EMITTED: &'static str = "text_emitted";
+    pub const SIGNAL_CHOICE_RESOLVED: &'static str = "choice_resolved";
+    pub const SIGNAL_LINE_STARTED: &'static str = "line_started";
+    pub const SIGNAL_LINE_REVEALED: &'static str = "line_revealed";
+    pub const SIGNAL_CHOICES_PRESENTED: &'static str = "choices_presented";
+    pub const SIGNAL_CHOICE_MADE: &'static str = "choice_made";
+    pub const SIGNAL_ACTION_EXECUTED: &'static str = "action_executed";
+    pub const SIGNAL_BLACKBOARD_CHANGED: &'static str = "blackboard_changed";
+    pub const METHOD_NOTIFY_WATCHER: &'static str = "notify_watcher";
+    pub const METHOD_RUN_COMMAND: &'static str = "run_command";
 
+    /// emitted when a track ends or is replaced by another track, with the file of the track
     #[signal]
     fn track_ended(track: GString) {}
+    /// emitted for `@signal` lines with the signal name and its arguments. Like the other signals it is emitted deferred, so it arrives in order with `line_started` and `action_executed`
     #[signal]
     fn track_signal(name: GString, args: Array<Variant>) {}
+    /// emitted when a track starts playing, including tracks reached with a `jump`, with the file of the track
     #[signal]
     fn track_started(track: GString) {}
+    /// emitted when a text or choice line is displayed, with the character name and text after templating and translation, and the index of the line's top level node in its track
+    #[signal]
+    fn line_started(character: GString, text: GString, index: i64) {}
+    /// emitted once the text of the current line is fully revealed
+    #[signal]
+    fn line_revealed() {}
+    /// emitted when the buttons of a choice are displayed, with the text of each option that can be chosen
+    #[signal]
+    fn choices_presented(options: PackedStringArray) {}
+    /// emitted when an option is chosen, with the index of the option in its choice and its text
+    #[signal]
+    fn choice_made(index: i64, text: GString) {}
+    /// emitted for the code of every action run, whether from an action line, an option, or [super::core_dialog::SqoreDialog::blackboard_action]
+    #[signal]
+    fn action_executed(code: GString) {}
+    /// emitted when a blackboard entry changes. A missing entry is null
+    #[signal]
+    fn blackboard_changed(key: GString, old_value: Variant, new_value: Variant) {}
+    /// emitted by `[emit=name]` tags in dialog text as the text is revealed
+    #[signal]
+    fn text_emitted(name: GString) {}
+    /// emitted when a choice is resolved, with the index of the selected option and whether it was selected because the choice timed out
+    #[signal]
+    fn choice_resolved(index: i64, timed_out: bool) {}
+
+    /// Calls a watcher registered with [super::core_dialog::SqoreDialog::watch]. Called deferred from here rather than from SqoreDialog so the watcher can use SqoreDialog freely
+    #[func]
+    fn notify_watcher(
+        &self,
+        watcher: Callable,
+        key: GString,
+        old_value: Variant,
+        new_value: Variant,
+    ) {
+        watcher.callv(VariantArray::from_iter([
+            key.to_variant(),
+            old_value,
+            new_value,
+        ]));
+    }
+
+    /// Runs a custom command used through [SqoreDialog::blackboard_action] once the action is done. The result is stored under `store` unless it is empty
+    #[func]
+    fn run_command(&self, name: GString, args: PackedStringArray, store: GString) {
+        let store = store.to_string();
+        SqoreDialog::run_commands(vec![CommandCall {
+            name: name.to_string(),
+            args: args.as_slice().iter().map(|arg| arg.to_string()).collect(),
+            store: Some(store).filter(|store| !store.is_empty()),
+        }]);
+    }
 }
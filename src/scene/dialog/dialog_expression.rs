@@ -0,0 +1,749 @@
+//! The expression language used by blackboard queries (`requires` entries and [super::core_dialog::SqoreDialog::blackboard_query])
+//!
+//! Supported syntax:
+//! - literals: numbers (`5`, `2.5`), strings (`"two words"` or `'two words'`), `true`, `false`, `nil`
+//! - variables: any blackboard key, e.g. `gold` or `met_captain`. The read-only `settings.<key>` and `project.<path>` keys give gameplay options and project settings, a `project.` path runs up to the next space (`project.application/config/name`)
+//! - arithmetic: `+ - * / %` and unary `-`. `+` on strings concatenates. Whole numbers stay exact unless mixed with fractions
+//! - comparisons: `== != < <= > >=`
+//! - lists and maps: `visited contains "docks"`, `"docks" in visited` (a key for maps, text for strings), and `len(visited)`
+//! - logic: `and`, `or`, `not` (or `&&`, `||`, `!`)
+//! - parentheses for grouping
+//!
+//! Precedence from lowest to highest: `or`, `and`, `not`, comparisons (including `contains` and `in`), `+ -`, `* / %`, unary `-`
+use std::fmt;
+
+use super::dialog_blackboard::{Blackboard, Entry};
+
+/// An error found while parsing or evaluating an expression. `position` is the 0-based character offset into the expression
+#[derive(Debug, Clone, PartialEq)]
+pub struct QueryError {
+    pub position: usize,
+    pub message: String,
+}
+
+impl QueryError {
+    fn new(position: usize, message: impl Into<String>) -> Self {
+        Self {
+            position,
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for QueryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} (at column {})", self.message, self.position + 1)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(f32),
+    Int(i64),
+    String(String),
+    Ident(String),
+    Op(BinaryOp),
+    Not,
+    LParen,
+    RParen,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinaryOp {
+    Or,
+    And,
+    Equal,
+    NotEqual,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Mod,
+    Contains,
+    In,
+}
+
+impl BinaryOp {
+    fn symbol(&self) -> &'static str {
+        match self {
+            BinaryOp::Or => "or",
+            BinaryOp::And => "and",
+            BinaryOp::Equal => "==",
+            BinaryOp::NotEqual => "!=",
+            BinaryOp::Less => "<",
+            BinaryOp::LessEqual => "<=",
+            BinaryOp::Greater => ">",
+            BinaryOp::GreaterEqual => ">=",
+            BinaryOp::Add => "+",
+            BinaryOp::Sub => "-",
+            BinaryOp::Mul => "*",
+            BinaryOp::Div => "/",
+            BinaryOp::Mod => "%",
+            BinaryOp::Contains => "contains",
+            BinaryOp::In => "in",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expression {
+    Literal(Entry),
+    Variable {
+        name: String,
+        position: usize,
+    },
+    Not(Box<Expression>),
+    Negate {
+        value: Box<Expression>,
+        position: usize,
+    },
+    /// `len(value)`
+    Len {
+        value: Box<Expression>,
+        position: usize,
+    },
+    Binary {
+        op: BinaryOp,
+        lhs: Box<Expression>,
+        rhs: Box<Expression>,
+        position: usize,
+    },
+}
+
+impl Expression {
+    pub fn parse(code: &str) -> Result<Self, QueryError> {
+        let tokens = tokenize(code)?;
+        let mut parser = Parser {
+            tokens,
+            index: 0,
+            end: code.chars().count(),
+        };
+        let expr = parser.parse_or()?;
+        if let Some((token, position)) = parser.peek() {
+            return Err(QueryError::new(
+                *position,
+                format!("unexpected {:?} after end of expression", token),
+            ));
+        }
+        Ok(expr)
+    }
+
+    /// Evaluates the expression, looking up variables through `resolve`. Unknown variables resolve to [Entry::None]
+    pub fn evaluate(&self, resolve: &dyn Fn(&str) -> Option<Entry>) -> Result<Entry, QueryError> {
+        match self {
+            Expression::Literal(entry) => Ok(entry.clone()),
+            Expression::Variable { name, .. } => Ok(resolve(name).unwrap_or_default()),
+            Expression::Not(value) => Ok(Entry::Bool(!value.evaluate(resolve)?.is_truthy())),
+            Expression::Negate { value, position } => match value.evaluate(resolve)? {
+                Entry::Number(val) => Ok(Entry::Number(-val)),
+                Entry::Int(val) => {
+                    Ok(Entry::Int(val.checked_neg().ok_or_else(|| {
+                        QueryError::new(*position, "integer overflow")
+                    })?))
+                }
+                other => Err(QueryError::new(
+                    *position,
+                    format!("cannot negate {}", other.type_name()),
+                )),
+            },
+            Expression::Len { value, position } => {
+                let value = value.evaluate(resolve)?;
+                match value.len() {
+                    Some(len) => Ok(Entry::Int(len as i64)),
+                    None => Err(QueryError::new(
+                        *position,
+                        format!("cannot take the length of {}", value.type_name()),
+                    )),
+                }
+            }
+            Expression::Binary {
+                op,
+                lhs,
+                rhs,
+                position,
+            } => {
+                // short circuit logic operators
+                match op {
+                    BinaryOp::And => {
+                        return Ok(Entry::Bool(
+                            lhs.evaluate(resolve)?.is_truthy()
+                                && rhs.evaluate(resolve)?.is_truthy(),
+                        ))
+                    }
+                    BinaryOp::Or => {
+                        return Ok(Entry::Bool(
+                            lhs.evaluate(resolve)?.is_truthy()
+                                || rhs.evaluate(resolve)?.is_truthy(),
+                        ))
+                    }
+                    _ => (),
+                }
+                let lhs = lhs.evaluate(resolve)?;
+                let rhs = rhs.evaluate(resolve)?;
+                apply_binary(*op, lhs, rhs, *position)
+            }
+        }
+    }
+
+    /// Collects the names of all variables referenced by this expression
+    pub fn variables(&self) -> Vec<String> {
+        let mut buffer = Vec::new();
+        self.collect_variables(&mut buffer);
+        buffer
+    }
+
+    fn collect_variables(&self, buffer: &mut Vec<String>) {
+        match self {
+            Expression::Literal(_) => (),
+            Expression::Variable { name, .. } => buffer.push(name.clone()),
+            Expression::Not(value)
+            | Expression::Negate { value, .. }
+            | Expression::Len { value, .. } => value.collect_variables(buffer),
+            Expression::Binary { lhs, rhs, .. } => {
+                lhs.collect_variables(buffer);
+                rhs.collect_variables(buffer);
+            }
+        }
+    }
+}
+
+fn apply_binary(
+    op: BinaryOp,
+    lhs: Entry,
+    rhs: Entry,
+    position: usize,
+) -> Result<Entry, QueryError> {
+    let mismatch = |lhs: &Entry, rhs: &Entry| {
+        QueryError::new(
+            position,
+            format!(
+                "cannot apply '{}' to {} and {}",
+                op.symbol(),
+                lhs.type_name(),
+                rhs.type_name()
+            ),
+        )
+    };
+    match op {
+        BinaryOp::Equal => Ok(Entry::Bool(lhs.loosely_eq(&rhs))),
+        BinaryOp::NotEqual => Ok(Entry::Bool(!lhs.loosely_eq(&rhs))),
+        BinaryOp::Contains => match lhs.contains(&rhs) {
+            Some(found) => Ok(Entry::Bool(found)),
+            None => Err(mismatch(&lhs, &rhs)),
+        },
+        BinaryOp::In => match rhs.contains(&lhs) {
+            Some(found) => Ok(Entry::Bool(found)),
+            None => Err(mismatch(&lhs, &rhs)),
+        },
+        BinaryOp::Less | BinaryOp::LessEqual | BinaryOp::Greater | BinaryOp::GreaterEqual => {
+            let ordering = match (&lhs, &rhs) {
+                // missing values never satisfy an ordering
+                (Entry::None, _) | (_, Entry::None) => return Ok(Entry::Bool(false)),
+                (Entry::String(a), Entry::String(b)) => a.partial_cmp(b),
+                (Entry::Int(a), Entry::Int(b)) => a.partial_cmp(b),
+                _ => match (lhs.as_number(), rhs.as_number()) {
+                    (Some(a), Some(b)) => a.partial_cmp(&b),
+                    _ => return Err(mismatch(&lhs, &rhs)),
+                },
+            };
+            let Some(ordering) = ordering else {
+                return Ok(Entry::Bool(false));
+            };
+            Ok(Entry::Bool(match op {
+                BinaryOp::Less => ordering.is_lt(),
+                BinaryOp::LessEqual => ordering.is_le(),
+                BinaryOp::Greater => ordering.is_gt(),
+                _ => ordering.is_ge(),
+            }))
+        }
+        BinaryOp::Add => match (&lhs, &rhs) {
+            (Entry::String(a), b) if *b != Entry::None => Ok(Entry::String(format!("{}{}", a, b))),
+            (a, Entry::String(b)) if *a != Entry::None => Ok(Entry::String(format!("{}{}", a, b))),
+            (Entry::Int(a), Entry::Int(b)) => a
+                .checked_add(*b)
+                .map(Entry::Int)
+                .ok_or_else(|| QueryError::new(position, "integer overflow")),
+            _ => match (lhs.as_number(), rhs.as_number()) {
+                (Some(a), Some(b)) => Ok(Entry::Number(a + b)),
+                _ => Err(mismatch(&lhs, &rhs)),
+            },
+        },
+        BinaryOp::Sub | BinaryOp::Mul | BinaryOp::Div | BinaryOp::Mod => {
+            if let (Entry::Int(a), Entry::Int(b)) = (&lhs, &rhs) {
+                if let Some(result) = apply_int(op, *a, *b, position)? {
+                    return Ok(Entry::Int(result));
+                }
+            }
+            let (Some(a), Some(b)) = (lhs.as_number(), rhs.as_number()) else {
+                return Err(mismatch(&lhs, &rhs));
+            };
+            if matches!(op, BinaryOp::Div | BinaryOp::Mod) && b == 0f32 {
+                return Err(QueryError::new(position, "division by zero"));
+            }
+            Ok(Entry::Number(match op {
+                BinaryOp::Sub => a - b,
+                BinaryOp::Mul => a * b,
+                BinaryOp::Div => a / b,
+                _ => a % b,
+            }))
+        }
+        BinaryOp::And | BinaryOp::Or => unreachable!("logic operators are short circuited"),
+    }
+}
+
+/// Arithmetic on two ints. Returns `None` for a division that leaves a fraction, which is done on floats instead
+fn apply_int(op: BinaryOp, a: i64, b: i64, position: usize) -> Result<Option<i64>, QueryError> {
+    if matches!(op, BinaryOp::Div | BinaryOp::Mod) && b == 0 {
+        return Err(QueryError::new(position, "division by zero"));
+    }
+    let result = match op {
+        BinaryOp::Sub => a.checked_sub(b),
+        BinaryOp::Mul => a.checked_mul(b),
+        // i64::MIN / -1 overflows, and so does the remainder used to check for a fraction
+        BinaryOp::Div => match a.checked_rem(b) {
+            Some(0) => a.checked_div(b),
+            Some(_) => return Ok(None),
+            None => None,
+        },
+        _ => a.checked_rem(b),
+    };
+    match result {
+        Some(result) => Ok(Some(result)),
+        None => Err(QueryError::new(position, "integer overflow")),
+    }
+}
+
+fn tokenize(code: &str) -> Result<Vec<(Token, usize)>, QueryError> {
+    let chars: Vec<char> = code.chars().collect();
+    let mut tokens = Vec::new();
+    let mut index = 0;
+    while index < chars.len() {
+        let start = index;
+        let c = chars[index];
+        let next = chars.get(index + 1).copied();
+        if c.is_whitespace() {
+            index += 1;
+            continue;
+        }
+        let token = match (c, next) {
+            ('(', _) => Token::LParen,
+            (')', _) => Token::RParen,
+            ('=', Some('=')) => Token::Op(BinaryOp::Equal),
+            ('!', Some('=')) => Token::Op(BinaryOp::NotEqual),
+            ('<', Some('=')) => Token::Op(BinaryOp::LessEqual),
+            ('>', Some('=')) => Token::Op(BinaryOp::GreaterEqual),
+            ('&', Some('&')) => Token::Op(BinaryOp::And),
+            ('|', Some('|')) => Token::Op(BinaryOp::Or),
+            ('<', _) => Token::Op(BinaryOp::Less),
+            ('>', _) => Token::Op(BinaryOp::Greater),
+            ('!', _) => Token::Not,
+            ('+', _) => Token::Op(BinaryOp::Add),
+            ('-', _) => Token::Op(BinaryOp::Sub),
+            ('*', _) => Token::Op(BinaryOp::Mul),
+            ('/', _) => Token::Op(BinaryOp::Div),
+            ('%', _) => Token::Op(BinaryOp::Mod),
+            ('"', _) | ('\'', _) => {
+                let (text, end) = read_string(&chars, index)?;
+                index = end;
+                tokens.push((Token::String(text), start));
+                continue;
+            }
+            (c, _)
+                if c.is_ascii_digit() || (c == '.' && next.is_some_and(|n| n.is_ascii_digit())) =>
+            {
+                while index < chars.len() && (chars[index].is_ascii_digit() || chars[index] == '.')
+                {
+                    index += 1;
+                }
+                let literal: String = chars[start..index].iter().collect();
+                let token = match (literal.parse::<i64>(), literal.parse::<f32>()) {
+                    (Ok(value), _) => Token::Int(value),
+                    (_, Ok(value)) => Token::Number(value),
+                    _ => {
+                        return Err(QueryError::new(
+                            start,
+                            format!("invalid number '{}'", literal),
+                        ))
+                    }
+                };
+                tokens.push((token, start));
+                continue;
+            }
+            (c, _) if is_ident_char(c) => {
+                while index < chars.len()
+                    && (is_ident_char(chars[index])
+                        || (chars[index] == '/' && is_project_path(&chars[start..index])))
+                {
+                    index += 1;
+                }
+                let word: String = chars[start..index].iter().collect();
+                let token = match word.as_str() {
+                    "and" => Token::Op(BinaryOp::And),
+                    "or" => Token::Op(BinaryOp::Or),
+                    "not" => Token::Not,
+                    "contains" => Token::Op(BinaryOp::Contains),
+                    "in" => Token::Op(BinaryOp::In),
+                    _ => Token::Ident(word),
+                };
+                tokens.push((token, start));
+                continue;
+            }
+            (c, _) => {
+                return Err(QueryError::new(
+                    start,
+                    format!("unexpected character '{}'", c),
+                ));
+            }
+        };
+        // all remaining tokens are one or two characters long
+        index += match token {
+            Token::Op(BinaryOp::Equal)
+            | Token::Op(BinaryOp::NotEqual)
+            | Token::Op(BinaryOp::LessEqual)
+            | Token::Op(BinaryOp::GreaterEqual)
+            | Token::Op(BinaryOp::And)
+            | Token::Op(BinaryOp::Or) => 2,
+            _ => 1,
+        };
+        tokens.push((token, start));
+    }
+    Ok(tokens)
+}
+
+fn is_ident_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_' || c == '.'
+}
+
+/// project settings paths contain slashes, so `project.` keys read them as part of the key
+fn is_project_path(word: &[char]) -> bool {
+    word.iter()
+        .copied()
+        .take(Blackboard::PROJECT_PREFIX.len())
+        .eq(Blackboard::PROJECT_PREFIX.chars())
+}
+
+/// reads a quoted string starting at `start`, returning the unescaped text and the index after the closing quote
+fn read_string(chars: &[char], start: usize) -> Result<(String, usize), QueryError> {
+    let quote = chars[start];
+    let mut buffer = String::new();
+    let mut index = start + 1;
+    while index < chars.len() {
+        match chars[index] {
+            '\\' => {
+                let Some(escaped) = chars.get(index + 1) else {
+                    break;
+                };
+                buffer.push(match escaped {
+                    'n' => '\n',
+                    't' => '\t',
+                    other => *other,
+                });
+                index += 2;
+            }
+            c if c == quote => return Ok((buffer, index + 1)),
+            c => {
+                buffer.push(c);
+                index += 1;
+            }
+        }
+    }
+    Err(QueryError::new(start, "unterminated string"))
+}
+
+struct Parser {
+    tokens: Vec<(Token, usize)>,
+    index: usize,
+    /// the length of the expression, used to report errors at the end of input
+    end: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&(Token, usize)> {
+        self.tokens.get(self.index)
+    }
+
+    fn advance(&mut self) -> Option<(Token, usize)> {
+        let token = self.tokens.get(self.index).cloned();
+        self.index += 1;
+        token
+    }
+
+    fn peek_op(&self, ops: &[BinaryOp]) -> Option<(BinaryOp, usize)> {
+        match self.peek() {
+            Some((Token::Op(op), position)) if ops.contains(op) => Some((*op, *position)),
+            _ => None,
+        }
+    }
+
+    fn binary_level(
+        &mut self,
+        ops: &[BinaryOp],
+        next: fn(&mut Self) -> Result<Expression, QueryError>,
+    ) -> Result<Expression, QueryError> {
+        let mut lhs = next(self)?;
+        while let Some((op, position)) = self.peek_op(ops) {
+            self.index += 1;
+            let rhs = next(self)?;
+            lhs = Expression::Binary {
+                op,
+                lhs: Box::new(lhs),
+                rhs: Box::new(rhs),
+                position,
+            };
+        }
+        Ok(lhs)
+    }
+
+    fn parse_or(&mut self) -> Result<Expression, QueryError> {
+        self.binary_level(&[BinaryOp::Or], Self::parse_and)
+    }
+
+    fn parse_and(&mut self) -> Result<Expression, QueryError> {
+        self.binary_level(&[BinaryOp::And], Self::parse_not)
+    }
+
+    fn parse_not(&mut self) -> Result<Expression, QueryError> {
+        if let Some((Token::Not, _)) = self.peek() {
+            self.index += 1;
+            return Ok(Expression::Not(Box::new(self.parse_not()?)));
+        }
+        self.parse_comparison()
+    }
+
+    fn parse_comparison(&mut self) -> Result<Expression, QueryError> {
+        let lhs = self.parse_additive()?;
+        let Some((op, position)) = self.peek_op(&[
+            BinaryOp::Equal,
+            BinaryOp::NotEqual,
+            BinaryOp::Less,
+            BinaryOp::LessEqual,
+            BinaryOp::Greater,
+            BinaryOp::GreaterEqual,
+            BinaryOp::Contains,
+            BinaryOp::In,
+        ]) else {
+            return Ok(lhs);
+        };
+        self.index += 1;
+        let rhs = self.parse_additive()?;
+        if let Some((_, position)) = self.peek_op(&[
+            BinaryOp::Equal,
+            BinaryOp::NotEqual,
+            BinaryOp::Less,
+            BinaryOp::LessEqual,
+            BinaryOp::Greater,
+            BinaryOp::GreaterEqual,
+            BinaryOp::Contains,
+            BinaryOp::In,
+        ]) {
+            return Err(QueryError::new(
+                position,
+                "comparisons cannot be chained, use 'and' or parentheses",
+            ));
+        }
+        Ok(Expression::Binary {
+            op,
+            lhs: Box::new(lhs),
+            rhs: Box::new(rhs),
+            position,
+        })
+    }
+
+    fn parse_additive(&mut self) -> Result<Expression, QueryError> {
+        self.binary_level(&[BinaryOp::Add, BinaryOp::Sub], Self::parse_multiplicative)
+    }
+
+    fn parse_multiplicative(&mut self) -> Result<Expression, QueryError> {
+        self.binary_level(
+            &[BinaryOp::Mul, BinaryOp::Div, BinaryOp::Mod],
+            Self::parse_unary,
+        )
+    }
+
+    fn parse_unary(&mut self) -> Result<Expression, QueryError> {
+        if let Some((BinaryOp::Sub, position)) = self.peek_op(&[BinaryOp::Sub]) {
+            self.index += 1;
+            return Ok(Expression::Negate {
+                value: Box::new(self.parse_unary()?),
+                position,
+            });
+        }
+        self.parse_primary()
+    }
+
+    fn parse_primary(&mut self) -> Result<Expression, QueryError> {
+        let Some((token, position)) = self.advance() else {
+            return Err(QueryError::new(
+                self.end,
+                "expected a value but found the end of the expression",
+            ));
+        };
+        match token {
+            Token::Number(value) => Ok(Expression::Literal(Entry::Number(value))),
+            Token::Int(value) => Ok(Expression::Literal(Entry::Int(value))),
+            Token::Ident(name)
+                if name == "len" && matches!(self.peek(), Some((Token::LParen, _))) =>
+            {
+                self.index += 1;
+                let value = self.parse_or()?;
+                match self.advance() {
+                    Some((Token::RParen, _)) => Ok(Expression::Len {
+                        value: Box::new(value),
+                        position,
+                    }),
+                    Some((_, position)) => Err(QueryError::new(position, "expected ')'")),
+                    None => Err(QueryError::new(
+                        self.end,
+                        "missing ')' before the end of the expression",
+                    )),
+                }
+            }
+            Token::String(value) => Ok(Expression::Literal(Entry::String(value))),
+            Token::Ident(name) => Ok(match name.as_str() {
+                "true" => Expression::Literal(Entry::Bool(true)),
+                "false" => Expression::Literal(Entry::Bool(false)),
+                "nil" | "null" => Expression::Literal(Entry::None),
+                _ => Expression::Variable { name, position },
+            }),
+            Token::LParen => {
+                let inner = self.parse_or()?;
+                match self.advance() {
+                    Some((Token::RParen, _)) => Ok(inner),
+                    Some((_, position)) => Err(QueryError::new(position, "expected ')'")),
+                    None => Err(QueryError::new(
+                        self.end,
+                        "missing ')' before the end of the expression",
+                    )),
+                }
+            }
+            Token::RParen => Err(QueryError::new(position, "unexpected ')'")),
+            Token::Op(op) => Err(QueryError::new(
+                position,
+                format!("expected a value but found '{}'", op.symbol()),
+            )),
+            Token::Not => Err(QueryError::new(position, "unexpected 'not'")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn eval(code: &str) -> Result<Entry, QueryError> {
+        let resolve = |name: &str| match name {
+            "gold" => Some(Entry::Int(7)),
+            "price" => Some(Entry::Number(2.5)),
+            "name" => Some(Entry::String("Mara".to_string())),
+            "met_captain" => Some(Entry::Bool(true)),
+            _ => None,
+        };
+        Expression::parse(code)?.evaluate(&resolve)
+    }
+
+    #[test]
+    fn arithmetic_follows_precedence() {
+        assert_eq!(eval("1 + 2 * 3"), Ok(Entry::Int(7)));
+        assert_eq!(eval("10 - 4 - 3"), Ok(Entry::Int(3)));
+        assert_eq!(eval("-2 * 3 + 10 % 4"), Ok(Entry::Int(-4)));
+        assert_eq!(eval("gold + 1 >= 8"), Ok(Entry::Bool(true)));
+        assert_eq!(eval("7 / 2"), Ok(Entry::Number(3.5)));
+    }
+
+    #[test]
+    fn parentheses_group() {
+        assert_eq!(eval("(1 + 2) * 3"), Ok(Entry::Int(9)));
+        assert_eq!(eval("-(gold - 10)"), Ok(Entry::Int(3)));
+        assert_eq!(eval("((price))"), Ok(Entry::Number(2.5)));
+    }
+
+    #[test]
+    fn compares_strings_and_numbers() {
+        assert_eq!(eval("name == \"Mara\""), Ok(Entry::Bool(true)));
+        assert_eq!(eval("name != 'Kai'"), Ok(Entry::Bool(true)));
+        assert_eq!(eval("\"apple\" < \"banana\""), Ok(Entry::Bool(true)));
+        assert_eq!(eval("gold > price"), Ok(Entry::Bool(true)));
+        assert_eq!(eval("gold == 7.0"), Ok(Entry::Bool(true)));
+        // a string never equals a number, and they can't be ordered
+        assert_eq!(eval("\"7\" == gold"), Ok(Entry::Bool(false)));
+        assert!(eval("name < 5").is_err());
+        // missing values never satisfy an ordering
+        assert_eq!(eval("missing > 0"), Ok(Entry::Bool(false)));
+    }
+
+    #[test]
+    fn logic_operators_bind_looser_than_comparisons() {
+        assert_eq!(eval("not met_captain or gold > 5"), Ok(Entry::Bool(true)));
+        assert_eq!(
+            eval("not (met_captain or gold > 5)"),
+            Ok(Entry::Bool(false))
+        );
+        assert_eq!(
+            eval("gold > 5 and price < 2 or name == \"Mara\""),
+            Ok(Entry::Bool(true))
+        );
+        assert_eq!(
+            eval("gold > 5 and (price < 2 or missing)"),
+            Ok(Entry::Bool(false))
+        );
+        assert_eq!(
+            eval("!met_captain || gold == 7 && true"),
+            Ok(Entry::Bool(true))
+        );
+        // the right hand side is not evaluated once the result is known
+        assert_eq!(eval("false and 1 / 0"), Ok(Entry::Bool(false)));
+    }
+
+    #[test]
+    fn integer_overflow_is_an_error() {
+        let min = "(-9223372036854775807 - 1)";
+        assert_eq!(eval(&format!("{} + 0", min)), Ok(Entry::Int(i64::MIN)));
+        for code in [
+            format!("{} / -1", min),
+            format!("{} % -1", min),
+            format!("{} - 1", min),
+            format!("{} * -1", min),
+            "9223372036854775807 + 1".to_string(),
+        ] {
+            assert_eq!(
+                eval(&code).map_err(|err| err.message),
+                Err("integer overflow".to_string()),
+                "{}",
+                code
+            );
+        }
+        assert_eq!(eval(&format!("{} / 2", min)), Ok(Entry::Int(i64::MIN / 2)));
+    }
+
+    #[test]
+    fn malformed_expressions_are_errors() {
+        for code in [
+            "",
+            "gold >",
+            "(gold > 5",
+            "gold > 5)",
+            "1 < 2 < 3",
+            "\"unterminated",
+            "gold # 5",
+            "not",
+            "len(gold",
+            "and gold",
+        ] {
+            assert!(
+                Expression::parse(code).is_err(),
+                "{:?} should not parse",
+                code
+            );
+        }
+        assert_eq!(
+            Expression::parse("gold >").unwrap_err().position,
+            "gold >".len()
+        );
+        assert!(eval("1 / 0").is_err());
+        assert!(eval("-name").is_err());
+    }
+}
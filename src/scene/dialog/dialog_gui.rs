@@ -1,46 +1,70 @@
-use std::collections::VecDeque;
-
 use godot::{
     engine::{
         control::{LayoutPreset, SizeFlags},
         object::ConnectFlags,
+        texture_rect::{ExpandMode, StretchMode},
         tween::{EaseType, TransitionType},
-        Button, CanvasLayer, Control, HSeparator, ICanvasLayer, InputEvent, Label, MarginContainer,
-        PanelContainer, RichTextLabel, Tween, VBoxContainer,
+        AudioStream, AudioStreamPlayer, Button, CanvasLayer, Control, HBoxContainer, HSeparator,
+        ICanvasLayer, InputEvent, Label, LabelSettings, MarginContainer, PanelContainer,
+        ProgressBar, ResourceLoader, RichTextLabel, TextureRect, Tween, VBoxContainer,
     },
     obj::EngineEnum,
     prelude::*,
 };
 
-use crate::{scene::game_globals::Sqore, util::SqoreUtil};
+use crate::util::SqoreUtil;
 
 use super::{
     core_dialog::SqoreDialog,
     dialog_events::DialogEvents,
+    dialog_history::{DialogHistoryEntry, DialogHistoryPanel},
+    dialog_runner::{AvailableOption, DialogEvent},
     dialog_settings::{DialogAlign, DialogSettings, EEaseType, ETransType},
-    dialog_track::{ChoiceOptionEntry, Line},
+    dialog_track::{ChoiceTimeout, Line},
+    dialog_typewriter::TypewriterScript,
 };
 
-/// The current state of the Dialog GUI,
-#[derive(Debug, Default, PartialEq)]
-enum DialogState {
-    /// Active meaning we are actively processing nodes and pushing data to be visible
-    #[default]
-    Active,
-    /// Pending meaning we are currently waiting on an external process and don't want to create a degenerate reference to CoreDialog by forcing a push
-    Pending,
+/// The countdown of a timed choice
+struct ChoiceCountdown {
+    remaining: f64,
+    /// index of the option selected when time runs out
+    default: usize,
+    bar: Gd<ProgressBar>,
 }
 
 #[derive(GodotClass)]
 #[class(init, base=CanvasLayer)]
 pub struct DialogGUI {
     tween: Option<Gd<Tween>>,
-    pub track: Option<VecDeque<Line>>,
+    /// the root of the layout, either the default layout or the instanced `gui_scene`
+    root: Option<Gd<Control>>,
+    /// where choice buttons are placed, `None` places them in the center of the screen
+    choices_container: Option<Gd<Control>>,
+    /// the buttons of the available options with the index of their option in the choice
+    option_buttons: Vec<(usize, Gd<Button>)>,
+    choice_countdown: Option<ChoiceCountdown>,
+    /// whether the pending choice is being resolved by its timeout rather than the player
+    choice_timed_out: bool,
     character_label: Option<Gd<Label>>,
     dialog_text: Option<Gd<RichTextLabel>>,
     options_root: Option<Gd<Control>>,
+    history_panel: Option<Gd<DialogHistoryPanel>>,
+    voice_player: Option<Gd<AudioStreamPlayer>>,
+    portrait: Option<Gd<TextureRect>>,
+    /// the name label style for characters without their own
+    default_name_style: Option<Gd<LabelSettings>>,
+    /// plays the typing blip of the current character, its stream is `None` for characters without one
+    blip_player: Option<Gd<AudioStreamPlayer>>,
+    /// seconds left before auto mode advances the current line
+    auto_timer: f64,
+    /// whether the current line had been seen before, letting skip mode pass it
+    line_seen: bool,
+    /// the reveal of the current line, `None` once it is fully visible
+    typewriter: Option<TypewriterScript>,
+    reveal_elapsed: f64,
+    revealed_characters: i32,
+    next_text_event: usize,
     current_index: usize,
-    state: DialogState,
 
     base: Base<CanvasLayer>,
 }
@@ -53,9 +77,9 @@ impl ICanvasLayer for DialogGUI {
 
     fn ready(&mut self) {
         self.create_structure();
-        if let Some(line) = self.get_next_text_line() {
-            self.load_line(&line);
-        } else if self.state != DialogState::Pending {
+        if let Some((line, options)) = self.get_next_line() {
+            self.load_line(&line, &options);
+        } else {
             godot_warn!(
                 "No text nodes found in dialog track on load. Something must have gone wrong?"
             );
@@ -63,62 +87,163 @@ impl ICanvasLayer for DialogGUI {
     }
 
     fn input(&mut self, event: Gd<InputEvent>) {
+        let settings = self.get_settings();
+        if Self::is_optional_action_pressed(&event, &settings.bind().history_action) {
+            self.toggle_history_panel();
+            return;
+        }
+        if Self::is_optional_action_pressed(&event, &settings.bind().auto_action) {
+            let mut dialog = SqoreDialog::singleton();
+            let auto_mode = !dialog.bind().auto_mode;
+            dialog.bind_mut().auto_mode = auto_mode;
+            return;
+        }
+        if Self::is_optional_action_pressed(&event, &settings.bind().skip_action) {
+            let mut dialog = SqoreDialog::singleton();
+            let skip_mode = !dialog.bind().skip_mode;
+            dialog.bind_mut().skip_mode = skip_mode;
+            return;
+        }
+        if self.is_history_open() {
+            // the backlog is being read, don't progress behind it
+            return;
+        }
         if self.options_root.is_some() {
             // means there is a dialog choice being made
             return;
         }
-        let settings = self.get_settings();
         if !event.is_action_pressed(StringName::from(settings.bind().interact_action.clone())) {
             return;
         }
-        let mut progress_next_node_flag = true;
-        if let Some(tween) = &mut self.tween {
-            if tween.is_running() {
-                // forces tween to finish (should usually only run once)
-                while tween.custom_step(10f64) {}
-                self.tween = None;
-                progress_next_node_flag = false;
-            }
-        }
-        if progress_next_node_flag {
+        if !self.finish_reveal() && !self.finish_tween() {
             self.load_next_line();
         }
     }
-    fn process(&mut self, _delta: f64) {
-        if self.state != DialogState::Pending {
-            return;
-        }
-        // downtime should only be for 2-3 frames MAX! so this aaggresive polling ***shouldn't*** have a big effect on the performance??
-        self.load_next_line();
+    fn process(&mut self, delta: f64) {
+        self.process_reveal(delta);
+        self.process_choice_countdown(delta);
+        self.process_auto_modes(delta);
     }
 
     fn exit_tree(&mut self) {
         //pass
-        if let Some(event_bus) = &mut SqoreDialog::singleton().bind().get_event_bus() {
-            event_bus.emit_signal(StringName::from(DialogEvents::SIGNAL_TRACK_ENDED), &[]);
-        }
+        let gui = self.to_gd();
+        let mut dialog = SqoreDialog::singleton();
+        let mut dialog = dialog.bind_mut();
+        dialog.save_seen_lines();
+        dialog.on_gui_exited(&gui);
     }
 }
 
 #[godot_api]
 impl DialogGUI {
-    pub fn update_track(&mut self, n_track: VecDeque<Line>) {
-        self.track = Some(n_track);
+    /// The RichTextLabel that displays the dialog text. Required in custom GUI scenes
+    pub const NODE_DIALOG_TEXT: &'static str = "%DialogText";
+    /// The Label that displays the character name. Optional
+    pub const NODE_CHARACTER_NAME: &'static str = "%CharacterName";
+    /// The TextureRect that displays character portraits. Optional
+    pub const NODE_PORTRAIT: &'static str = "%Portrait";
+    /// The Control that choice buttons are added to. Optional, choices are centered on screen without it
+    pub const NODE_CHOICES: &'static str = "%Choices";
+    /// Called on the root of a custom GUI scene when it appears, replacing the default appear animation
+    pub const METHOD_SHOW_DIALOG: &'static str = "show_dialog";
+    /// Called on the root of a custom GUI scene when the track ends. Returns the duration of its hide animation in seconds, the GUI is freed after it
+    pub const METHOD_HIDE_DIALOG: &'static str = "hide_dialog";
+
+    /// Whether the choice buttons are currently waiting for a selection
+    pub fn is_choice_pending(&self) -> bool {
+        self.options_root.is_some()
     }
     fn create_structure(&mut self) {
+        let settings = self.get_settings();
+        let custom_root = settings
+            .bind()
+            .gui_scene
+            .clone()
+            .and_then(|scene| self.create_custom_structure(scene));
+        let mut root = match custom_root {
+            Some(root) => root,
+            None => self.create_default_structure(&settings),
+        };
+        if let Some(theme) = settings.bind().theme.clone() {
+            root.set_theme(theme);
+        }
+        self.root = Some(root.clone());
+        self.create_voice_player(&settings);
+        self.create_blip_player(&settings);
+
+        if root.has_method(Self::METHOD_SHOW_DIALOG.into()) {
+            root.call(Self::METHOD_SHOW_DIALOG.into(), &[]);
+        } else {
+            self.play_appear_animation(root, &settings);
+        }
+    }
+
+    /// Instances the `gui_scene` from the dialog settings, see [DialogGUI::NODE_DIALOG_TEXT] and the other constants for the nodes it must provide. Returns `None` if the scene does not fit the contract
+    fn create_custom_structure(&mut self, scene: Gd<PackedScene>) -> Option<Gd<Control>> {
+        let Some(node) = scene.instantiate() else {
+            godot_error!("Failed to instantiate the dialog GUI scene, using the default layout");
+            return None;
+        };
+        let mut root = match node.try_cast::<Control>() {
+            Ok(root) => root,
+            Err(mut node) => {
+                godot_error!(
+                    "The root of the dialog GUI scene must be a Control, using the default layout"
+                );
+                node.queue_free();
+                return None;
+            }
+        };
+        let Some(mut dialog_text) = root.try_get_node_as::<RichTextLabel>(Self::NODE_DIALOG_TEXT)
+        else {
+            godot_error!(
+                "The dialog GUI scene has no RichTextLabel named \"{}\", using the default layout",
+                Self::NODE_DIALOG_TEXT
+            );
+            root.queue_free();
+            return None;
+        };
+        dialog_text.set_use_bbcode(true);
+        self.dialog_text = Some(dialog_text);
+        self.character_label = root.try_get_node_as::<Label>(Self::NODE_CHARACTER_NAME);
+        // keep the style set up in the scene unless the settings provide one
+        self.default_name_style = self
+            .get_settings()
+            .bind()
+            .character_name_label_style
+            .clone()
+            .or_else(|| {
+                self.character_label
+                    .as_ref()
+                    .and_then(|label| label.get_label_settings())
+            });
+        self.portrait = root.try_get_node_as::<TextureRect>(Self::NODE_PORTRAIT);
+        self.choices_container = root.try_get_node_as::<Control>(Self::NODE_CHOICES);
+        if let Some(portrait) = &mut self.portrait {
+            portrait.set_visible(false);
+        }
+        self.base_mut().add_child(root.clone().upcast());
+        Some(root)
+    }
+
+    fn create_default_structure(&mut self, settings: &Gd<DialogSettings>) -> Gd<Control> {
         /* INTENDED LAYOUT
         CanvasLayer (self.base)
         | MarginContainer
-        | | PanelContainer
-        | | | MarginContainer
-        | | | | VBoxContainer
-        | | | | | Label (self.character_label)
-        | | | | | HSeperator
-        | | | | | RichTextLabel (self.dialog_text)
+        | | HBoxContainer
+        | | | TextureRect (self.portrait)
+        | | | PanelContainer
+        | | | | MarginContainer
+        | | | | | VBoxContainer
+        | | | | | | Label (self.character_label)
+        | | | | | | HSeperator
+        | | | | | | RichTextLabel (self.dialog_text)
         */
-        // load settings
-        let settings = self.get_settings(); // create instances.
+        // create instances.
         let mut margin = MarginContainer::new_alloc();
+        let mut hbox = HBoxContainer::new_alloc();
+        let mut portrait = TextureRect::new_alloc();
         let mut panel = PanelContainer::new_alloc();
         let mut panel_margin = MarginContainer::new_alloc();
         let mut vbox = VBoxContainer::new_alloc();
@@ -132,10 +257,20 @@ impl DialogGUI {
         vbox.add_child(rich_text.clone().upcast());
         panel_margin.add_child(vbox.upcast());
         panel.add_child(panel_margin.clone().upcast());
-        margin.add_child(panel.clone().upcast());
+        hbox.add_child(portrait.clone().upcast());
+        hbox.add_child(panel.clone().upcast());
+        margin.add_child(hbox.upcast());
         self.base_mut().add_child(margin.clone().upcast());
         self.character_label = Some(label.clone());
         self.dialog_text = Some(rich_text.clone());
+        self.portrait = Some(portrait.clone());
+        self.default_name_style = settings.bind().character_name_label_style.clone();
+
+        // the portrait keeps its aspect ratio, sized by the height of the panel
+        portrait.set_expand_mode(ExpandMode::FIT_WIDTH_PROPORTIONAL);
+        portrait.set_stretch_mode(StretchMode::KEEP_ASPECT_CENTERED);
+        portrait.set_visible(false);
+        panel.set_h_size_flags(SizeFlags::EXPAND_FILL);
 
         // layout
         if let Some(label_settings) = &settings.bind().character_name_label_style {
@@ -171,21 +306,21 @@ impl DialogGUI {
         margin.add_theme_constant_override(StringName::from("margin_bottom"), BOTTOM_MARGIN);
         margin.set_anchors_and_offsets_preset(LayoutPreset::BOTTOM_WIDE);
         margin.force_update_transform();
-        /*
-        	*/
-        // Animation
-        let margin_size = margin.get_size();
-        let margin_pos = margin.get_position();
-        margin.set_position(Vector2 {
-            x: margin_pos.x,
-            y: margin_pos.y + margin_size.y,
+        margin.upcast()
+    }
+
+    /// slides `root` up from below its position
+    fn play_appear_animation(&mut self, mut root: Gd<Control>, settings: &Gd<DialogSettings>) {
+        let root_size = root.get_size();
+        let root_pos = root.get_position();
+        root.set_position(Vector2 {
+            x: root_pos.x,
+            y: root_pos.y + root_size.y,
         });
         // Handle Tween Creation
         let Some(tween) = &mut self.to_gd().create_tween() else {
             return;
         };
-        //         Some(EaseType::from_ord(ease.get_property())),
-        // Some(TransitionType::from_ord(trans.get_property())),
 
         let mut tween = tween
             .set_trans(TransitionType::from_ord(
@@ -208,39 +343,65 @@ impl DialogGUI {
             ))
             .unwrap();
         tween.tween_property(
-            margin.upcast(),
+            root.upcast(),
             NodePath::from("position:y"),
-            margin_pos.y.to_variant(),
+            root_pos.y.to_variant(),
             settings.bind().anim_appear_duration as f64,
         );
     }
-    /*
-        pub struct ChoiceOptionEntry {
-            text: String,
-            requires: String,
-            action: String,
-        }
-    */
-    fn create_options(&mut self, choices: &[ChoiceOptionEntry]) {
+    fn create_voice_player(&mut self, settings: &Gd<DialogSettings>) {
+        let mut player = AudioStreamPlayer::new_alloc();
+        player.set_bus(settings.bind().voice_bus.clone());
+        player
+            .connect_ex(
+                "finished".into(),
+                Callable::from_object_method(&self.to_gd(), "on_voice_finished"),
+            )
+            .flags(ConnectFlags::DEFERRED.ord() as u32)
+            .done();
+        self.base_mut().add_child(player.clone().upcast());
+        self.voice_player = Some(player);
+    }
+
+    fn create_blip_player(&mut self, settings: &Gd<DialogSettings>) {
+        let mut player = AudioStreamPlayer::new_alloc();
+        player.set_bus(settings.bind().voice_bus.clone());
+        self.base_mut().add_child(player.clone().upcast());
+        self.blip_player = Some(player);
+    }
+
+    fn create_options(&mut self, options: &[AvailableOption], timeout: Option<ChoiceTimeout>) {
         let mut root = VBoxContainer::new_alloc();
-        self.to_gd().add_child(root.clone().upcast());
-        self.options_root = Some(root.clone().upcast());
-        let mut is_first = self.get_settings().bind().auto_focus_choice_buttons;
-        for (index, option) in choices.iter().enumerate() {
-            if !option.requires.is_empty()
-                && !SqoreDialog::singleton()
-                    .bind_mut()
-                    .blackboard_query(option.requires.clone().into())
-            {
-                // does not meet conditions
-                continue;
+        let use_container = self.choices_container.is_some();
+        match self.choices_container.clone() {
+            Some(mut container) => container.add_child(root.clone().upcast()),
+            None => {
+                if let Some(theme) = self.get_settings().bind().theme.clone() {
+                    root.set_theme(theme);
+                }
+                self.to_gd().add_child(root.clone().upcast());
             }
+        }
+        self.options_root = Some(root.clone().upcast());
+        self.option_buttons.clear();
+        self.choice_timed_out = false;
+        let settings = self.get_settings();
+        let mut is_first = settings.bind().auto_focus_choice_buttons;
+        let mut option_texts = PackedStringArray::new();
+        for (position, available) in options.iter().enumerate() {
+            let (index, option) = (available.index, &available.option);
             let mut button = Button::new_alloc();
             root.add_child(button.clone().upcast());
-            button.set_text(self.parse_text(&option.text).into());
-            let action = option.action.clone();
-            if !action.is_empty() {
-                self.state = DialogState::Pending;
+            self.option_buttons.push((index, button.clone()));
+            let option_text = self.parse_text_with_id(&option.id, &option.text);
+            button.set_text(option_text.clone().into());
+            option_texts.push(option_text.to_godot());
+            if available.chosen {
+                let variation = settings.bind().chosen_option_theme_variation.clone();
+                if !variation.is_empty() {
+                    button.set_theme_type_variation(StringName::from(variation));
+                }
+                button.set_modulate(settings.bind().chosen_option_modulate);
             }
             button
                 .connect_ex(
@@ -248,10 +409,21 @@ impl DialogGUI {
                     Callable::from_fn(
                         format!("choice_button_{} ({})", index, option.text),
                         move |_| {
-                            SqoreDialog::singleton()
+                            let mut dialog = SqoreDialog::singleton();
+                            if dialog.bind_mut().choose_option(position).is_none() {
+                                return Err(());
+                            }
+                            dialog.bind_mut().emit_event(
+                                DialogEvents::SIGNAL_CHOICE_MADE,
+                                &[(index as i64).to_variant(), option_text.to_variant()],
+                            );
+                            dialog
                                 .bind_mut()
-                                .blackboard_action(action.clone().into());
-                            let Some(gui) = &mut SqoreDialog::singleton().bind().gui.clone() else {
+                                .record_history(DialogHistoryEntry::Choice {
+                                    index,
+                                    text: option_text.clone(),
+                                });
+                            let Some(gui) = &mut dialog.bind().gui.clone() else {
                                 godot_error!(
                                     "Failed to find instance of the CoreDialog's DialogGUI"
                                 );
@@ -269,6 +441,32 @@ impl DialogGUI {
                 is_first = false;
             }
         }
+        SqoreDialog::singleton().bind_mut().emit_event(
+            DialogEvents::SIGNAL_CHOICES_PRESENTED,
+            &[option_texts.to_variant()],
+        );
+        // a choice resumed from a snapshot keeps counting down from where it was
+        let time_left = SqoreDialog::singleton()
+            .bind_mut()
+            .take_resumed_choice_time_left();
+        self.choice_countdown = timeout.map(|timeout| {
+            let remaining = time_left.map_or(timeout.seconds(), |time_left| {
+                time_left.clamp(0.0, timeout.seconds())
+            });
+            let mut bar = ProgressBar::new_alloc();
+            bar.set_max(timeout.seconds());
+            bar.set_value(remaining);
+            bar.set_show_percentage(false);
+            root.add_child(bar.clone().upcast());
+            ChoiceCountdown {
+                remaining,
+                default: timeout.default,
+                bar,
+            }
+        });
+        if use_container {
+            return;
+        }
         let align = self.get_settings().bind().choice_buttons_align.clone();
         root.set_anchors_and_offsets_preset(match align {
             DialogAlign::Left => LayoutPreset::CENTER_LEFT,
@@ -278,117 +476,144 @@ impl DialogGUI {
         });
     }
 
-    pub fn load_line(&mut self, track: &Line) {
-        match track {
-            #[allow(unused_variables)]
+    /// Displays a text or choice line. `options` are the options of a choice that can be chosen
+    pub fn load_line(&mut self, track: &Line, options: &[AvailableOption]) {
+        self.stop_voice();
+        self.line_seen = SqoreDialog::singleton().bind_mut().mark_line_seen(track);
+        let (parsed_char, parsed_text) = match track {
             Line::Text {
                 text,
                 character,
-                requires,
+                id,
+                voice,
+                ..
             } => {
-                let parsed_text = self.parse_text(text);
-                let parsed_char = self.parse_text(character);
-
-                if let Some(dialog_text) = self.dialog_text.as_mut() {
-                    dialog_text.set_text(parsed_text.to_godot());
-                }
-
-                if let Some(character_label) = &mut self.character_label {
-                    character_label.set_text(parsed_char.to_godot());
-                }
+                self.play_voice(voice);
+                (
+                    self.show_character(character),
+                    self.parse_text_with_id(id, text),
+                )
             }
             Line::Choice {
                 prompt,
                 character,
-                options,
+                id,
+                voice,
+                timeout,
+                ..
             } => {
-                let parsed_prompt = self.parse_text(prompt);
-                let parsed_char = self.parse_text(character);
-                if let Some(dialog_text) = &mut self.dialog_text {
-                    dialog_text.set_text(parsed_prompt.to_godot());
-                }
-                if let Some(character_label) = &mut self.character_label {
-                    character_label.set_text(parsed_char.to_godot());
-                }
-                self.create_options(options);
+                self.play_voice(voice);
+                self.create_options(options, *timeout);
+                (
+                    self.show_character(character),
+                    self.parse_text_with_id(id, prompt),
+                )
             }
             _ => {
                 godot_warn!("DialogGUI does not handle Line of type: {:#?}", track);
+                return;
             }
+        };
+        let settings = self.get_settings();
+        let script = TypewriterScript::parse(&parsed_text, settings.bind().words_per_minute);
+        for warning in script.warnings.iter() {
+            godot_warn!("{}", warning);
         }
-        let wpm = self.get_settings().bind().words_per_minute;
-        let mut tween = self.get_text_tween(EEaseType::InOut, ETransType::Linear);
-        let Some(text) = &mut self.dialog_text else {
+        Self::record_line(&parsed_char, &script.text);
+        let mut dialog = SqoreDialog::singleton();
+        let index = dialog.bind().current_line_index() as i64;
+        dialog.bind_mut().emit_event(
+            DialogEvents::SIGNAL_LINE_STARTED,
+            &[
+                parsed_char.to_variant(),
+                script.text.to_variant(),
+                index.to_variant(),
+            ],
+        );
+        if let Some(dialog_text) = &mut self.dialog_text {
+            dialog_text.set_text(script.text.to_godot());
+            dialog_text.set_visible_characters(0);
+        }
+        self.auto_timer = (settings.bind().auto_delay_base
+            + settings.bind().auto_delay_per_word * script.words as f32)
+            as f64;
+        self.reveal_elapsed = 0.0;
+        self.revealed_characters = 0;
+        self.next_text_event = 0;
+        self.typewriter = Some(script);
+    }
+
+    /// advances the typewriter reveal of the current line, firing any `[emit]` tags that were passed
+    fn process_reveal(&mut self, delta: f64) {
+        if self.typewriter.is_none() {
+            return;
+        }
+        self.reveal_elapsed += delta;
+        self.update_reveal(self.reveal_elapsed);
+    }
+
+    /// reveals the text up to `time`, ending the reveal once everything is visible
+    fn update_reveal(&mut self, time: f64) {
+        let Some(script) = &self.typewriter else {
             return;
         };
+        let mut emitted = Vec::new();
+        while let Some(event) = script.events.get(self.next_text_event) {
+            if event.time > time {
+                break;
+            }
+            emitted.push(event.name.clone());
+            self.next_text_event += 1;
+        }
+        let finished = time >= script.duration();
+        let visible = script.visible_characters_at(time) as i32;
+        if visible > self.revealed_characters && !finished {
+            self.play_blip();
+        }
+        self.revealed_characters = visible;
+        if let Some(dialog_text) = &mut self.dialog_text {
+            // -1 shows every character, also those of custom effect tags the count doesn't know
+            dialog_text.set_visible_characters(if finished { -1 } else { visible });
+        }
+        if finished {
+            self.typewriter = None;
+        }
+        for name in emitted {
+            Self::emit_text_event(name);
+        }
+        if finished {
+            SqoreDialog::singleton()
+                .bind_mut()
+                .emit_event(DialogEvents::SIGNAL_LINE_REVEALED, &[]);
+        }
+    }
 
-        const ZERO_TEXT: f32 = 0f32;
-        const FULL_TEXT: f32 = 1f32;
-        const MINUTES_TO_SECONDS: f32 = 60.0;
+    /// reveals the rest of the current line at once, returning whether it was still being revealed
+    fn finish_reveal(&mut self) -> bool {
+        if self.typewriter.is_none() {
+            return false;
+        }
+        self.update_reveal(f64::INFINITY);
+        true
+    }
 
-        let words = text
-            .get_text()
-            .clone()
-            .to_string()
-            .split_whitespace()
-            .count();
-        let duration_seconds = (words as f32) * wpm.powi(-1) * MINUTES_TO_SECONDS;
-        text.set_visible_ratio(ZERO_TEXT);
-        tween.tween_property(
-            text.clone().upcast(),
-            NodePath::from("visible_ratio"),
-            FULL_TEXT.to_variant(),
-            duration_seconds as f64,
-        );
+    fn emit_text_event(name: String) {
+        SqoreDialog::singleton()
+            .bind_mut()
+            .emit_event(DialogEvents::SIGNAL_TEXT_EMITTED, &[name.to_variant()]);
     }
 
-    fn get_next_text_line(&mut self) -> Option<Line> {
-        let Some(track) = &mut self.track else {
-            return None;
-        };
-        #[allow(unused_variables)]
-        while let Some(line) = track.pop_front() {
-            let result: Option<Line> = match line.clone() {
-                Line::Text {
-                    text,
-                    character,
-                    requires,
-                } => {
-                    if requires.is_empty()
-                        || SqoreDialog::singleton()
-                            .bind_mut()
-                            .blackboard_query(requires.to_godot())
-                    {
-                        Some(line)
-                    } else {
-                        None
-                    }
-                }
-                Line::Choice {
-                    prompt,
-                    character,
-                    options,
-                } => Some(line),
-                Line::Action { action } => {
-                    self.state = DialogState::Pending;
-                    godot_print!("Pending processing for action {}", action);
-                    SqoreDialog::singleton()
-                        .call_deferred("blackboard_action".into(), &[action.to_variant()]);
-                    return None; // force break to allow processing events
-                }
-                Line::Signal { name, args } => {
-                    SqoreDialog::singleton()
-                        .bind_mut()
-                        .handle_dialog_signal(&line);
-                    continue;
-                }
-                Line::None => continue,
-            };
-            if result.is_some() {
-                return result;
+    /// takes the next line to display from the dialog runner, along with the options that can be chosen if it is a choice
+    fn get_next_line(&mut self) -> Option<(Line, Vec<AvailableOption>)> {
+        loop {
+            let event = SqoreDialog::singleton().bind_mut().next_dialog_event();
+            match event {
+                DialogEvent::Text(line) => return Some((line, Vec::new())),
+                DialogEvent::Choice { line, options } => return Some((line, options)),
+                DialogEvent::Commands(calls) => SqoreDialog::run_commands(calls),
+                _ => return None,
             }
         }
-        None
     }
 
     fn get_text_tween(&mut self, ease: EEaseType, trans: ETransType) -> Gd<Tween> {
@@ -410,81 +635,329 @@ impl DialogGUI {
     }
 
     fn get_settings(&self) -> Gd<DialogSettings> {
-        SqoreDialog::singleton()
-            .bind()
-            .get_override_settings()
-            .unwrap_or(
-                Sqore::singleton()
-                    .bind()
-                    .get_config()
-                    .bind()
-                    .get_dialog()
-                    .unwrap_or(DialogSettings::new_gd()),
-            )
+        SqoreDialog::singleton().bind().get_settings()
     }
 
-    fn dialog_choice_was_made_callable(&mut self, _index: usize) {
+    fn dialog_choice_was_made_callable(&mut self, index: usize) {
         let Some(root) = &mut self.options_root.clone() else {
             return;
         };
         root.queue_free();
         self.options_root = None;
+        self.option_buttons.clear();
+        self.choice_countdown = None;
+        Self::emit_choice_resolved(index, self.choice_timed_out);
+        self.choice_timed_out = false;
         self.load_next_line();
     }
 
-    #[func]
-    pub fn make_dialog_choice(&mut self, index: i32) -> bool {
-        let Some(root) = self.options_root.clone() else {
-            return false;
+    /// The seconds left to pick an option of the timed choice being shown, if any
+    pub fn choice_time_left(&self) -> Option<f64> {
+        self.choice_countdown
+            .as_ref()
+            .map(|countdown| countdown.remaining.max(0.0))
+    }
+
+    fn emit_choice_resolved(index: usize, timed_out: bool) {
+        SqoreDialog::singleton().bind_mut().emit_event(
+            DialogEvents::SIGNAL_CHOICE_RESOLVED,
+            &[(index as i64).to_variant(), timed_out.to_variant()],
+        );
+    }
+
+    /// counts down a timed choice, selecting its default option when time runs out. Stops while the history is open, and like the rest of `process` while the tree is paused
+    fn process_choice_countdown(&mut self, delta: f64) {
+        if self.is_history_open() {
+            return;
+        }
+        let Some(countdown) = &mut self.choice_countdown else {
+            return;
         };
-        let Some(child) = root.get_child(index) else {
-            return false;
+        countdown.remaining -= delta;
+        countdown.bar.set_value(countdown.remaining.max(0.0));
+        if countdown.remaining > 0.0 {
+            return;
+        }
+        let default = countdown.default;
+        self.choice_countdown = None;
+        self.time_out_choice(default);
+    }
+
+    fn time_out_choice(&mut self, default: usize) {
+        let mut button = self
+            .option_buttons
+            .iter()
+            .find(|(index, _)| *index == default)
+            .map(|(_, button)| button.clone());
+        if button.is_none() {
+            // hidden by its requirement or by `once`, or out of range
+            godot_warn!(
+                "The default option {} of the timed choice is not available, selecting the first available option instead",
+                default
+            );
+            button = self
+                .option_buttons
+                .first()
+                .map(|(_, button)| button.clone());
+        }
+        let Some(mut button) = button else {
+            godot_warn!("Timed choice has no available option to select");
+            return;
         };
-        let Ok(child) = &mut child.try_cast::<Button>() else {
+        self.choice_timed_out = true;
+        for (_, option_button) in self.option_buttons.iter_mut() {
+            option_button.set_disabled(true);
+        }
+        button.emit_signal("pressed".into(), &[]);
+    }
+
+    /// Selects the option at `index` among the currently displayed options
+    #[func]
+    pub fn make_dialog_choice(&mut self, index: i32) -> bool {
+        let Some((_, button)) = usize::try_from(index)
+            .ok()
+            .and_then(|index| self.option_buttons.get(index))
+        else {
             return false;
         };
-        child.set_deferred("pressed".into(), true.to_variant());
+        button
+            .clone()
+            .set_deferred("pressed".into(), true.to_variant());
         true
     }
 
     fn load_next_line(&mut self) {
-        if self.state == DialogState::Pending {
-            return;
+        if let Some((line, options)) = self.get_next_line() {
+            self.load_line(&line, &options);
+        } else {
+            self.stop_voice();
+            // nothing left for the auto modes to advance
+            self.line_seen = false;
+            self.auto_timer = f64::INFINITY;
+            self.play_hide_animation();
         }
+    }
 
-        let Some(margin) = self.base().get_child(0) else {
-            godot_error!("Failed to access child of DialogGUI!");
+    /// hides the GUI and frees it once hidden
+    fn play_hide_animation(&mut self) {
+        let Some(mut root) = self.root.clone() else {
+            godot_error!("Failed to access the root of DialogGUI!");
+            self.base_mut().queue_free();
             return;
         };
-        let margin = margin.cast::<Control>();
-        if let Some(line) = self.get_next_text_line() {
-            self.load_line(&line);
+        let settings = self.get_settings();
+        let mut tween = self.get_text_tween(
+            settings.bind().anim_hide_ease.clone(),
+            settings.bind().anim_hide_trans.clone(),
+        );
+        if root.has_method(Self::METHOD_HIDE_DIALOG.into()) {
+            let duration = root
+                .call(Self::METHOD_HIDE_DIALOG.into(), &[])
+                .try_to::<f64>()
+                .unwrap_or(0.0);
+            tween.tween_interval(duration);
         } else {
-            let settings = self.get_settings();
-            let mut tween = self.get_text_tween(
-                settings.bind().anim_hide_ease.clone(),
-                settings.bind().anim_hide_trans.clone(),
-            );
-            let margin_size = margin.get_size();
-            let margin_pos = margin.get_position();
-
+            let root_size = root.get_size();
+            let root_pos = root.get_position();
             tween.tween_property(
-                margin.upcast(),
+                root.upcast(),
                 NodePath::from("position:y"),
-                (margin_pos.y + margin_size.y).to_variant(),
+                (root_pos.y + root_size.y).to_variant(),
                 settings.bind().get_anim_hide_duration() as f64,
             );
-            tween.tween_callback(Callable::from_object_method(&self.to_gd(), "queue_free"));
         }
+        tween.tween_callback(Callable::from_object_method(&self.to_gd(), "queue_free"));
+    }
+
+    fn play_voice(&mut self, voice: &str) {
+        if voice.is_empty() {
+            return;
+        }
+        let Some(player) = &mut self.voice_player else {
+            return;
+        };
+        // the track loader already checked that the clip exists
+        let Some(stream) = ResourceLoader::singleton()
+            .load(voice.to_godot())
+            .and_then(|res| res.try_cast::<AudioStream>().ok())
+        else {
+            godot_warn!("Voice clip \"{}\" is not an AudioStream", voice);
+            return;
+        };
+        player.set_stream(stream);
+        player.play();
+    }
+
+    fn play_blip(&mut self) {
+        let is_speaking = self
+            .voice_player
+            .as_ref()
+            .is_some_and(|player| player.is_playing());
+        let Some(player) = &mut self.blip_player else {
+            return;
+        };
+        if is_speaking || player.is_playing() || player.get_stream().is_none() {
+            return;
+        }
+        player.play();
+    }
+
+    /// Styles the name label, portrait, and typing blip for a line's `character` field, returning the name to display. Unregistered characters are displayed as written
+    fn show_character(&mut self, reference: &String) -> String {
+        let settings = self.get_settings();
+        let found = settings.bind().find_character(reference);
+        let default_style = self.default_name_style.clone();
+        let (name, label_style, name_color, portrait, blip) = match found {
+            Some((character, expression)) => {
+                let character = character.bind();
+                (
+                    self.parse_text(&character.get_name().to_string()),
+                    character.name_label_settings.clone().or(default_style),
+                    character.use_name_color.then_some(character.name_color),
+                    character.get_portrait(expression.to_godot()),
+                    character.typing_blip.clone(),
+                )
+            }
+            None => (self.parse_text(reference), default_style, None, None, None),
+        };
+        if let Some(label) = &mut self.character_label {
+            match label_style {
+                Some(style) => label.set_label_settings(style),
+                None => label.set("label_settings".into(), Variant::nil()),
+            }
+            match name_color {
+                Some(color) => label.add_theme_color_override("font_color".into(), color),
+                None => label.remove_theme_color_override("font_color".into()),
+            }
+            label.set_text(name.to_godot());
+        }
+        if let Some(portrait_rect) = &mut self.portrait {
+            portrait_rect.set_visible(portrait.is_some());
+            if let Some(texture) = portrait {
+                portrait_rect.set_texture(texture);
+            }
+        }
+        if let Some(player) = &mut self.blip_player {
+            player.stop();
+            match blip {
+                Some(stream) => player.set_stream(stream),
+                None => player.set("stream".into(), Variant::nil()),
+            }
+        }
+        name
+    }
+
+    fn stop_voice(&mut self) {
+        if let Some(player) = &mut self.voice_player {
+            player.stop();
+        }
+    }
+
+    #[func]
+    fn on_voice_finished(&mut self) {
+        if !self.get_settings().bind().voice_auto_advance
+            || self.is_choice_pending()
+            || self.is_history_open()
+        {
+            return;
+        }
+        self.finish_reveal();
+        self.load_next_line();
+    }
+
+    /// forces a running tween to finish, returning whether one was running
+    fn finish_tween(&mut self) -> bool {
+        let Some(tween) = &mut self.tween else {
+            return false;
+        };
+        if !tween.is_running() {
+            return false;
+        }
+        // forces tween to finish (should usually only run once)
+        while tween.custom_step(10f64) {}
+        self.tween = None;
+        true
+    }
+
+    fn process_auto_modes(&mut self, delta: f64) {
+        if self.is_choice_pending() || self.is_history_open() {
+            return;
+        }
+        let (auto_mode, skip_mode) = {
+            let dialog = SqoreDialog::singleton();
+            let dialog = dialog.bind();
+            (dialog.auto_mode, dialog.skip_mode)
+        };
+        if skip_mode && self.line_seen {
+            self.finish_reveal();
+            self.load_next_line();
+            return;
+        }
+        let is_revealing = self.typewriter.is_some();
+        let is_speaking = self
+            .voice_player
+            .as_ref()
+            .is_some_and(|player| player.is_playing());
+        if !auto_mode || is_revealing || is_speaking {
+            return;
+        }
+        self.auto_timer -= delta;
+        if self.auto_timer <= 0.0 {
+            self.load_next_line();
+        }
+    }
+
+    fn is_optional_action_pressed(event: &Gd<InputEvent>, action: &GString) -> bool {
+        !action.is_empty() && event.is_action_pressed(StringName::from(action))
+    }
+
+    fn record_line(character: &str, text: &str) {
+        SqoreDialog::singleton()
+            .bind_mut()
+            .record_history(DialogHistoryEntry::Line {
+                character: character.to_string(),
+                text: text.to_string(),
+            });
+    }
+
+    fn toggle_history_panel(&mut self) {
+        if let Some(panel) = &mut self.history_panel {
+            let open = !panel.is_visible();
+            panel.set_visible(open);
+            if open {
+                panel.bind_mut().refresh();
+            }
+            return;
+        }
+        // the panel loads the history when it enters the tree
+        let mut panel = DialogHistoryPanel::new_alloc();
+        if let Some(theme) = self.get_settings().bind().theme.clone() {
+            panel.set_theme(theme);
+        }
+        self.base_mut().add_child(panel.clone().upcast());
+        panel.set_anchors_and_offsets_preset(LayoutPreset::FULL_RECT);
+        self.history_panel = Some(panel);
     }
 
-    pub fn mark_event_handled(&mut self) {
-        godot_print!("Action marked as handled");
-        self.state = DialogState::Active;
+    fn is_history_open(&self) -> bool {
+        self.history_panel
+            .as_ref()
+            .is_some_and(|panel| panel.is_visible())
     }
 
     fn parse_text(&self, in_text: &String) -> String {
         let trans = self.base().tr(in_text.into()).into();
         SqoreDialog::singleton().bind().blackboard_parse(trans)
     }
+
+    /// translates by the stable id when one is set and has a translation, falling back to the text itself
+    fn parse_text_with_id(&self, id: &String, in_text: &String) -> String {
+        if !id.is_empty() {
+            let trans: String = self.base().tr(id.into()).into();
+            if trans != *id {
+                return SqoreDialog::singleton().bind().blackboard_parse(trans);
+            }
+        }
+        self.parse_text(in_text)
+    }
 }
@@ -0,0 +1,84 @@
+use godot::{
+    engine::{control::SizeFlags, IPanelContainer, PanelContainer, RichTextLabel},
+    prelude::*,
+};
+
+use super::core_dialog::SqoreDialog;
+
+/// A single entry in the dialog history, recorded by [SqoreDialog] as lines are displayed and choices are made
+#[derive(Debug, Clone, PartialEq)]
+pub enum DialogHistoryEntry {
+    Line { character: String, text: String },
+    Choice { index: usize, text: String },
+}
+
+impl DialogHistoryEntry {
+    /// Converts to a dictionary for GDScript. Lines have the keys `type` ("line"), `character` and `text`. Choices have the keys `type` ("choice"), `index` and `text`
+    pub fn to_dict(&self) -> Dictionary {
+        let mut dict = Dictionary::new();
+        match self {
+            DialogHistoryEntry::Line { character, text } => {
+                dict.set("type", "line");
+                dict.set("character", character.clone());
+                dict.set("text", text.clone());
+            }
+            DialogHistoryEntry::Choice { index, text } => {
+                dict.set("type", "choice");
+                dict.set("index", *index as i64);
+                dict.set("text", text.clone());
+            }
+        }
+        dict
+    }
+
+    fn to_bbcode(&self) -> String {
+        match self {
+            DialogHistoryEntry::Line { character, text } if character.is_empty() => text.clone(),
+            DialogHistoryEntry::Line { character, text } => {
+                format!("[b]{}[/b]: {}", character, text)
+            }
+            DialogHistoryEntry::Choice { text, .. } => format!("[i]> {}[/i]", text),
+        }
+    }
+}
+
+/// A scrollable backlog of everything recorded in the dialog history. [super::dialog_gui::DialogGUI] opens one when the `history_action` from [super::dialog_settings::DialogSettings] is pressed, but it can also be placed in any scene.
+#[derive(GodotClass)]
+#[class(init, base=PanelContainer)]
+pub struct DialogHistoryPanel {
+    text: Option<Gd<RichTextLabel>>,
+    base: Base<PanelContainer>,
+}
+
+#[godot_api]
+impl IPanelContainer for DialogHistoryPanel {
+    fn ready(&mut self) {
+        let mut text = RichTextLabel::new_alloc();
+        text.set_use_bbcode(true);
+        text.set_scroll_follow(true);
+        text.set_selection_enabled(true);
+        text.set_v_size_flags(SizeFlags::EXPAND_FILL);
+        text.set_h_size_flags(SizeFlags::EXPAND_FILL);
+        self.base_mut().add_child(text.clone().upcast());
+        self.text = Some(text);
+        self.refresh();
+    }
+}
+
+#[godot_api]
+impl DialogHistoryPanel {
+    /// Reloads the displayed text from the dialog history
+    #[func]
+    pub fn refresh(&mut self) {
+        let Some(text) = &mut self.text else {
+            return;
+        };
+        let lines: Vec<String> = SqoreDialog::singleton()
+            .bind()
+            .history
+            .iter()
+            .map(|entry| entry.to_bbcode())
+            .collect();
+        text.set_text(lines.join("\n").to_godot());
+    }
+}
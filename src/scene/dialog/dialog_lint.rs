@@ -0,0 +1,584 @@
+//! Static checks for dialog tracks, run without playing the track.
+use std::collections::HashSet;
+
+use godot::prelude::*;
+
+use super::{
+    dialog_blackboard::Blackboard,
+    dialog_expression::Expression,
+    dialog_template::Template,
+    dialog_track::{DialogError, DialogTrack, JumpTarget, Line},
+    dialog_typewriter::TypewriterScript,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+impl Severity {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct DialogDiagnostic {
+    pub severity: Severity,
+    /// the position of the node in the track, if the diagnostic relates to a single node. The top level index, followed by the nested sequence and line index for each branch or choice option the node is nested in
+    pub position: Option<Vec<usize>>,
+    pub message: String,
+}
+
+impl DialogDiagnostic {
+    fn error(position: &[usize], message: String) -> Self {
+        Self {
+            severity: Severity::Error,
+            position: Some(position.to_vec()),
+            message,
+        }
+    }
+
+    fn warning(position: &[usize], message: String) -> Self {
+        Self {
+            severity: Severity::Warning,
+            position: Some(position.to_vec()),
+            message,
+        }
+    }
+
+    /// A diagnostic for a track that failed to load at all
+    pub fn from_load_error(err: &DialogError) -> Self {
+        Self {
+            severity: Severity::Error,
+            position: None,
+            message: format!("{:?}", err),
+        }
+    }
+
+    /// The index of the top level node containing the node this diagnostic relates to
+    pub fn line(&self) -> Option<usize> {
+        self.position
+            .as_ref()
+            .and_then(|position| position.first().copied())
+    }
+
+    /// Converts to a dictionary for GDScript. Keys are `file`, `line` (the top level index, -1 for the whole track), `position` (an array of indices into nested nodes, empty for the whole track), `severity` and `message`
+    pub fn to_dict(&self, file: &str) -> Dictionary {
+        let mut dict = Dictionary::new();
+        dict.set("file", file);
+        dict.set("line", self.line().map(|line| line as i64).unwrap_or(-1));
+        dict.set(
+            "position",
+            Array::from_iter(self.position.iter().flatten().map(|index| *index as i64)),
+        );
+        dict.set("severity", self.severity.as_str());
+        dict.set("message", self.message.clone());
+        dict
+    }
+}
+
+/// The commands a track's actions are checked against
+#[derive(Debug, Clone, Default)]
+pub struct KnownCommands {
+    /// name and argument count of every registered command, see [Blackboard::command_signatures]
+    pub signatures: Vec<(String, usize)>,
+    /// names of commands the game registers itself, accepted with any number of arguments
+    pub declared: Vec<String>,
+    /// whether more commands may be registered before the track plays, as in the editor where game code has not run. Unknown commands are warnings instead of errors then
+    pub incomplete: bool,
+}
+
+impl KnownCommands {
+    pub fn new(blackboard: &Blackboard, declared: Vec<String>, incomplete: bool) -> Self {
+        Self {
+            signatures: blackboard.command_signatures(),
+            declared,
+            incomplete,
+        }
+    }
+}
+
+/// Checks a track for mistakes that would otherwise only show up while playing it. Commands are checked against `commands`
+pub fn lint_track(track: &DialogTrack, commands: &KnownCommands) -> Vec<DialogDiagnostic> {
+    let mut diagnostics = Vec::new();
+    let set_keys = collect_set_keys(track);
+    for (position, line) in track.walk() {
+        match line {
+            Line::Text {
+                text,
+                character,
+                requires,
+                ..
+            } => {
+                lint_query(&position, requires, &mut diagnostics);
+                lint_templates(&position, text, &set_keys, &mut diagnostics);
+                lint_typewriter(&position, text, &mut diagnostics);
+                lint_templates(&position, character, &set_keys, &mut diagnostics);
+            }
+            Line::Choice {
+                prompt,
+                character,
+                options,
+                ..
+            } => {
+                lint_templates(&position, prompt, &set_keys, &mut diagnostics);
+                lint_typewriter(&position, prompt, &mut diagnostics);
+                lint_templates(&position, character, &set_keys, &mut diagnostics);
+                for option in options.iter() {
+                    if option.once && option.sticky {
+                        diagnostics.push(DialogDiagnostic::warning(
+                            &position,
+                            format!(
+                                "Option \"{}\" is both once and sticky, it is hidden once chosen",
+                                option.text
+                            ),
+                        ));
+                    }
+                    lint_query(&position, &option.requires, &mut diagnostics);
+                    lint_templates(&position, &option.text, &set_keys, &mut diagnostics);
+                    lint_action(&position, &option.action, commands, track, &mut diagnostics);
+                }
+                lint_choice_reachable(&position, line, &mut diagnostics);
+            }
+            Line::Action { action } => {
+                lint_action(&position, action, commands, track, &mut diagnostics);
+            }
+            Line::Branch { branches } => {
+                for branch in branches.iter() {
+                    lint_query(&position, &branch.requires, &mut diagnostics);
+                }
+            }
+            Line::Signal { .. } | Line::Label { .. } | Line::None => (),
+        }
+    }
+    diagnostics
+}
+
+/// All keys assigned with `set`, `push` or `toggle`, or stored from a command result, anywhere in the track. Keys are kept as [Blackboard::canonical_key]
+fn collect_set_keys(track: &DialogTrack) -> HashSet<String> {
+    let mut keys = HashSet::new();
+    for (_, line) in track.walk() {
+        let codes: Vec<&String> = match line {
+            Line::Action { action } => vec![action],
+            Line::Choice { options, .. } => options.iter().map(|opt| &opt.action).collect(),
+            _ => continue,
+        };
+        for code in codes {
+            for call in Blackboard::split_commands(code) {
+                if matches!(call.name.as_str(), "set" | "push" | "toggle") && !call.args.is_empty()
+                {
+                    keys.insert(Blackboard::canonical_key(&call.args[0]).to_string());
+                }
+                if let Some(key) = &call.store {
+                    keys.insert(Blackboard::canonical_key(key).to_string());
+                }
+            }
+        }
+    }
+    keys
+}
+
+fn lint_query(position: &[usize], requires: &str, diagnostics: &mut Vec<DialogDiagnostic>) {
+    if requires.trim().is_empty() {
+        return;
+    }
+    if let Err(err) = Expression::parse(requires) {
+        diagnostics.push(DialogDiagnostic::error(
+            position,
+            format!("Malformed query \"{}\": {}", requires, err),
+        ));
+    }
+}
+
+fn lint_templates(
+    position: &[usize],
+    text: &str,
+    set_keys: &HashSet<String>,
+    diagnostics: &mut Vec<DialogDiagnostic>,
+) {
+    let template = match Template::parse(text) {
+        Ok(template) => template,
+        Err(err) => {
+            diagnostics.push(DialogDiagnostic::error(
+                position,
+                format!("Malformed template in \"{}\": {}", text, err),
+            ));
+            return;
+        }
+    };
+    for key in template.required_keys() {
+        if !set_keys.contains(Blackboard::canonical_key(key)) && !Blackboard::is_read_only_key(key)
+        {
+            diagnostics.push(DialogDiagnostic::warning(
+                position,
+                format!(
+                    "Template key \"{}\" is never set in this track. It must be set externally",
+                    key
+                ),
+            ));
+        }
+    }
+}
+
+fn lint_typewriter(position: &[usize], text: &str, diagnostics: &mut Vec<DialogDiagnostic>) {
+    for warning in TypewriterScript::parse(text, 1.0).warnings {
+        diagnostics.push(DialogDiagnostic::warning(position, warning));
+    }
+}
+
+fn lint_action(
+    position: &[usize],
+    code: &str,
+    commands: &KnownCommands,
+    track: &DialogTrack,
+    diagnostics: &mut Vec<DialogDiagnostic>,
+) {
+    for call in Blackboard::split_commands(code) {
+        let (command, args) = (&call.name, &call.args);
+        let arg_counts: Vec<usize> = commands
+            .signatures
+            .iter()
+            .filter(|(name, _)| name == command)
+            .map(|(_, count)| *count)
+            .collect();
+        if arg_counts.is_empty() && !commands.declared.contains(command) {
+            if commands.incomplete {
+                diagnostics.push(DialogDiagnostic::warning(
+                    position,
+                    format!(
+                        "Unknown command \"{}\" in \"{}\". It must be registered before the track plays",
+                        command, code
+                    ),
+                ));
+            } else {
+                diagnostics.push(DialogDiagnostic::error(
+                    position,
+                    format!("Unknown command \"{}\" in \"{}\"", command, code),
+                ));
+            }
+            continue;
+        }
+        // declared commands are registered by the game, their arguments are unknown here
+        if !arg_counts.is_empty() && !arg_counts.contains(&args.len()) {
+            diagnostics.push(DialogDiagnostic::error(
+                position,
+                format!(
+                    "Command \"{}\" expects {:?} argument(s), found {}",
+                    command,
+                    arg_counts,
+                    args.len()
+                ),
+            ));
+            continue;
+        }
+        if command == "jump" {
+            lint_jump(position, &args[0], track, diagnostics);
+        }
+        let written = match command.as_str() {
+            "set" | "add" | "sub" | "unset" | "push" | "remove" | "clear" | "toggle" => {
+                args.first()
+            }
+            _ => None,
+        };
+        for key in written.into_iter().chain(call.store.as_ref()) {
+            if Blackboard::is_read_only_key(key) {
+                diagnostics.push(DialogDiagnostic::error(
+                    position,
+                    format!(
+                        "\"{}\" is read-only and cannot be changed by \"{}\"",
+                        key, code
+                    ),
+                ));
+            }
+        }
+    }
+}
+
+fn lint_jump(
+    position: &[usize],
+    target: &str,
+    track: &DialogTrack,
+    diagnostics: &mut Vec<DialogDiagnostic>,
+) {
+    let target = match JumpTarget::parse(target) {
+        Ok(target) => target,
+        Err(reason) => {
+            diagnostics.push(DialogDiagnostic::error(position, reason));
+            return;
+        }
+    };
+    match target {
+        JumpTarget::Index(target_index) => {
+            if target_index >= track.lines.len() {
+                diagnostics.push(DialogDiagnostic::error(
+                    position,
+                    format!(
+                        "Jump target {} is out of range, the track has {} nodes",
+                        target_index,
+                        track.lines.len()
+                    ),
+                ));
+            }
+        }
+        JumpTarget::Label(label) => {
+            if track.get_label_index(&label).is_none() {
+                diagnostics.push(DialogDiagnostic::error(
+                    position,
+                    format!("Jump target label \"{}\" does not exist", label),
+                ));
+            }
+        }
+        // cross track labels are validated when the track is loaded
+        JumpTarget::Track { .. } => (),
+    }
+}
+
+fn lint_choice_reachable(position: &[usize], line: &Line, diagnostics: &mut Vec<DialogDiagnostic>) {
+    let Line::Choice {
+        options, timeout, ..
+    } = line
+    else {
+        return;
+    };
+    if let Some(default) = timeout.and_then(|timeout| options.get(timeout.default)) {
+        if !default.requires.trim().is_empty() {
+            diagnostics.push(DialogDiagnostic::warning(
+                position,
+                "The default option of this timed choice has a requirement, the first available option is selected instead when it is not met".to_string(),
+            ));
+        }
+        if default.once {
+            diagnostics.push(DialogDiagnostic::warning(
+                position,
+                "The default option of this timed choice is a once option, the first available option is selected instead once it was chosen".to_string(),
+            ));
+        }
+    }
+    if options.is_empty() {
+        diagnostics.push(DialogDiagnostic::error(
+            position,
+            "Choice has no options".to_string(),
+        ));
+        return;
+    }
+    let mut all_conditional = true;
+    let mut all_unreachable = true;
+    for option in options.iter() {
+        if option.requires.trim().is_empty() {
+            all_conditional = false;
+            all_unreachable = false;
+            continue;
+        }
+        let Ok(expression) = Expression::parse(&option.requires) else {
+            // already reported as a malformed query
+            all_unreachable = false;
+            continue;
+        };
+        // only constant queries can be decided without playing the track
+        let is_constant_false = expression.variables().is_empty()
+            && !expression
+                .evaluate(&|_| None)
+                .map(|value| value.is_truthy())
+                .unwrap_or(false);
+        if !is_constant_false {
+            all_unreachable = false;
+        }
+    }
+    if all_unreachable {
+        diagnostics.push(DialogDiagnostic::error(
+            position,
+            "Choice has no reachable option, every requirement is always false".to_string(),
+        ));
+    } else if all_conditional {
+        diagnostics.push(DialogDiagnostic::warning(
+            position,
+            "Every option of this choice has a requirement, the choice may have no available options".to_string(),
+        ));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scene::dialog::dialog_screenplay::parse_screenplay;
+
+    /// Builds a track from screenplay lines, see [parse_screenplay]
+    fn track(lines: &[&str]) -> DialogTrack {
+        let lines = parse_screenplay(&lines.join("\n")).expect("test screenplay should parse");
+        DialogTrack::from_lines(lines, "res://test.dialog").expect("test track should build")
+    }
+
+    fn lint(lines: &[&str]) -> Vec<DialogDiagnostic> {
+        let commands = KnownCommands::new(&Blackboard::headless(), Vec::new(), false);
+        lint_track(&track(lines), &commands)
+    }
+
+    /// The position and severity of each diagnostic, in order
+    fn found(diagnostics: &[DialogDiagnostic]) -> Vec<(Vec<usize>, Severity)> {
+        diagnostics
+            .iter()
+            .map(|diagnostic| {
+                let position = diagnostic.position.clone().unwrap_or_default();
+                (position, diagnostic.severity)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn commands_are_checked_against_the_known_commands() {
+        let mut blackboard = Blackboard::headless();
+        blackboard.register_queued_command("give", 2);
+        let lines = track(&[
+            "$ give sword 1",
+            "$ shake 1 2 3",
+            "$ give sword",
+            "@if true",
+            "$ teleport home",
+            "@end",
+        ]);
+        let commands = KnownCommands::new(&blackboard, vec!["shake".to_string()], false);
+        let diagnostics = lint_track(&lines, &commands);
+        assert_eq!(
+            found(&diagnostics),
+            vec![(vec![2], Severity::Error), (vec![3, 0, 0], Severity::Error)]
+        );
+        assert!(diagnostics[0]
+            .message
+            .contains("\"give\" expects [2] argument(s), found 1"));
+        assert!(diagnostics[1]
+            .message
+            .contains("Unknown command \"teleport\""));
+        assert_eq!(diagnostics[1].line(), Some(3));
+
+        // in the editor the game may still register the command
+        let commands = KnownCommands::new(&blackboard, vec!["shake".to_string()], true);
+        assert_eq!(
+            found(&lint_track(&lines, &commands)),
+            vec![
+                (vec![2], Severity::Error),
+                (vec![3, 0, 0], Severity::Warning)
+            ]
+        );
+    }
+
+    #[test]
+    fn malformed_queries_and_templates_are_errors() {
+        let diagnostics = lint(&[
+            "[if gold >] Hello",
+            "Hi {{ name",
+            "@if (gold > 5",
+            "Nested {{ two keys }}",
+            "@else",
+            "Mara: Fine",
+            "@end",
+        ]);
+        assert_eq!(
+            found(&diagnostics),
+            vec![
+                (vec![0], Severity::Error),
+                (vec![1], Severity::Error),
+                (vec![2], Severity::Error),
+                (vec![2, 0, 0], Severity::Error),
+            ]
+        );
+        assert!(diagnostics[0]
+            .message
+            .starts_with("Malformed query \"gold >\""));
+        assert!(diagnostics[1].message.starts_with("Malformed template"));
+        assert!(diagnostics[2].message.starts_with("Malformed query"));
+        assert!(diagnostics[3].message.starts_with("Malformed template"));
+    }
+
+    #[test]
+    fn template_keys_must_be_set_somewhere() {
+        let diagnostics = lint(&[
+            "Hello {{ name }}",
+            "-> Rest {set tired false}",
+            "-> Leave",
+            "{{ tired }} and {{ settings.text_speed }}",
+        ]);
+        assert_eq!(found(&diagnostics), vec![(vec![0], Severity::Warning)]);
+        assert!(diagnostics[0].message.contains("\"name\" is never set"));
+    }
+
+    #[test]
+    fn jump_targets_must_exist() {
+        let diagnostics = lint(&[
+            "@label start",
+            "$ jump start",
+            "$ jump missing",
+            "$ jump 9",
+            "$ jump res://other.dialog:anywhere",
+        ]);
+        assert_eq!(
+            found(&diagnostics),
+            vec![(vec![2], Severity::Error), (vec![3], Severity::Error)]
+        );
+        assert!(diagnostics[0]
+            .message
+            .contains("label \"missing\" does not exist"));
+        assert!(diagnostics[1]
+            .message
+            .contains("9 is out of range, the track has 5 nodes"));
+    }
+
+    #[test]
+    fn options_that_cannot_be_shown_are_reported() {
+        let diagnostics = lint(&[
+            "Pick",
+            "-> (once) (sticky) A",
+            "-> B [false]",
+            "Again",
+            "-> C [1 > 2]",
+            "-> D [not true]",
+            "Last",
+            "-> E [gold > 1]",
+            "-> F [false]",
+        ]);
+        assert_eq!(
+            found(&diagnostics),
+            vec![
+                (vec![0], Severity::Warning),
+                (vec![1], Severity::Error),
+                (vec![2], Severity::Warning),
+            ]
+        );
+        assert!(diagnostics[0].message.contains("both once and sticky"));
+        assert!(diagnostics[1].message.contains("no reachable option"));
+        assert!(diagnostics[2]
+            .message
+            .contains("may have no available options"));
+    }
+
+    #[test]
+    fn read_only_keys_cannot_be_written() {
+        let diagnostics = lint(&[
+            "$ set settings.text_speed 2",
+            "@if gold > 1",
+            "$ add project.application/config/name 1",
+            "@end",
+            "$ set gold 1",
+        ]);
+        assert_eq!(
+            found(&diagnostics),
+            vec![(vec![0], Severity::Error), (vec![1, 0, 0], Severity::Error)]
+        );
+        assert!(diagnostics[0]
+            .message
+            .contains("\"settings.text_speed\" is read-only"));
+    }
+
+    #[test]
+    fn template_keys_match_across_the_save_prefix() {
+        assert!(lint(&["$ set save.gold 5", "You have {{ gold }} coins"]).is_empty());
+        assert!(lint(&["$ set gold 5", "You have {{ save.gold }} coins"]).is_empty());
+        let diagnostics = lint(&["$ set local.gold 5", "You have {{ gold }} coins"]);
+        assert_eq!(diagnostics.len(), 1);
+        assert!(diagnostics[0].message.contains("\"gold\" is never set"));
+    }
+}
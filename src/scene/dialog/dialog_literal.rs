@@ -0,0 +1,229 @@
+//! Parses the literal values written in action code, such as `3`, `"old docks"` or `["a", 2]`, without the engine.
+//! Used by [Blackboard::headless](super::dialog_blackboard::Blackboard::headless), the in-engine blackboard parses with Godot's JSON parser.
+use std::collections::BTreeMap;
+
+use super::dialog_blackboard::Entry;
+
+/// Parses a JSON literal: `true`, `false`, a number, a double quoted string, or an array or object of those. Anything else (including `null`) gives `None`. Numbers without a fraction or exponent are ints
+pub fn parse_literal(value: &str) -> Option<Entry> {
+    let mut reader = LiteralReader {
+        text: value.trim(),
+        position: 0,
+    };
+    let entry = reader.read_value()?;
+    reader.skip_whitespace();
+    (reader.position == reader.text.len()).then_some(entry)
+}
+
+fn parse_scalar(value: &str) -> Option<Entry> {
+    match value {
+        "true" => Some(Entry::Bool(true)),
+        "false" => Some(Entry::Bool(false)),
+        _ => match value.parse::<i64>() {
+            Ok(int) => Some(Entry::Int(int)),
+            Err(_) => value
+                .parse::<f32>()
+                .ok()
+                .filter(|number| number.is_finite())
+                .map(Entry::Number),
+        },
+    }
+}
+
+fn unescape_json(text: &str) -> Option<String> {
+    let mut result = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => return None,
+            '\\' => result.push(match chars.next()? {
+                '"' => '"',
+                '\\' => '\\',
+                '/' => '/',
+                'b' => '\u{8}',
+                'f' => '\u{c}',
+                'n' => '\n',
+                'r' => '\r',
+                't' => '\t',
+                'u' => {
+                    let code: String = chars.by_ref().take(4).collect();
+                    char::from_u32(u32::from_str_radix(&code, 16).ok()?)?
+                }
+                _ => return None,
+            }),
+            c => result.push(c),
+        }
+    }
+    Some(result)
+}
+
+struct LiteralReader<'a> {
+    text: &'a str,
+    /// byte offset into `text`
+    position: usize,
+}
+
+impl LiteralReader<'_> {
+    fn peek(&self) -> Option<u8> {
+        self.text.as_bytes().get(self.position).copied()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.peek().is_some_and(|c| c.is_ascii_whitespace()) {
+            self.position += 1;
+        }
+    }
+
+    /// skips whitespace, then consumes `c` if it comes next
+    fn eat(&mut self, c: u8) -> bool {
+        self.skip_whitespace();
+        let found = self.peek() == Some(c);
+        if found {
+            self.position += 1;
+        }
+        found
+    }
+
+    fn read_value(&mut self) -> Option<Entry> {
+        self.skip_whitespace();
+        match self.peek()? {
+            b'"' => self.read_string().map(Entry::String),
+            b'[' => self.read_list(),
+            b'{' => self.read_map(),
+            _ => {
+                let start = self.position;
+                while self
+                    .peek()
+                    .is_some_and(|c| !c.is_ascii_whitespace() && !b",:]}".contains(&c))
+                {
+                    self.position += 1;
+                }
+                parse_scalar(&self.text[start..self.position])
+            }
+        }
+    }
+
+    fn read_string(&mut self) -> Option<String> {
+        let bytes = self.text.as_bytes();
+        let start = self.position + 1;
+        let mut end = start;
+        loop {
+            match bytes.get(end)? {
+                b'\\' => end += 2,
+                b'"' => break,
+                _ => end += 1,
+            }
+        }
+        self.position = end + 1;
+        unescape_json(&self.text[start..end])
+    }
+
+    fn read_list(&mut self) -> Option<Entry> {
+        self.position += 1;
+        let mut items = Vec::new();
+        if self.eat(b']') {
+            return Some(Entry::List(items));
+        }
+        loop {
+            items.push(self.read_value()?);
+            if self.eat(b']') {
+                return Some(Entry::List(items));
+            }
+            if !self.eat(b',') {
+                return None;
+            }
+        }
+    }
+
+    fn read_map(&mut self) -> Option<Entry> {
+        self.position += 1;
+        let mut entries = BTreeMap::new();
+        if self.eat(b'}') {
+            return Some(Entry::Map(entries));
+        }
+        loop {
+            self.skip_whitespace();
+            if self.peek()? != b'"' {
+                return None;
+            }
+            let key = self.read_string()?;
+            if !self.eat(b':') {
+                return None;
+            }
+            entries.insert(key, self.read_value()?);
+            if self.eat(b'}') {
+                return Some(Entry::Map(entries));
+            }
+            if !self.eat(b',') {
+                return None;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn string(text: &str) -> Entry {
+        Entry::String(text.to_string())
+    }
+
+    #[test]
+    fn parses_scalars() {
+        assert_eq!(parse_literal("true"), Some(Entry::Bool(true)));
+        assert_eq!(parse_literal(" false "), Some(Entry::Bool(false)));
+        assert_eq!(parse_literal("-12"), Some(Entry::Int(-12)));
+        assert_eq!(parse_literal("3.0"), Some(Entry::Number(3.0)));
+        assert_eq!(parse_literal("1e2"), Some(Entry::Number(100.0)));
+        assert_eq!(
+            parse_literal(r#""say \"hi\"\né""#),
+            Some(string("say \"hi\"\né"))
+        );
+    }
+
+    #[test]
+    fn parses_nested_lists_and_maps() {
+        assert_eq!(
+            parse_literal(r#"[1, "two", [true]]"#),
+            Some(Entry::List(vec![
+                Entry::Int(1),
+                string("two"),
+                Entry::List(vec![Entry::Bool(true)]),
+            ]))
+        );
+        assert_eq!(
+            parse_literal(r#"{ "b": 2.5, "a": { "list": [] } }"#),
+            Some(Entry::Map(BTreeMap::from([
+                ("b".to_string(), Entry::Number(2.5)),
+                (
+                    "a".to_string(),
+                    Entry::Map(BTreeMap::from([(
+                        "list".to_string(),
+                        Entry::List(Vec::new())
+                    )]))
+                ),
+            ])))
+        );
+        assert_eq!(parse_literal("{}"), Some(Entry::Map(BTreeMap::new())));
+    }
+
+    #[test]
+    fn rejects_anything_else() {
+        for value in [
+            "",
+            "null",
+            "sword",
+            "inf",
+            "\"unterminated",
+            "\"bad \\q escape\"",
+            "[1, 2",
+            "[1 2]",
+            "{a: 1}",
+            "{\"a\" 1}",
+            "1 2",
+        ] {
+            assert_eq!(parse_literal(value), None, "{:?} should not parse", value);
+        }
+    }
+}
@@ -0,0 +1,231 @@
+//! Collects the translatable strings of dialog tracks into Godot translation files.
+//!
+//! Lines and options with an `id` use it as their translation key, everything else uses the text itself (matching what [super::dialog_gui::DialogGUI] passes to `tr()`). Text is written unchanged, so `{{ }}` template placeholders survive translation.
+use std::collections::HashMap;
+
+use super::dialog_track::{DialogTrack, Line};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TranslationEntry {
+    pub key: String,
+    pub text: String,
+    /// the track files the string was found in
+    pub files: Vec<String>,
+}
+
+#[derive(Debug, Default)]
+pub struct TranslationCatalog {
+    pub entries: Vec<TranslationEntry>,
+    index: HashMap<String, usize>,
+}
+
+impl TranslationCatalog {
+    /// Adds every translatable string of `track`, with `character_name` mapping `character` fields to the names displayed for them. Returns a warning for each id that was already used with a different text
+    pub fn add_track(
+        &mut self,
+        track: &DialogTrack,
+        character_name: &dyn Fn(&str) -> String,
+    ) -> Vec<String> {
+        let mut warnings = Vec::new();
+        for (_, line) in track.walk() {
+            match line {
+                Line::Text {
+                    text,
+                    character,
+                    id,
+                    ..
+                } => {
+                    self.add(id, text, &track.file, &mut warnings);
+                    self.add("", &character_name(character), &track.file, &mut warnings);
+                }
+                Line::Choice {
+                    prompt,
+                    character,
+                    options,
+                    id,
+                    ..
+                } => {
+                    self.add(id, prompt, &track.file, &mut warnings);
+                    self.add("", &character_name(character), &track.file, &mut warnings);
+                    for option in options.iter() {
+                        self.add(&option.id, &option.text, &track.file, &mut warnings);
+                    }
+                }
+                _ => (),
+            }
+        }
+        warnings
+    }
+
+    fn add(&mut self, id: &str, text: &str, file: &str, warnings: &mut Vec<String>) {
+        if text.trim().is_empty() {
+            return;
+        }
+        let key = if id.is_empty() { text } else { id };
+        let Some(&index) = self.index.get(key) else {
+            self.index.insert(key.to_string(), self.entries.len());
+            self.entries.push(TranslationEntry {
+                key: key.to_string(),
+                text: text.to_string(),
+                files: vec![file.to_string()],
+            });
+            return;
+        };
+        let entry = &mut self.entries[index];
+        if entry.text != text {
+            warnings.push(format!(
+                "Translation id \"{}\" in {} is already used for \"{}\", ignoring \"{}\"",
+                key, file, entry.text, text
+            ));
+        }
+        if !entry.files.iter().any(|existing| existing == file) {
+            entry.files.push(file.to_string());
+        }
+    }
+
+    /// A CSV file in the layout Godot imports as translations, with a single column for `locale` holding the source text
+    pub fn to_csv(&self, locale: &str) -> String {
+        let mut csv = format!("keys,{}\n", csv_field(locale));
+        for entry in self.entries.iter() {
+            csv += &format!("{},{}\n", csv_field(&entry.key), csv_field(&entry.text));
+        }
+        csv
+    }
+
+    /// A gettext template. Entries keyed by an id keep their source text as a translator comment
+    pub fn to_pot(&self) -> String {
+        let mut pot = String::from(
+            "msgid \"\"\nmsgstr \"\"\n\"Content-Type: text/plain; charset=UTF-8\\n\"\n",
+        );
+        for entry in self.entries.iter() {
+            pot += "\n";
+            if entry.key != entry.text {
+                for text_line in entry.text.lines() {
+                    pot += &format!("#. {}\n", text_line);
+                }
+            }
+            pot += &format!("#: {}\n", entry.files.join(" "));
+            pot += &format!("msgid {}\nmsgstr \"\"\n", po_string(&entry.key));
+        }
+        pot
+    }
+}
+
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+fn po_string(text: &str) -> String {
+    let escaped = text
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+        .replace('\t', "\\t");
+    format!("\"{}\"", escaped)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scene::dialog::dialog_screenplay::parse_screenplay;
+
+    /// Builds a track from screenplay lines, see [parse_screenplay]
+    fn track(file: &str, lines: &[&str]) -> DialogTrack {
+        let lines = parse_screenplay(&lines.join("\n")).expect("test screenplay should parse");
+        DialogTrack::from_lines(lines, file).expect("test track should build")
+    }
+
+    fn keys_and_texts(catalog: &TranslationCatalog) -> Vec<(&str, &str)> {
+        catalog
+            .entries
+            .iter()
+            .map(|entry| (entry.key.as_str(), entry.text.as_str()))
+            .collect()
+    }
+
+    #[test]
+    fn strings_are_keyed_by_id_or_by_text() {
+        let mut catalog = TranslationCatalog::default();
+        let warnings = catalog.add_track(
+            &track(
+                "res://a.dialog",
+                &["mara: Hello ##greet", "Welcome", "-> Stay ##stay", "-> Go"],
+            ),
+            &|character| character.to_uppercase(),
+        );
+        assert!(warnings.is_empty());
+        assert_eq!(
+            keys_and_texts(&catalog),
+            vec![
+                ("greet", "Hello"),
+                ("MARA", "MARA"),
+                ("Welcome", "Welcome"),
+                ("stay", "Stay"),
+                ("Go", "Go"),
+            ]
+        );
+    }
+
+    #[test]
+    fn ids_reused_with_another_text_are_warnings() {
+        let mut catalog = TranslationCatalog::default();
+        let name = |character: &str| character.to_string();
+        catalog.add_track(&track("res://a.dialog", &["Hello ##greet", "Bye"]), &name);
+        let warnings = catalog.add_track(
+            &track("res://b.dialog", &["Hello again ##greet", "Bye"]),
+            &name,
+        );
+        assert_eq!(
+            warnings,
+            vec![
+                "Translation id \"greet\" in res://b.dialog is already used for \"Hello\", ignoring \"Hello again\""
+            ]
+        );
+        assert_eq!(
+            keys_and_texts(&catalog),
+            vec![("greet", "Hello"), ("Bye", "Bye")]
+        );
+        assert_eq!(
+            catalog.entries[1].files,
+            vec!["res://a.dialog", "res://b.dialog"]
+        );
+    }
+
+    #[test]
+    fn csv_fields_are_quoted_when_needed() {
+        let mut catalog = TranslationCatalog::default();
+        let mut warnings = Vec::new();
+        catalog.add("", "Plain", "res://a.dialog", &mut warnings);
+        catalog.add("", "Well, \"maybe\"", "res://a.dialog", &mut warnings);
+        catalog.add("two_lines", "One\nTwo", "res://a.dialog", &mut warnings);
+        assert_eq!(
+            catalog.to_csv("en"),
+            "keys,en\nPlain,Plain\n\"Well, \"\"maybe\"\"\",\"Well, \"\"maybe\"\"\"\ntwo_lines,\"One\nTwo\"\n"
+        );
+    }
+
+    #[test]
+    fn pot_strings_are_escaped_and_ids_keep_their_text_as_comments() {
+        let mut catalog = TranslationCatalog::default();
+        let mut warnings = Vec::new();
+        catalog.add("", "Say \"hi\"\tC:\\", "res://a.dialog", &mut warnings);
+        catalog.add("two_lines", "One\nTwo", "res://a.dialog", &mut warnings);
+        catalog.add("two_lines", "One\nTwo", "res://b.dialog", &mut warnings);
+        let pot = catalog.to_pot();
+        assert!(pot.starts_with("msgid \"\"\nmsgstr \"\"\n"));
+        assert!(pot.ends_with(concat!(
+            "\n#: res://a.dialog\n",
+            "msgid \"Say \\\"hi\\\"\\tC:\\\\\"\n",
+            "msgstr \"\"\n",
+            "\n#. One\n",
+            "#. Two\n",
+            "#: res://a.dialog res://b.dialog\n",
+            "msgid \"two_lines\"\n",
+            "msgstr \"\"\n",
+        )));
+    }
+}
@@ -0,0 +1,307 @@
+use godot::prelude::*;
+
+use super::{
+    core_dialog::SqoreDialog,
+    dialog_blackboard::{Blackboard, Entry, Scope},
+    dialog_runner::{DialogEvent, DialogRunner},
+    dialog_track::{DialogError, DialogTrack, Line},
+};
+
+/// Plays a dialog track without any GUI, for testing tracks from scripts. Works on a copy of the dialog blackboard (custom commands included), so the game's dialog state is left untouched.
+///
+/// ```gdscript
+/// var playthrough := DialogPlaythrough.from_file("res://dialog/mara.dialog")
+/// playthrough.expect_line("Mara", "Where are you headed?")
+/// playthrough.expect_choice(["The docks", "Nowhere in particular"])
+/// playthrough.choose(0)
+/// playthrough.expect_value("destination", "docks")
+/// playthrough.expect_end()
+/// assert(playthrough.get_failures().is_empty())
+/// ```
+#[derive(GodotClass)]
+#[class(init, base=RefCounted)]
+pub struct DialogPlaythrough {
+    runner: Option<DialogRunner>,
+    blackboard: Blackboard,
+    /// the signals reached so far, as dictionaries with the keys `name` and `args`
+    signals: Array<Dictionary>,
+    failures: PackedStringArray,
+
+    base: Base<RefCounted>,
+}
+
+#[godot_api]
+impl DialogPlaythrough {
+    /// Starts a playthrough of a track file. Returns null if the track fails to load
+    #[func]
+    fn from_file(file_path: GString) -> Option<Gd<Self>> {
+        Self::start(DialogTrack::load_from_file(file_path))
+    }
+
+    /// Starts a playthrough of a track dictionary. Returns null if the track fails to load
+    #[func]
+    fn from_dict(track_dict: Dictionary) -> Option<Gd<Self>> {
+        Self::start(DialogTrack::load_from_dict(
+            track_dict,
+            "<internal dict>".to_godot(),
+        ))
+    }
+
+    fn start(track: Result<DialogTrack, DialogError>) -> Option<Gd<Self>> {
+        let track = match track {
+            Ok(track) => track,
+            Err(err) => {
+                godot_error!("DialogError: {:#?}", err);
+                return None;
+            }
+        };
+        let mut playthrough = Self::new_gd();
+        {
+            let mut zelf = playthrough.bind_mut();
+            zelf.runner = Some(DialogRunner::new(track, 0));
+            zelf.blackboard = SqoreDialog::singleton().bind().blackboard.clone();
+            zelf.blackboard.clear_scope(Scope::Local);
+            zelf.blackboard.take_changes();
+        }
+        Some(playthrough)
+    }
+
+    /// Advances to the next line to display. Returns a dictionary with the key `type`: "text" lines have the keys `character` and `text`, "choice" lines have `character`, `text` (the prompt) and `options` (the texts of the options that can be chosen), and "end" has no other keys. Text is templated but not translated
+    #[func]
+    fn advance(&mut self) -> Dictionary {
+        loop {
+            let Some(runner) = &mut self.runner else {
+                return Self::event_dict("end");
+            };
+            let event = runner.next(&mut self.blackboard);
+            for warning in runner.take_warnings() {
+                godot_warn!("{}", warning);
+            }
+            // a playthrough has no event bus to report these to
+            runner.take_executed_actions();
+            self.blackboard.take_changes();
+            match event {
+                DialogEvent::Text(line) => return self.line_dict("text", &line),
+                DialogEvent::Choice { line, options } => {
+                    let mut dict = self.line_dict("choice", &line);
+                    let texts = options
+                        .iter()
+                        .map(|available| {
+                            self.blackboard
+                                .format_text(available.option.text.clone())
+                                .to_godot()
+                        })
+                        .collect::<PackedStringArray>();
+                    dict.set("options", texts);
+                    return dict;
+                }
+                DialogEvent::Signal { name, args } => {
+                    let mut signal = Dictionary::new();
+                    signal.set("name", name);
+                    signal.set(
+                        "args",
+                        args.iter()
+                            .map(|arg| arg.to_godot())
+                            .collect::<PackedStringArray>(),
+                    );
+                    self.signals.push(signal);
+                }
+                DialogEvent::Commands(calls) => {
+                    for call in calls {
+                        let command = SqoreDialog::singleton().bind().command_callable(&call);
+                        let Some((callable, args)) = command else {
+                            continue;
+                        };
+                        let result = callable.callv(args);
+                        self.blackboard
+                            .store_command_result(&call, Entry::from_variant(&result));
+                    }
+                    for warning in self.blackboard.take_warnings() {
+                        godot_warn!("{}", warning);
+                    }
+                }
+                DialogEvent::TrackRequested { file, label } => {
+                    let runner = DialogTrack::load_from_file(file.to_godot())
+                        .and_then(|track| DialogRunner::from_label(track, label.as_deref()));
+                    match runner {
+                        Ok(runner) => self.runner = Some(runner),
+                        Err(err) => {
+                            self.fail(format!("Failed to jump to \"{}\": {:?}", file, err));
+                            self.runner = None;
+                        }
+                    }
+                }
+                DialogEvent::Ended => return Self::event_dict("end"),
+            }
+        }
+    }
+
+    /// Chooses the option at `position` among the options of the current choice. Returns false if no choice is pending or the position is out of range
+    #[func]
+    fn choose(&mut self, position: i64) -> bool {
+        let Some(runner) = &mut self.runner else {
+            self.fail("Cannot choose an option, the playthrough has ended".to_string());
+            return false;
+        };
+        let result = runner.choose(&mut self.blackboard, position.max(0) as usize);
+        runner.take_executed_actions();
+        self.blackboard.take_changes();
+        if let Err(err) = result {
+            self.fail(format!("Cannot choose option {}: {:?}", position, err));
+            return false;
+        }
+        true
+    }
+
+    /// Plays the track to its end, resolving choices in order with `choices` (positions among the options that can be chosen). Stops at the first choice left once `choices` runs out. Returns every line reached, see [DialogPlaythrough::advance]
+    #[func]
+    fn play(&mut self, choices: PackedInt64Array) -> Array<Dictionary> {
+        let mut choices = choices.as_slice().iter();
+        let mut lines = Array::new();
+        loop {
+            let line = self.advance();
+            let line_type = line.get_or_nil("type").to_string();
+            lines.push(line);
+            match line_type.as_str() {
+                "end" => return lines,
+                "choice" => {
+                    let Some(position) = choices.next() else {
+                        return lines;
+                    };
+                    if !self.choose(*position) {
+                        return lines;
+                    }
+                }
+                _ => (),
+            }
+        }
+    }
+
+    /// Advances and checks that the next line is a text line with this character and text
+    #[func]
+    fn expect_line(&mut self, character: GString, text: GString) -> bool {
+        let line = self.advance();
+        let found = (
+            line.get_or_nil("type").to_string(),
+            line.get_or_nil("character").to_string(),
+            line.get_or_nil("text").to_string(),
+        );
+        if found != ("text".to_string(), character.to_string(), text.to_string()) {
+            self.fail(format!(
+                "Expected the line {}: \"{}\", found {}",
+                character, text, line
+            ));
+            return false;
+        }
+        true
+    }
+
+    /// Advances and checks that the next line is a choice offering exactly these options
+    #[func]
+    fn expect_choice(&mut self, options: PackedStringArray) -> bool {
+        let line = self.advance();
+        let found = line
+            .get("options")
+            .and_then(|found| PackedStringArray::try_from_variant(&found).ok());
+        if found.as_ref() != Some(&options) {
+            self.fail(format!(
+                "Expected a choice with the options {}, found {}",
+                options, line
+            ));
+            return false;
+        }
+        true
+    }
+
+    /// Advances and checks that the track is over
+    #[func]
+    fn expect_end(&mut self) -> bool {
+        let line = self.advance();
+        if line.get_or_nil("type").to_string() != "end" {
+            self.fail(format!("Expected the end of the track, found {}", line));
+            return false;
+        }
+        true
+    }
+
+    /// Checks the value of a blackboard entry. A null `value` expects the entry to be missing
+    #[func]
+    fn expect_value(&mut self, key: GString, value: Variant) -> bool {
+        let found = self
+            .blackboard
+            .get(key.to_string().as_str())
+            .unwrap_or_default();
+        let expected = Entry::from_variant(&value);
+        if found != expected {
+            self.fail(format!(
+                "Expected \"{}\" to be {}, found {}",
+                key, expected, found
+            ));
+            return false;
+        }
+        true
+    }
+
+    /// Sets a blackboard entry of this playthrough, see [SqoreDialog::blackboard_set]
+    #[func]
+    fn set_value(&mut self, key: GString, value: Variant) -> bool {
+        let entry = Entry::from_variant(&value);
+        if entry == Entry::None {
+            godot_warn!(
+                "Cannot store value of type {:?} in the blackboard (key \"{}\")",
+                value.get_type(),
+                key
+            );
+            return false;
+        }
+        self.blackboard.set_entry(key.to_string().as_str(), entry)
+    }
+
+    /// Gets a blackboard entry of this playthrough, or null if it doesn't exist
+    #[func]
+    fn get_value(&self, key: GString) -> Variant {
+        self.blackboard
+            .get(key.to_string().as_str())
+            .unwrap_or_default()
+            .to_variant()
+    }
+
+    /// The track signals reached so far, as dictionaries with the keys `name` and `args`
+    #[func]
+    fn get_signals(&self) -> Array<Dictionary> {
+        self.signals.clone()
+    }
+
+    /// The messages of every failed expectation so far
+    #[func]
+    fn get_failures(&self) -> PackedStringArray {
+        self.failures.clone()
+    }
+
+    fn fail(&mut self, message: String) {
+        godot_error!("{}", message);
+        self.failures.push(message.to_godot());
+    }
+
+    fn event_dict(event_type: &str) -> Dictionary {
+        let mut dict = Dictionary::new();
+        dict.set("type", event_type);
+        dict
+    }
+
+    fn line_dict(&self, event_type: &str, line: &Line) -> Dictionary {
+        let (character, text) = match line {
+            Line::Text {
+                character, text, ..
+            } => (character, text),
+            Line::Choice {
+                character, prompt, ..
+            } => (character, prompt),
+            _ => return Self::event_dict(event_type),
+        };
+        let mut dict = Self::event_dict(event_type);
+        dict.set("character", self.blackboard.format_text(character.clone()));
+        dict.set("text", self.blackboard.format_text(text.clone()));
+        dict
+    }
+}
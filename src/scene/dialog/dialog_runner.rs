@@ -0,0 +1,694 @@
+//! Plays a [DialogTrack] against a [Blackboard] without any nodes. [super::dialog_gui::DialogGUI] displays what the runner produces, and [super::dialog_playthrough::DialogPlaythrough] drives one from scripts to test tracks.
+use super::{
+    dialog_blackboard::{Blackboard, CommandCall, Entry},
+    dialog_cursor::DialogCursor,
+    dialog_track::{ChoiceOptionEntry, DialogError, DialogTrack, JumpTarget, Line},
+};
+
+/// What the runner reached when asked for the next line
+#[derive(Debug, Clone, PartialEq)]
+pub enum DialogEvent {
+    /// a text line whose requirement passed
+    Text(Line),
+    /// a choice line, waiting for [DialogRunner::choose]. `options` are the options that can be chosen
+    Choice {
+        line: Line,
+        options: Vec<AvailableOption>,
+    },
+    Signal {
+        name: String,
+        args: Vec<String>,
+    },
+    /// an action used custom commands, see [Blackboard::register_queued_command]. Run them and store their results with [Blackboard::store_command_result] before asking for the next line, so the lines after the action see the results
+    Commands(Vec<CommandCall>),
+    /// an action jumped to another track. Load it and continue with a new runner, see [DialogRunner::from_label]
+    TrackRequested {
+        file: String,
+        label: Option<String>,
+    },
+    Ended,
+}
+
+/// An option that passed its requirement and `once`/`fallback` rules
+#[derive(Debug, Clone, PartialEq)]
+pub struct AvailableOption {
+    /// the index of the option in its choice
+    pub index: usize,
+    pub option: ChoiceOptionEntry,
+    /// whether this is a `sticky` option that was chosen before
+    pub chosen: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum RunnerError {
+    NoChoicePending,
+    /// the position is out of range of the options that can be chosen
+    InvalidOption(usize),
+}
+
+#[derive(Debug, Clone)]
+pub struct DialogRunner {
+    track: DialogTrack,
+    cursor: DialogCursor,
+    /// the options of the choice waiting for [DialogRunner::choose]
+    pending_choice: Option<Vec<AvailableOption>>,
+    /// a jump to another track, reported by the next call to [DialogRunner::next]
+    requested_track: Option<(String, Option<String>)>,
+    /// action code run since the last [DialogRunner::take_executed_actions]
+    executed_actions: Vec<String>,
+    warnings: Vec<String>,
+}
+
+impl DialogRunner {
+    /// Blackboard entries starting with this remember which `once` and `sticky` options were chosen
+    pub const CHOSEN_OPTION_PREFIX: &'static str = "chosen.";
+
+    /// Starts `track` at the top level line `index`
+    pub fn new(track: DialogTrack, index: usize) -> Self {
+        let cursor = DialogCursor::new(track.lines.clone(), index);
+        Self::with_cursor(track, cursor)
+    }
+
+    /// Starts `track` at a label, or at its first line when `label` is `None`
+    pub fn from_label(track: DialogTrack, label: Option<&str>) -> Result<Self, DialogError> {
+        let index = match label {
+            None => 0,
+            Some(label) => {
+                track
+                    .get_label_index(label)
+                    .ok_or_else(|| DialogError::UnknownLabel {
+                        file: track.file.clone(),
+                        label: label.to_string(),
+                    })?
+            }
+        };
+        Ok(Self::new(track, index))
+    }
+
+    /// Resumes `track` at a [DialogRunner::position], so the line it points at is the next line. Returns None if the position does not fit the track
+    pub fn at(track: DialogTrack, position: &[usize]) -> Option<Self> {
+        let cursor = DialogCursor::at(track.lines.clone(), position)?;
+        Some(Self::with_cursor(track, cursor))
+    }
+
+    fn with_cursor(track: DialogTrack, cursor: DialogCursor) -> Self {
+        Self {
+            track,
+            cursor,
+            pending_choice: None,
+            requested_track: None,
+            executed_actions: Vec::new(),
+            warnings: Vec::new(),
+        }
+    }
+
+    pub fn track(&self) -> &DialogTrack {
+        &self.track
+    }
+
+    /// The position of the line last taken, see [DialogCursor::position]
+    pub fn position(&self) -> Vec<usize> {
+        self.cursor.position()
+    }
+
+    /// The options of the choice waiting for [DialogRunner::choose], if any
+    pub fn pending_choice(&self) -> Option<&[AvailableOption]> {
+        self.pending_choice.as_deref()
+    }
+
+    /// Makes the choice line the cursor points at the pending choice, so [DialogRunner::next] reports it without taking any line first. Used to resume a [DialogRunner::at] position taken while a choice was pending. Returns false, leaving the runner as it was, if that line is not a choice
+    pub fn resume_choice(&mut self, blackboard: &Blackboard) -> bool {
+        let mut cursor = self.cursor.clone();
+        let Some(Line::Choice { options, .. }) = cursor.next_line() else {
+            return false;
+        };
+        self.cursor = cursor;
+        let options = self.available_options(blackboard, &options);
+        self.pending_choice = Some(options);
+        true
+    }
+
+    /// The action code run since the last call, in order. Empty actions are left out
+    pub fn take_executed_actions(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.executed_actions)
+    }
+
+    /// Problems found since the last call, such as malformed queries
+    pub fn take_warnings(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.warnings)
+    }
+
+    /// Runs the track up to the next line to display, running actions, following branches and reporting signals and custom commands along the way. A pending choice is reported again until it is chosen
+    pub fn next(&mut self, blackboard: &mut Blackboard) -> DialogEvent {
+        if let Some(options) = &self.pending_choice {
+            let line = self.cursor.current_line().cloned().unwrap_or(Line::None);
+            return DialogEvent::Choice {
+                line,
+                options: options.clone(),
+            };
+        }
+        loop {
+            let calls = blackboard.take_command_calls();
+            if !calls.is_empty() {
+                return DialogEvent::Commands(calls);
+            }
+            if let Some((file, label)) = self.requested_track.take() {
+                return DialogEvent::TrackRequested { file, label };
+            }
+            let Some(line) = self.cursor.next_line() else {
+                return DialogEvent::Ended;
+            };
+            match &line {
+                Line::Text { requires, .. } => {
+                    if self.query(blackboard, requires) {
+                        return DialogEvent::Text(line);
+                    }
+                }
+                Line::Choice { options, .. } => {
+                    let options = self.available_options(blackboard, options);
+                    self.pending_choice = Some(options.clone());
+                    return DialogEvent::Choice { line, options };
+                }
+                Line::Action { action } => self.run_action(blackboard, action),
+                Line::Signal { name, args } => {
+                    return DialogEvent::Signal {
+                        name: name.clone(),
+                        args: args.clone(),
+                    }
+                }
+                Line::Branch { branches } => {
+                    let taken = branches.iter().position(|branch| {
+                        branch.is_else() || self.query(blackboard, &branch.requires)
+                    });
+                    if let Some(taken) = taken {
+                        self.cursor.enter(taken);
+                    }
+                }
+                Line::Label { .. } | Line::None => (),
+            }
+        }
+    }
+
+    /// Chooses the option at `position` among the options of the pending choice. Its lines are played next, then the lines after the choice. Returns the chosen option
+    pub fn choose(
+        &mut self,
+        blackboard: &mut Blackboard,
+        position: usize,
+    ) -> Result<AvailableOption, RunnerError> {
+        let Some(options) = &self.pending_choice else {
+            return Err(RunnerError::NoChoicePending);
+        };
+        let Some(chosen) = options.get(position).cloned() else {
+            return Err(RunnerError::InvalidOption(position));
+        };
+        self.pending_choice = None;
+        if chosen.option.is_remembered() {
+            blackboard.set_entry(&self.chosen_option_key(&chosen.option), Entry::Bool(true));
+        }
+        // entered before the action runs, so a jump in the action replaces the option's lines
+        self.cursor.enter(chosen.index);
+        self.run_action(blackboard, &chosen.option.action);
+        Ok(chosen)
+    }
+
+    /// Runs action code, applying any `jump` or `end` it triggers to this track
+    pub fn run_action(&mut self, blackboard: &mut Blackboard, code: &str) {
+        if code.trim().is_empty() {
+            return;
+        }
+        blackboard.parse_action(code.to_string());
+        self.warnings.extend(blackboard.take_warnings());
+        self.executed_actions.push(code.to_string());
+        let Some((event_name, event_arg)) = blackboard.get_event() else {
+            return;
+        };
+        blackboard.mark_event_handled();
+        match event_name.as_str() {
+            "end" => {
+                self.cursor.clear();
+                self.pending_choice = None;
+            }
+            "jump" => match event_arg {
+                Entry::String(target) => match JumpTarget::parse(target.as_str()) {
+                    Ok(target) => self.jump(target),
+                    Err(reason) => self.warnings.push(reason),
+                },
+                Entry::Int(index) if index >= 0 => self.jump(JumpTarget::Index(index as usize)),
+                _ => self
+                    .warnings
+                    .push(format!("Invalid jump target: {}", event_arg)),
+            },
+            _ => self.warnings.push(format!(
+                "Unhandled internal event! event: \"{}\"",
+                event_name
+            )),
+        }
+    }
+
+    fn jump(&mut self, target: JumpTarget) {
+        let index = match target {
+            JumpTarget::Index(index) => index,
+            JumpTarget::Label(label) => {
+                let Some(index) = self.track.get_label_index(&label) else {
+                    self.warnings.push(format!(
+                        "Jump target label \"{}\" does not exist in {}",
+                        label, self.track.file
+                    ));
+                    return;
+                };
+                index
+            }
+            JumpTarget::Track { file, label } => {
+                self.requested_track = Some((self.track.resolve_track_path(&file), label));
+                self.cursor.clear();
+                return;
+            }
+        };
+        self.cursor = DialogCursor::new(self.track.lines.clone(), index);
+        self.pending_choice = None;
+    }
+
+    /// The options that can be chosen, with their index and whether they were already chosen. Options failing their requirement or `once` options that were chosen are left out, and `fallback` options are only included when nothing else is available
+    pub fn available_options(
+        &mut self,
+        blackboard: &Blackboard,
+        options: &[ChoiceOptionEntry],
+    ) -> Vec<AvailableOption> {
+        let mut available = Vec::new();
+        let mut fallbacks = Vec::new();
+        for (index, option) in options.iter().enumerate() {
+            if !self.query(blackboard, &option.requires) {
+                continue;
+            }
+            let chosen = self.is_option_chosen(blackboard, option);
+            if chosen && option.once {
+                continue;
+            }
+            let entry = AvailableOption {
+                index,
+                option: option.clone(),
+                chosen: chosen && option.sticky,
+            };
+            if option.fallback {
+                fallbacks.push(entry);
+            } else {
+                available.push(entry);
+            }
+        }
+        if available.is_empty() {
+            fallbacks
+        } else {
+            available
+        }
+    }
+
+    pub fn is_option_chosen(&self, blackboard: &Blackboard, option: &ChoiceOptionEntry) -> bool {
+        blackboard
+            .get(&self.chosen_option_key(option))
+            .is_some_and(|entry| entry.is_truthy())
+    }
+
+    /// The blackboard key remembering that an option of the current choice was chosen. Options without an id are told apart by the position of their choice too, as options of different choices often share their text
+    fn chosen_option_key(&self, option: &ChoiceOptionEntry) -> String {
+        if !option.id.is_empty() {
+            return format!(
+                "{}{}#{}",
+                Self::CHOSEN_OPTION_PREFIX,
+                self.track.file,
+                option.id
+            );
+        }
+        let position: Vec<String> = self
+            .cursor
+            .position()
+            .iter()
+            .map(|index| index.to_string())
+            .collect();
+        format!(
+            "{}{}#{}#{}",
+            Self::CHOSEN_OPTION_PREFIX,
+            self.track.file,
+            position.join("/"),
+            option.text
+        )
+    }
+
+    /// Evaluates a requirement, treating malformed queries as failed
+    fn query(&mut self, blackboard: &Blackboard, query: &str) -> bool {
+        match blackboard.parse_query(query) {
+            Ok(value) => value,
+            Err(err) => {
+                self.warnings
+                    .push(format!("Malformed query \"{}\": {}", query, err));
+                false
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scene::dialog::{
+        dialog_blackboard::EntryChange, dialog_screenplay::parse_screenplay,
+    };
+
+    /// Builds a track from screenplay lines, see [parse_screenplay]
+    fn track(lines: &[&str]) -> DialogTrack {
+        let lines = parse_screenplay(&lines.join("\n")).expect("test screenplay should parse");
+        DialogTrack::from_lines(lines, "res://test.dialog").expect("test track should build")
+    }
+
+    fn text(event: DialogEvent) -> String {
+        match event {
+            DialogEvent::Text(Line::Text { text, .. }) => text,
+            other => panic!("expected a text line, found {:?}", other),
+        }
+    }
+
+    fn option_texts(event: DialogEvent) -> Vec<String> {
+        match event {
+            DialogEvent::Choice { options, .. } => options
+                .into_iter()
+                .map(|available| available.option.text)
+                .collect(),
+            other => panic!("expected a choice, found {:?}", other),
+        }
+    }
+
+    #[test]
+    fn plays_text_lines_in_order() {
+        let mut blackboard = Blackboard::headless();
+        let mut runner = DialogRunner::new(track(&["Mara: Hello", "Mara: Goodbye"]), 0);
+        assert_eq!(text(runner.next(&mut blackboard)), "Hello");
+        assert_eq!(text(runner.next(&mut blackboard)), "Goodbye");
+        assert_eq!(runner.next(&mut blackboard), DialogEvent::Ended);
+        assert_eq!(runner.next(&mut blackboard), DialogEvent::Ended);
+    }
+
+    #[test]
+    fn skips_lines_failing_their_requirement() {
+        let mut blackboard = Blackboard::headless();
+        let mut runner = DialogRunner::new(
+            track(&["$ set gold 3", "[if gold >= 5] Rich", "[if gold < 5] Poor"]),
+            0,
+        );
+        assert_eq!(text(runner.next(&mut blackboard)), "Poor");
+        assert_eq!(blackboard.get("gold"), Some(Entry::Int(3)));
+    }
+
+    #[test]
+    fn takes_the_first_passing_branch() {
+        let mut blackboard = Blackboard::headless();
+        blackboard.set_entry("gold", Entry::Number(7.0));
+        let mut runner = DialogRunner::new(
+            track(&[
+                "@if gold > 10",
+                "Very rich",
+                "@elif gold > 5",
+                "Rich",
+                "$ set rich true",
+                "@else",
+                "Poor",
+                "@end",
+                "After",
+            ]),
+            0,
+        );
+        assert_eq!(text(runner.next(&mut blackboard)), "Rich");
+        assert_eq!(text(runner.next(&mut blackboard)), "After");
+        assert_eq!(blackboard.get("rich"), Some(Entry::Bool(true)));
+    }
+
+    #[test]
+    fn choosing_runs_the_option_action() {
+        let mut blackboard = Blackboard::headless();
+        let mut runner = DialogRunner::new(
+            track(&[
+                "Where to?",
+                "-> Docks {set destination \"docks\"}",
+                "-> Home {set destination \"home\"}",
+                "Safe travels",
+            ]),
+            0,
+        );
+        assert_eq!(
+            option_texts(runner.next(&mut blackboard)),
+            vec!["Docks", "Home"]
+        );
+        // the choice is reported again until it is resolved
+        assert!(matches!(
+            runner.next(&mut blackboard),
+            DialogEvent::Choice { .. }
+        ));
+        let chosen = runner.choose(&mut blackboard, 1).unwrap();
+        assert_eq!(chosen.index, 1);
+        assert_eq!(
+            blackboard.get("destination"),
+            Some(Entry::String("home".to_string()))
+        );
+        assert_eq!(text(runner.next(&mut blackboard)), "Safe travels");
+    }
+
+    #[test]
+    fn reports_executed_actions_and_blackboard_changes() {
+        let mut blackboard = Blackboard::headless();
+        blackboard.set_entry("gold", Entry::Int(5));
+        blackboard.take_changes();
+        let mut runner = DialogRunner::new(track(&["$ add gold 2", "$ set gold 7", "Done"]), 0);
+        assert_eq!(text(runner.next(&mut blackboard)), "Done");
+        assert_eq!(
+            runner.take_executed_actions(),
+            vec!["add gold 2", "set gold 7"]
+        );
+        // setting an entry to the value it already has is not a change
+        assert_eq!(
+            blackboard.take_changes(),
+            vec![EntryChange {
+                key: "gold".to_string(),
+                old: Entry::Int(5),
+                new: Entry::Int(7),
+            }]
+        );
+        assert!(runner.take_executed_actions().is_empty());
+    }
+
+    #[test]
+    fn custom_commands_run_before_the_next_line() {
+        let mut blackboard = Blackboard::headless();
+        assert!(blackboard.register_queued_command("give_item", 1));
+        let mut runner = DialogRunner::new(
+            track(&[
+                "$ give_item sword -> has_sword; set gold 1",
+                "[if has_sword] Armed",
+                "Unarmed",
+            ]),
+            0,
+        );
+        let call = CommandCall {
+            name: "give_item".to_string(),
+            args: vec!["sword".to_string()],
+            store: Some("has_sword".to_string()),
+        };
+        assert_eq!(
+            runner.next(&mut blackboard),
+            DialogEvent::Commands(vec![call.clone()])
+        );
+        // the rest of the action has run, the result is stored by the caller
+        assert_eq!(blackboard.get("gold"), Some(Entry::Int(1)));
+        assert!(!blackboard.has_entry("has_sword"));
+        blackboard.store_command_result(&call, Entry::Bool(true));
+        assert_eq!(text(runner.next(&mut blackboard)), "Armed");
+    }
+
+    #[test]
+    fn custom_commands_of_options_run_before_the_option_lines() {
+        let mut blackboard = Blackboard::headless();
+        assert!(blackboard.register_queued_command("shout", 0));
+        let mut runner = DialogRunner::new(track(&["Well?", "-> Shout {shout}", "Done"]), 0);
+        runner.next(&mut blackboard);
+        runner.choose(&mut blackboard, 0).unwrap();
+        assert!(matches!(
+            runner.next(&mut blackboard),
+            DialogEvent::Commands(calls) if calls.len() == 1
+        ));
+        assert_eq!(text(runner.next(&mut blackboard)), "Done");
+    }
+
+    #[test]
+    fn option_lines_play_before_returning_to_the_track() {
+        let mut blackboard = Blackboard::headless();
+        // options carrying their own lines are only written in JSON tracks
+        let mut track = track(&["Question", "-> Ask {set asked true}", "-> Leave", "Done"]);
+        let Line::Choice { options, .. } = &mut track.lines[0] else {
+            panic!("expected a choice, found {:?}", track.lines[0]);
+        };
+        options[0].nodes.push(Line::Text {
+            text: "Answer".to_string(),
+            character: String::new(),
+            requires: String::new(),
+            id: String::new(),
+            voice: String::new(),
+        });
+        let mut runner = DialogRunner::new(track, 0);
+        runner.next(&mut blackboard);
+        runner.choose(&mut blackboard, 0).unwrap();
+        assert_eq!(text(runner.next(&mut blackboard)), "Answer");
+        assert_eq!(runner.position(), vec![0, 0, 0]);
+        assert_eq!(text(runner.next(&mut blackboard)), "Done");
+        assert_eq!(blackboard.get("asked"), Some(Entry::Bool(true)));
+    }
+
+    #[test]
+    fn once_options_are_hidden_after_being_chosen() {
+        let mut blackboard = Blackboard::headless();
+        let mut runner = DialogRunner::new(
+            track(&[
+                "@label hub",
+                "What now?",
+                "-> (once) Ask about the lighthouse {jump hub}",
+                "-> (fallback) Leave",
+            ]),
+            0,
+        );
+        assert_eq!(
+            option_texts(runner.next(&mut blackboard)),
+            vec!["Ask about the lighthouse"]
+        );
+        runner.choose(&mut blackboard, 0).unwrap();
+        assert_eq!(option_texts(runner.next(&mut blackboard)), vec!["Leave"]);
+    }
+
+    #[test]
+    fn once_options_are_remembered_per_choice() {
+        let mut blackboard = Blackboard::headless();
+        let mut runner = DialogRunner::new(
+            track(&[
+                "First?",
+                "-> (once) Ask",
+                "-> Leave",
+                "Second?",
+                "-> (once) Ask",
+                "-> Leave",
+            ]),
+            0,
+        );
+        assert_eq!(
+            option_texts(runner.next(&mut blackboard)),
+            vec!["Ask", "Leave"]
+        );
+        runner.choose(&mut blackboard, 0).unwrap();
+        // the same text in another choice is a different option
+        assert_eq!(
+            option_texts(runner.next(&mut blackboard)),
+            vec!["Ask", "Leave"]
+        );
+    }
+
+    #[test]
+    fn jumps_to_labels_and_ends_early() {
+        let mut blackboard = Blackboard::headless();
+        let mut runner = DialogRunner::new(
+            track(&[
+                "$ jump later",
+                "Skipped",
+                "@label later",
+                "Later",
+                "$ end",
+                "Never",
+            ]),
+            0,
+        );
+        assert_eq!(text(runner.next(&mut blackboard)), "Later");
+        assert_eq!(runner.next(&mut blackboard), DialogEvent::Ended);
+    }
+
+    #[test]
+    fn requests_other_tracks() {
+        let mut blackboard = Blackboard::headless();
+        let mut runner = DialogRunner::new(track(&["$ jump other.json:start", "Never"]), 0);
+        assert_eq!(
+            runner.next(&mut blackboard),
+            DialogEvent::TrackRequested {
+                file: "res://other.json".to_string(),
+                label: Some("start".to_string()),
+            }
+        );
+        assert_eq!(runner.next(&mut blackboard), DialogEvent::Ended);
+    }
+
+    #[test]
+    fn reports_signals() {
+        let mut blackboard = Blackboard::headless();
+        let mut runner = DialogRunner::new(track(&["@signal shake 0.5", "After"]), 0);
+        assert_eq!(
+            runner.next(&mut blackboard),
+            DialogEvent::Signal {
+                name: "shake".to_string(),
+                args: vec!["0.5".to_string()],
+            }
+        );
+        assert_eq!(text(runner.next(&mut blackboard)), "After");
+    }
+
+    #[test]
+    fn resumes_from_a_position() {
+        let mut blackboard = Blackboard::headless();
+        let mut first = DialogRunner::new(
+            track(&["One", "@if true", "Two", "Three", "@end", "Four"]),
+            0,
+        );
+        first.next(&mut blackboard);
+        first.next(&mut blackboard);
+        assert_eq!(first.position(), vec![1, 0, 0]);
+        let mut resumed = DialogRunner::at(first.track().clone(), &first.position()).unwrap();
+        assert_eq!(text(resumed.next(&mut blackboard)), "Two");
+        assert_eq!(text(resumed.next(&mut blackboard)), "Three");
+        assert_eq!(text(resumed.next(&mut blackboard)), "Four");
+        assert!(DialogRunner::at(first.track().clone(), &[9]).is_none());
+    }
+
+    #[test]
+    fn resumes_a_pending_choice() {
+        let mut blackboard = Blackboard::headless();
+        let mut first = DialogRunner::new(track(&["One", "Pick", "-> A", "-> B"]), 0);
+        first.next(&mut blackboard);
+        first.next(&mut blackboard);
+        assert!(first.pending_choice().is_some());
+        let mut resumed = DialogRunner::at(first.track().clone(), &first.position()).unwrap();
+        assert!(resumed.resume_choice(&blackboard));
+        assert_eq!(resumed.position(), first.position());
+        assert_eq!(option_texts(resumed.next(&mut blackboard)), vec!["A", "B"]);
+        assert!(resumed.choose(&mut blackboard, 1).is_ok());
+        assert_eq!(resumed.next(&mut blackboard), DialogEvent::Ended);
+
+        let mut not_a_choice = DialogRunner::at(first.track().clone(), &[0]).unwrap();
+        assert!(!not_a_choice.resume_choice(&blackboard));
+        assert!(not_a_choice.pending_choice().is_none());
+        assert_eq!(text(not_a_choice.next(&mut blackboard)), "One");
+    }
+
+    #[test]
+    fn rejects_invalid_choices() {
+        let mut blackboard = Blackboard::headless();
+        let mut runner = DialogRunner::new(track(&["Pick", "-> A", "-> B"]), 0);
+        assert_eq!(
+            runner.choose(&mut blackboard, 0),
+            Err(RunnerError::NoChoicePending)
+        );
+        runner.next(&mut blackboard);
+        assert_eq!(
+            runner.choose(&mut blackboard, 2),
+            Err(RunnerError::InvalidOption(2))
+        );
+        assert!(runner.choose(&mut blackboard, 1).is_ok());
+    }
+
+    #[test]
+    fn malformed_queries_fail_with_a_warning() {
+        let mut blackboard = Blackboard::headless();
+        let mut runner = DialogRunner::new(track(&["[if gold >=] Broken", "Fine"]), 0);
+        assert_eq!(text(runner.next(&mut blackboard)), "Fine");
+        assert_eq!(runner.take_warnings().len(), 1);
+    }
+}
@@ -0,0 +1,487 @@
+//! A line-oriented "screenplay" format for dialog tracks, for writers who would rather not edit nested JSON. Files with the `.dialog` extension are loaded with this format.
+//!
+//! ```text
+//! # comments start with '#'
+//! @label intro
+//! Mara: Hello there, {{ player_name }}! ##mara_greeting
+//! Mara:worried: The tide is coming in.
+//! [if gold >= 5] Mara: You look like you have some coin to spare.
+//! This line has no character, it's narration.
+//! : Narration that contains a colon: start it with one.
+//! Mara: Where are you headed?
+//! -> The docks [has_boat] {set destination "docks"; jump docks}
+//! -> (once) Ask about the lighthouse {jump lighthouse}
+//! -> Nowhere in particular
+//! @timeout 5 1
+//! @if destination == "docks"
+//! Mara: Mind the gulls.
+//! @elif gold < 5
+//! Mara: Maybe earn some coin first.
+//! @else
+//! $ set destination "home"
+//! @end
+//! @signal camera_shake 0.5 "very strong"
+//! @voice res://voice/mara_goodbye.ogg
+//! Mara: Safe travels!
+//! $ set met_mara true
+//! ```
+//!
+//! - `Character: text` is a text line, `Character:expression: text` also picks the character's expression portrait. Character names and expressions can't contain spaces. `[if query]` in front of it makes it conditional
+//! - `-> option [requires] {action}` lines add options to a choice. The line before them becomes the choice prompt. Both `[requires]` and `{action}` are optional
+//! - options can start with the flags `(once)`, `(sticky)` and `(fallback)`, see [ChoiceOptionEntry]
+//! - `@signal name args` emits a track signal. Arguments are separated by spaces, use quotes for arguments containing spaces
+//! - `@label name` marks a jump target
+//! - `@timeout seconds default` after a choice's options selects the option at index `default` when the player takes longer than `seconds`
+//! - `@voice path` plays a voice-over clip with the next text line
+//! - `@if query`, `@elif query`, `@else` and `@end` wrap lines in a conditional branch. Branches can be nested, but cannot contain labels. Choice options carrying their own lines are only available in the JSON format
+//! - `$ action code` runs a blackboard action
+//! - text and option lines can end with `##some_id` to give them a stable translation key
+use super::dialog_track::{ChoiceOptionEntry, ChoiceTimeout, ConditionalBranch, Line};
+
+/// A parse error on a single line. `line` is 1-based
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScreenplayError {
+    pub line: usize,
+    pub reason: String,
+}
+
+/// An `@if` block that has not reached its `@end` yet
+struct OpenBranch {
+    /// the lines before the `@if`
+    parent: Vec<Line>,
+    branches: Vec<ConditionalBranch>,
+    /// the query of the branch currently being read, empty after `@else`
+    requires: String,
+    /// the line number of the `@if`
+    line: usize,
+}
+
+pub fn parse_screenplay(text: &str) -> Result<Vec<Line>, ScreenplayError> {
+    let mut lines: Vec<Line> = Vec::new();
+    let mut pending_voice: Option<String> = None;
+    let mut open_branches: Vec<OpenBranch> = Vec::new();
+    for (index, raw) in text.lines().enumerate() {
+        let line_number = index + 1;
+        let error = |reason: String| ScreenplayError {
+            line: line_number,
+            reason,
+        };
+        let trimmed = raw.trim();
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            continue;
+        }
+        if let Some(option) = trimmed.strip_prefix("->") {
+            let (option, id) = split_id(option);
+            let mut option = parse_option(option.trim()).map_err(error)?;
+            option.id = id;
+            push_option(&mut lines, option).map_err(error)?;
+        } else if let Some(code) = trimmed.strip_prefix('$') {
+            let action = code.trim().to_string();
+            if action.is_empty() {
+                return Err(error("action lines need code after '$'".to_string()));
+            }
+            lines.push(Line::Action { action });
+        } else if let Some(path) = trimmed.strip_prefix("@voice ") {
+            let path = path.trim();
+            if path.is_empty() || path.contains(char::is_whitespace) {
+                return Err(error("'@voice' needs exactly one path".to_string()));
+            }
+            pending_voice = Some(path.to_string());
+        } else if let Some(args) = trimmed.strip_prefix("@timeout ") {
+            set_timeout(&mut lines, args).map_err(error)?;
+        } else if let Some(requires) = trimmed.strip_prefix("@if ") {
+            open_branches.push(OpenBranch {
+                parent: std::mem::take(&mut lines),
+                branches: Vec::new(),
+                requires: parse_branch_query("@if", requires).map_err(error)?,
+                line: line_number,
+            });
+        } else if let Some(requires) = trimmed.strip_prefix("@elif ") {
+            let requires = parse_branch_query("@elif", requires).map_err(error)?;
+            next_branch(
+                &mut open_branches,
+                &mut lines,
+                &pending_voice,
+                "@elif",
+                requires,
+            )
+            .map_err(error)?;
+        } else if trimmed == "@else" {
+            next_branch(
+                &mut open_branches,
+                &mut lines,
+                &pending_voice,
+                "@else",
+                String::new(),
+            )
+            .map_err(error)?;
+        } else if trimmed == "@end" {
+            next_branch(
+                &mut open_branches,
+                &mut lines,
+                &pending_voice,
+                "@end",
+                String::new(),
+            )
+            .map_err(error)?;
+            let Some(open) = open_branches.pop() else {
+                return Err(error("'@end' without an '@if'".to_string()));
+            };
+            let branches = open.branches;
+            lines = open.parent;
+            lines.push(Line::Branch { branches });
+        } else if let Some(directive) = trimmed.strip_prefix('@') {
+            let line = parse_directive(directive).map_err(error)?;
+            if matches!(line, Line::Label { .. }) && !open_branches.is_empty() {
+                return Err(error(
+                    "labels cannot be placed inside of '@if' blocks".to_string(),
+                ));
+            }
+            lines.push(line);
+        } else {
+            let (text, id) = split_id(trimmed);
+            let voice = pending_voice.take().unwrap_or_default();
+            lines.push(parse_text(text, id, voice).map_err(error)?);
+        }
+    }
+    if pending_voice.is_some() {
+        return Err(ScreenplayError {
+            line: text.lines().count(),
+            reason: "'@voice' must be followed by a text line".to_string(),
+        });
+    }
+    if let Some(open) = open_branches.last() {
+        return Err(ScreenplayError {
+            line: open.line,
+            reason: "'@if' is missing its '@end'".to_string(),
+        });
+    }
+    Ok(lines)
+}
+
+fn parse_branch_query(directive: &str, requires: &str) -> Result<String, String> {
+    let requires = requires.trim();
+    if requires.is_empty() {
+        return Err(format!("'{}' needs a query", directive));
+    }
+    Ok(requires.to_string())
+}
+
+/// finishes the branch currently being read in the innermost `@if` block, starting a new one with `requires`
+fn next_branch(
+    open_branches: &mut [OpenBranch],
+    lines: &mut Vec<Line>,
+    pending_voice: &Option<String>,
+    directive: &str,
+    requires: String,
+) -> Result<(), String> {
+    let Some(open) = open_branches.last_mut() else {
+        return Err(format!("'{}' without an '@if'", directive));
+    };
+    if pending_voice.is_some() {
+        return Err("'@voice' must be followed by a text line".to_string());
+    }
+    if open.requires.is_empty() && directive != "@end" {
+        return Err(format!("'{}' cannot follow '@else'", directive));
+    }
+    open.branches.push(ConditionalBranch {
+        requires: std::mem::replace(&mut open.requires, requires),
+        lines: std::mem::take(lines),
+    });
+    Ok(())
+}
+
+fn parse_directive(directive: &str) -> Result<Line, String> {
+    let (name, rest) = directive
+        .split_once(char::is_whitespace)
+        .unwrap_or((directive, ""));
+    let rest = rest.trim();
+    match name {
+        "signal" => {
+            let mut args = split_args(rest)?;
+            if args.is_empty() {
+                return Err("'@signal' needs a signal name".to_string());
+            }
+            let name = args.remove(0);
+            Ok(Line::Signal { name, args })
+        }
+        "label" => {
+            if rest.is_empty() || rest.contains(char::is_whitespace) {
+                return Err("'@label' needs exactly one name".to_string());
+            }
+            Ok(Line::Label {
+                name: rest.to_string(),
+            })
+        }
+        _ => Err(format!("unknown directive '@{}'", name)),
+    }
+}
+
+/// splits a trailing `##id` off a line
+fn split_id(line: &str) -> (&str, String) {
+    let Some(start) = line.rfind("##") else {
+        return (line, String::new());
+    };
+    let id = &line[start + 2..];
+    let at_word_start = line[..start].ends_with(char::is_whitespace) || start == 0;
+    if id.is_empty() || id.contains(char::is_whitespace) || !at_word_start {
+        return (line, String::new());
+    }
+    (line[..start].trim_end(), id.to_string())
+}
+
+fn parse_text(line: &str, id: String, voice: String) -> Result<Line, String> {
+    let (requires, line) = match line.strip_prefix("[if ") {
+        Some(conditional) => {
+            let Some((requires, rest)) = conditional.split_once(']') else {
+                return Err("missing ']' after '[if'".to_string());
+            };
+            (requires.trim().to_string(), rest.trim())
+        }
+        None => (String::new(), line),
+    };
+    let (character, text) = split_character(line);
+    let text = text.trim().to_string();
+    if text.is_empty() {
+        return Err("text lines cannot be empty".to_string());
+    }
+    Ok(Line::Text {
+        text,
+        character,
+        requires,
+        id,
+        voice,
+    })
+}
+
+/// splits a `name:` or `name:expression:` prefix off a text line. Names and expressions can't contain whitespace, so a colon further into the text is left alone
+fn split_character(line: &str) -> (String, &str) {
+    let is_word = |c: char| !c.is_whitespace() && c != ':';
+    let name_end = line.find(|c| !is_word(c)).unwrap_or(line.len());
+    let Some(rest) = line[name_end..].strip_prefix(':') else {
+        return (String::new(), line);
+    };
+    let expression_end = rest.find(|c| !is_word(c)).unwrap_or(rest.len());
+    match rest[expression_end..].strip_prefix(':') {
+        Some(text) if name_end > 0 && expression_end > 0 => {
+            (line[..name_end + 1 + expression_end].to_string(), text)
+        }
+        _ => (line[..name_end].to_string(), rest),
+    }
+}
+
+fn parse_option(option: &str) -> Result<ChoiceOptionEntry, String> {
+    let mut text = option.trim();
+    let (mut once, mut sticky, mut fallback) = (false, false, false);
+    loop {
+        let flag = if let Some(rest) = text.strip_prefix("(once)") {
+            once = true;
+            rest
+        } else if let Some(rest) = text.strip_prefix("(sticky)") {
+            sticky = true;
+            rest
+        } else if let Some(rest) = text.strip_prefix("(fallback)") {
+            fallback = true;
+            rest
+        } else {
+            break;
+        };
+        text = flag.trim_start();
+    }
+    let mut action = String::new();
+    let mut requires = String::new();
+    if text.ends_with('}') {
+        let Some(start) = text.rfind('{') else {
+            return Err("missing '{' before the option action".to_string());
+        };
+        action = text[start + 1..text.len() - 1].trim().to_string();
+        text = text[..start].trim();
+    }
+    // a trailing BBCode closing tag like [/b] is part of the text, not a requirement
+    if text.ends_with(']') {
+        if let Some(start) = text.rfind('[') {
+            if !text[start..].starts_with("[/") {
+                requires = text[start + 1..text.len() - 1].trim().to_string();
+                text = text[..start].trim();
+            }
+        }
+    }
+    if text.is_empty() {
+        return Err("choice options need text".to_string());
+    }
+    Ok(ChoiceOptionEntry {
+        text: text.to_string(),
+        requires,
+        action,
+        id: String::new(),
+        once,
+        sticky,
+        fallback,
+        nodes: Vec::new(),
+    })
+}
+
+/// adds the option to the choice before it, turning the preceding text line into the choice prompt if needed
+fn push_option(lines: &mut Vec<Line>, option: ChoiceOptionEntry) -> Result<(), String> {
+    match lines.pop() {
+        Some(Line::Choice {
+            prompt,
+            character,
+            mut options,
+            id,
+            voice,
+            timeout,
+        }) => {
+            if timeout.is_some() {
+                return Err("options cannot be added after '@timeout'".to_string());
+            }
+            options.push(option);
+            lines.push(Line::Choice {
+                prompt,
+                character,
+                options,
+                id,
+                voice,
+                timeout,
+            });
+            Ok(())
+        }
+        Some(Line::Text {
+            text,
+            character,
+            requires,
+            id,
+            voice,
+        }) if requires.is_empty() => {
+            lines.push(Line::Choice {
+                prompt: text,
+                character,
+                options: vec![option],
+                id,
+                voice,
+                timeout: None,
+            });
+            Ok(())
+        }
+        Some(Line::Text { .. }) => Err("a conditional line cannot be a choice prompt".to_string()),
+        _ => Err("choice options must follow a prompt line".to_string()),
+    }
+}
+
+/// adds a time limit to the choice right before the directive
+fn set_timeout(lines: &mut [Line], args: &str) -> Result<(), String> {
+    let Some(Line::Choice {
+        options, timeout, ..
+    }) = lines.last_mut()
+    else {
+        return Err("'@timeout' must follow the options of a choice".to_string());
+    };
+    let parts: Vec<&str> = args.split_whitespace().collect();
+    let (seconds, default) = match parts.as_slice() {
+        [seconds] => (*seconds, "0"),
+        [seconds, default] => (*seconds, *default),
+        _ => {
+            return Err(
+                "'@timeout' needs the seconds and optionally a default option index".to_string(),
+            )
+        }
+    };
+    let seconds = seconds
+        .parse::<f64>()
+        .map_err(|_| format!("invalid timeout '{}'", seconds))?;
+    let default = default
+        .parse::<i64>()
+        .map_err(|_| format!("invalid default option '{}'", default))?;
+    *timeout = Some(ChoiceTimeout::new(seconds, default, options.len())?);
+    Ok(())
+}
+
+/// splits arguments on whitespace, keeping quoted arguments (quotes included) together
+fn split_args(args: &str) -> Result<Vec<String>, String> {
+    let mut result = Vec::new();
+    let mut current = String::new();
+    let mut in_quotes = false;
+    for c in args.chars() {
+        match c {
+            '"' => {
+                in_quotes = !in_quotes;
+                current.push(c);
+            }
+            c if c.is_whitespace() && !in_quotes => {
+                if !current.is_empty() {
+                    result.push(std::mem::take(&mut current));
+                }
+            }
+            c => current.push(c),
+        }
+    }
+    if in_quotes {
+        return Err("unterminated quote in arguments".to_string());
+    }
+    if !current.is_empty() {
+        result.push(current);
+    }
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn speaker_and_text(line: &str) -> (String, String) {
+        match parse_screenplay(line)
+            .expect("test line should parse")
+            .as_slice()
+        {
+            [Line::Text {
+                character, text, ..
+            }] => (character.clone(), text.clone()),
+            other => panic!("expected a single text line, found {:?}", other),
+        }
+    }
+
+    #[test]
+    fn text_lines_take_a_character_prefix() {
+        assert_eq!(
+            speaker_and_text("Mara: Hello there"),
+            ("Mara".to_string(), "Hello there".to_string())
+        );
+        assert_eq!(
+            speaker_and_text("Mara:Hello"),
+            ("Mara".to_string(), "Hello".to_string())
+        );
+        // a colon later in the text does not start an expression
+        assert_eq!(
+            speaker_and_text("Mara: Note: the tide"),
+            ("Mara".to_string(), "Note: the tide".to_string())
+        );
+    }
+
+    #[test]
+    fn text_lines_take_a_character_expression_prefix() {
+        assert_eq!(
+            speaker_and_text("mara:angry: Get off my boat!"),
+            ("mara:angry".to_string(), "Get off my boat!".to_string())
+        );
+        assert_eq!(
+            speaker_and_text("[if gold < 5] mara:sad:Pity."),
+            ("mara:sad".to_string(), "Pity.".to_string())
+        );
+    }
+
+    #[test]
+    fn text_lines_without_a_prefix_are_narration() {
+        assert_eq!(
+            speaker_and_text("The gulls cry."),
+            (String::new(), "The gulls cry.".to_string())
+        );
+        assert_eq!(
+            speaker_and_text(": Narration with a colon: here"),
+            (String::new(), "Narration with a colon: here".to_string())
+        );
+        assert_eq!(
+            speaker_and_text("Three words here: not a name"),
+            (String::new(), "Three words here: not a name".to_string())
+        );
+    }
+}
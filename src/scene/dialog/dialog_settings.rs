@@ -1,4 +1,9 @@
-use godot::{engine::LabelSettings, prelude::*};
+use godot::{
+    engine::{LabelSettings, Theme},
+    prelude::*,
+};
+
+use super::dialog_character::DialogCharacter;
 
 #[derive(GodotConvert, Var, Default, Export, Clone)]
 #[godot(via=i64)]
@@ -54,6 +59,14 @@ pub enum ETransType {
 #[derive(GodotClass)]
 #[class(base=Resource)]
 pub struct DialogSettings {
+    /// A scene to use for the dialog GUI instead of the default layout. Its root must be a Control containing a RichTextLabel with the unique name `%DialogText`. `%CharacterName` (Label), `%Portrait` (TextureRect) and `%Choices` (a Control that choice buttons are added to) are optional. The root can define `show_dialog()` and `hide_dialog() -> float` (returning the hide duration in seconds) to replace the default animations
+    #[export]
+    pub gui_scene: Option<Gd<PackedScene>>,
+
+    /// A theme applied to the dialog GUI, its choice buttons, and the history panel
+    #[export]
+    pub theme: Option<Gd<Theme>>,
+
     #[export]
     pub character_name_label_style: Option<Gd<LabelSettings>>,
 
@@ -66,6 +79,26 @@ pub struct DialogSettings {
     #[export]
     pub interact_action: GString,
 
+    /// The input action that opens and closes the dialog history panel. Leave empty to disable the panel
+    #[export]
+    pub history_action: GString,
+
+    /// The input action that toggles auto mode. Leave empty to disable
+    #[export]
+    pub auto_action: GString,
+
+    /// The input action that toggles skipping already seen lines. Leave empty to disable
+    #[export]
+    pub skip_action: GString,
+
+    /// Seconds auto mode waits after a line is revealed, before adding [DialogSettings::auto_delay_per_word]
+    #[export]
+    pub auto_delay_base: f32,
+
+    /// Extra seconds auto mode waits for each word of the line
+    #[export]
+    pub auto_delay_per_word: f32,
+
     #[export]
     pub anim_appear_ease: EEaseType,
 
@@ -90,9 +123,41 @@ pub struct DialogSettings {
     #[export]
     pub choice_buttons_align: DialogAlign,
 
+    /// Tints the buttons of `sticky` options that were already chosen
+    #[export]
+    pub chosen_option_modulate: Color,
+
+    /// A theme type variation for the buttons of `sticky` options that were already chosen. Leave empty to only tint them
+    #[export]
+    pub chosen_option_theme_variation: GString,
+
     #[export]
     pub words_per_minute: f32,
 
+    /// Characters that lines can refer to by id, see [DialogCharacter]
+    #[export]
+    pub characters: Array<Gd<DialogCharacter>>,
+
+    /// How long each bark line stays visible after it would have been read at [DialogSettings::words_per_minute]
+    #[export]
+    pub bark_linger_duration: f32,
+
+    /// Seconds after a bark is hidden before the same speaker can bark again
+    #[export]
+    pub bark_cooldown: f32,
+
+    /// How far above the speaker's origin barks are displayed
+    #[export]
+    pub bark_height_offset: f32,
+
+    /// The audio bus voice-over clips are played on
+    #[export]
+    pub voice_bus: StringName,
+
+    /// When enabled, lines with a voice-over clip advance on their own once the clip finishes
+    #[export]
+    pub voice_auto_advance: bool,
+
     base: Base<Resource>,
 }
 
@@ -102,9 +167,16 @@ impl IResource for DialogSettings {
         Self {
             base,
             dialog_font_size: 22u32,
+            gui_scene: None,
+            theme: None,
             character_name_label_style: None,
             dialog_align: DialogAlign::Center,
             interact_action: "interact".to_godot(),
+            history_action: GString::new(),
+            auto_action: GString::new(),
+            skip_action: GString::new(),
+            auto_delay_base: 1f32,
+            auto_delay_per_word: 0.3f32,
             anim_appear_duration: 1f32,
             anim_hide_duration: 1f32,
             anim_appear_ease: Default::default(),
@@ -113,7 +185,32 @@ impl IResource for DialogSettings {
             anim_hide_trans: Default::default(),
             auto_focus_choice_buttons: true,
             choice_buttons_align: DialogAlign::Center,
+            chosen_option_modulate: Color::from_rgb(0.7, 0.7, 0.7),
+            chosen_option_theme_variation: GString::new(),
             words_per_minute: 150f32,
+            characters: Array::new(),
+            bark_linger_duration: 1.5f32,
+            bark_cooldown: 5f32,
+            bark_height_offset: 2f32,
+            voice_bus: StringName::from("Master"),
+            voice_auto_advance: false,
+        }
+    }
+}
+
+impl DialogSettings {
+    /// Finds the registered character for a line's `character` field, which is either `id` or `id:expression`. Returns the character and the expression (empty if none was given)
+    pub fn find_character(&self, reference: &str) -> Option<(Gd<DialogCharacter>, String)> {
+        let (id, expression) = reference
+            .split_once(':')
+            .map(|(id, expression)| (id.trim(), expression.trim()))
+            .unwrap_or((reference.trim(), ""));
+        if id.is_empty() {
+            return None;
         }
+        self.characters
+            .iter_shared()
+            .find(|character| character.bind().id.to_string() == id)
+            .map(|character| (character, expression.to_string()))
     }
 }
@@ -0,0 +1,590 @@
+//! The `{{ }}` templates of dialog text (character names, lines, prompts, and option texts)
+//!
+//! Supported syntax:
+//! - values: `{{ gold }}`, any blackboard key including the read-only `settings.` and `project.` keys
+//! - filters, applied left to right: `{{ name | upper }}`, `{{ coins | int }}`, `{{ missing | default: "stranger" }}`
+//! - filter arguments: quoted strings (`"two words"` or `'two words'`), numbers, `true`, `false`, separated by commas
+//!
+//! Filters:
+//! - `int`: drops the fraction of a number
+//! - `upper`, `lower`: changes the case of the text
+//! - `default: value`: used when the key does not exist
+//! - `join: separator`: joins the items of a list
+//! - `plural: "coin", "coins"`: picks the form for the number using the plural rules of the active locale, forms are given in the order the locale uses (e.g. one, few, many for Russian). Fractions pick the last form
+use std::fmt;
+
+use super::dialog_blackboard::Entry;
+
+/// An error found while parsing a template. `position` is the 0-based character offset into the text
+#[derive(Debug, Clone, PartialEq)]
+pub struct TemplateError {
+    pub position: usize,
+    pub message: String,
+}
+
+impl TemplateError {
+    fn new(position: usize, message: impl Into<String>) -> Self {
+        Self {
+            position,
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for TemplateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} (at column {})", self.message, self.position + 1)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Filter {
+    Int,
+    Upper,
+    Lower,
+    Default(Entry),
+    Join(String),
+    Plural(Vec<String>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Part {
+    Text(String),
+    Value { key: String, filters: Vec<Filter> },
+}
+
+/// A parsed text with `{{ key | filter }}` templates
+#[derive(Debug, Clone, PartialEq)]
+pub struct Template {
+    parts: Vec<Part>,
+}
+
+impl Template {
+    pub fn parse(text: &str) -> Result<Self, TemplateError> {
+        let chars: Vec<char> = text.chars().collect();
+        let mut parts = Vec::new();
+        let mut literal = String::new();
+        let mut index = 0;
+        while index < chars.len() {
+            if !starts_with(&chars, index, "{{") {
+                literal.push(chars[index]);
+                index += 1;
+                continue;
+            }
+            if !literal.is_empty() {
+                parts.push(Part::Text(std::mem::take(&mut literal)));
+            }
+            let (part, end) = parse_value(&chars, index)?;
+            parts.push(part);
+            index = end;
+        }
+        if !literal.is_empty() {
+            parts.push(Part::Text(literal));
+        }
+        Ok(Self { parts })
+    }
+
+    /// The keys read by the template, leaving out keys with a `default` filter since they don't need to be set
+    pub fn required_keys(&self) -> Vec<&str> {
+        self.parts
+            .iter()
+            .filter_map(|part| match part {
+                Part::Value { key, filters }
+                    if !filters
+                        .iter()
+                        .any(|filter| matches!(filter, Filter::Default(_))) =>
+                {
+                    Some(key.as_str())
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Whether rendering needs the active locale
+    pub fn uses_plural(&self) -> bool {
+        self.parts.iter().any(|part| match part {
+            Part::Value { filters, .. } => filters
+                .iter()
+                .any(|filter| matches!(filter, Filter::Plural(_))),
+            _ => false,
+        })
+    }
+
+    /// Fills in the values found with `lookup`. Missing values and filters that don't apply to their value are reported in `warnings`
+    pub fn render(
+        &self,
+        lookup: impl Fn(&str) -> Option<Entry>,
+        locale: &str,
+        warnings: &mut Vec<String>,
+    ) -> String {
+        let mut buffer = String::new();
+        for part in self.parts.iter() {
+            let (key, filters) = match part {
+                Part::Text(text) => {
+                    buffer += text;
+                    continue;
+                }
+                Part::Value { key, filters } => (key, filters),
+            };
+            let mut value = lookup(key).unwrap_or_default();
+            for filter in filters.iter() {
+                value = filter.apply(key, value, locale, warnings);
+            }
+            if value == Entry::None {
+                warnings.push(format!("Found null when parsing dialog key: {}", key));
+                continue;
+            }
+            buffer += value.to_string().as_str();
+        }
+        buffer
+    }
+}
+
+impl Filter {
+    fn parse(name: &str, args: Vec<Entry>, position: usize) -> Result<Self, TemplateError> {
+        let strings = || -> Option<Vec<String>> {
+            args.iter()
+                .map(|arg| match arg {
+                    Entry::String(text) => Some(text.clone()),
+                    _ => None,
+                })
+                .collect()
+        };
+        let filter = match (name, args.len()) {
+            ("int", 0) => Filter::Int,
+            ("upper", 0) => Filter::Upper,
+            ("lower", 0) => Filter::Lower,
+            ("default", 1) => Filter::Default(args[0].clone()),
+            ("join", 1) => match &args[0] {
+                Entry::String(separator) => Filter::Join(separator.clone()),
+                _ => {
+                    return Err(TemplateError::new(
+                        position,
+                        "the separator of \"join\" must be a string",
+                    ))
+                }
+            },
+            ("plural", count) if count > 0 => Filter::Plural(strings().ok_or_else(|| {
+                TemplateError::new(position, "the forms of \"plural\" must be strings")
+            })?),
+            ("int" | "upper" | "lower", _) => {
+                return Err(TemplateError::new(
+                    position,
+                    format!("filter \"{}\" takes no arguments", name),
+                ))
+            }
+            ("default" | "join", _) => {
+                return Err(TemplateError::new(
+                    position,
+                    format!("filter \"{}\" takes one argument", name),
+                ))
+            }
+            ("plural", _) => {
+                return Err(TemplateError::new(
+                    position,
+                    "filter \"plural\" needs at least one form",
+                ))
+            }
+            _ => {
+                return Err(TemplateError::new(
+                    position,
+                    format!("unknown filter \"{}\"", name),
+                ))
+            }
+        };
+        Ok(filter)
+    }
+
+    fn apply(&self, key: &str, value: Entry, locale: &str, warnings: &mut Vec<String>) -> Entry {
+        match (self, value) {
+            (Filter::Default(default), Entry::None) => default.clone(),
+            (_, Entry::None) => Entry::None,
+            (Filter::Default(_), value) => value,
+            (Filter::Int, Entry::Number(val)) => Entry::Int(val as i64),
+            (Filter::Int, Entry::Int(val)) => Entry::Int(val),
+            (Filter::Upper, value) => Entry::String(value.to_string().to_uppercase()),
+            (Filter::Lower, value) => Entry::String(value.to_string().to_lowercase()),
+            (Filter::Join(separator), Entry::List(items)) => Entry::String(
+                items
+                    .iter()
+                    .map(|item| item.to_string())
+                    .collect::<Vec<String>>()
+                    .join(separator),
+            ),
+            (Filter::Plural(forms), Entry::Int(val)) => {
+                Entry::String(pick_form(forms, plural_index(locale, val as f64)))
+            }
+            (Filter::Plural(forms), Entry::Number(val)) => {
+                Entry::String(pick_form(forms, plural_index(locale, val as f64)))
+            }
+            (filter, value) => {
+                warnings.push(format!(
+                    "Cannot apply the filter {} to the {} \"{}\"",
+                    filter.name(),
+                    value.type_name(),
+                    key
+                ));
+                value
+            }
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Filter::Int => "int",
+            Filter::Upper => "upper",
+            Filter::Lower => "lower",
+            Filter::Default(_) => "default",
+            Filter::Join(_) => "join",
+            Filter::Plural(_) => "plural",
+        }
+    }
+}
+
+fn pick_form(forms: &[String], index: usize) -> String {
+    forms
+        .get(index)
+        .or(forms.last())
+        .cloned()
+        .unwrap_or_default()
+}
+
+/// The index of the plural form for `count` in the language of `locale` (e.g. "en_US"), following the usual gettext rules. Non-whole counts give `usize::MAX`, which picks the last form
+pub fn plural_index(locale: &str, count: f64) -> usize {
+    let language = locale
+        .split(['_', '-'])
+        .next()
+        .unwrap_or_default()
+        .to_lowercase();
+    let count = count.abs();
+    if language == "fr" {
+        // French uses the singular for 0 and anything below 2
+        return if count < 2.0 { 0 } else { 1 };
+    }
+    if count.fract() != 0.0 {
+        return usize::MAX;
+    }
+    let whole = count as u64;
+    let (mod10, mod100) = (whole % 10, whole % 100);
+    let few = (2..=4).contains(&mod10) && !(12..=14).contains(&mod100);
+    match language.as_str() {
+        "ja" | "zh" | "ko" | "vi" | "th" | "id" | "ms" => 0,
+        "ru" | "uk" | "be" | "sr" | "hr" | "bs" if mod10 == 1 && mod100 != 11 => 0,
+        "pl" if whole == 1 => 0,
+        "ru" | "uk" | "be" | "sr" | "hr" | "bs" | "pl" if few => 1,
+        "ru" | "uk" | "be" | "sr" | "hr" | "bs" | "pl" => 2,
+        "cs" | "sk" => match whole {
+            1 => 0,
+            2..=4 => 1,
+            _ => 2,
+        },
+        _ => usize::from(whole != 1),
+    }
+}
+
+fn starts_with(chars: &[char], index: usize, pattern: &str) -> bool {
+    chars[index..]
+        .iter()
+        .copied()
+        .take(pattern.len())
+        .eq(pattern.chars())
+}
+
+/// reads a `{{ key | filter: args }}` value starting at the `{{` at `start`, returning it and the index after the closing `}}`
+fn parse_value(chars: &[char], start: usize) -> Result<(Part, usize), TemplateError> {
+    // split into the key and the filters, ignoring separators inside quotes
+    let mut segments = vec![(start + 2, String::new())];
+    let mut index = start + 2;
+    let mut quote = None;
+    loop {
+        let Some(&c) = chars.get(index) else {
+            return Err(TemplateError::new(start, "unclosed \"{{\""));
+        };
+        let segment = &mut segments.last_mut().unwrap().1;
+        match (quote, c) {
+            (Some(_), '\\') => {
+                segment.push(c);
+                if let Some(&escaped) = chars.get(index + 1) {
+                    segment.push(escaped);
+                    index += 1;
+                }
+            }
+            (Some(open), c) if c == open => {
+                segment.push(c);
+                quote = None;
+            }
+            (Some(_), c) => segment.push(c),
+            (None, '"' | '\'') => {
+                segment.push(c);
+                quote = Some(c);
+            }
+            (None, '|') => segments.push((index + 1, String::new())),
+            (None, '}') if chars.get(index + 1) == Some(&'}') => break,
+            (None, '{') if chars.get(index + 1) == Some(&'{') => {
+                return Err(TemplateError::new(index, "nested \"{{\""));
+            }
+            (None, c) => segment.push(c),
+        }
+        index += 1;
+    }
+    let mut segments = segments.into_iter();
+    let (key_position, key) = segments.next().unwrap();
+    let key = key.trim();
+    if key.is_empty() {
+        return Err(TemplateError::new(key_position, "missing key"));
+    }
+    if key.contains(char::is_whitespace) {
+        return Err(TemplateError::new(
+            key_position,
+            format!("\"{}\" is not a single key", key),
+        ));
+    }
+    let filters = segments
+        .map(|(position, filter)| parse_filter(&filter, position))
+        .collect::<Result<Vec<Filter>, TemplateError>>()?;
+    let part = Part::Value {
+        key: key.to_string(),
+        filters,
+    };
+    Ok((part, index + 2))
+}
+
+fn parse_filter(text: &str, position: usize) -> Result<Filter, TemplateError> {
+    let (name, args) = match text.split_once(':') {
+        Some((name, args)) => (name.trim(), parse_args(args, position)?),
+        None => (text.trim(), Vec::new()),
+    };
+    if name.is_empty() {
+        return Err(TemplateError::new(position, "missing filter name"));
+    }
+    Filter::parse(name, args, position)
+}
+
+/// reads comma separated quoted strings, numbers, and bools
+fn parse_args(text: &str, position: usize) -> Result<Vec<Entry>, TemplateError> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut args = Vec::new();
+    let mut index = 0;
+    loop {
+        while chars.get(index).is_some_and(|c| c.is_whitespace()) {
+            index += 1;
+        }
+        let arg = match chars.get(index) {
+            Some(&quote @ ('"' | '\'')) => {
+                let mut buffer = String::new();
+                index += 1;
+                loop {
+                    match chars.get(index) {
+                        None => return Err(TemplateError::new(position, "unterminated string")),
+                        Some('\\') => {
+                            buffer.extend(chars.get(index + 1).map(|escaped| match escaped {
+                                'n' => '\n',
+                                't' => '\t',
+                                other => *other,
+                            }));
+                            index += 2;
+                        }
+                        Some(&c) if c == quote => {
+                            index += 1;
+                            break;
+                        }
+                        Some(&c) => {
+                            buffer.push(c);
+                            index += 1;
+                        }
+                    }
+                }
+                Entry::String(buffer)
+            }
+            _ => {
+                let end = chars[index..]
+                    .iter()
+                    .position(|c| *c == ',')
+                    .map_or(chars.len(), |offset| index + offset);
+                let word: String = chars[index..end].iter().collect();
+                let word = word.trim();
+                index = end;
+                match word {
+                    "true" => Entry::Bool(true),
+                    "false" => Entry::Bool(false),
+                    _ => match (word.parse::<i64>(), word.parse::<f32>()) {
+                        (Ok(val), _) => Entry::Int(val),
+                        (_, Ok(val)) if val.is_finite() => Entry::Number(val),
+                        _ => {
+                            return Err(TemplateError::new(
+                                position,
+                                format!(
+                                    "expected a quoted string, number, or bool, found \"{}\"",
+                                    word
+                                ),
+                            ))
+                        }
+                    },
+                }
+            }
+        };
+        args.push(arg);
+        while chars.get(index).is_some_and(|c| c.is_whitespace()) {
+            index += 1;
+        }
+        match chars.get(index) {
+            None => return Ok(args),
+            Some(',') => index += 1,
+            Some(c) => {
+                return Err(TemplateError::new(
+                    position,
+                    format!("unexpected '{}' after an argument", c),
+                ))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    fn string(text: &str) -> Entry {
+        Entry::String(text.to_string())
+    }
+
+    /// Renders `text` against fixed entries, returning the output and the warnings
+    fn render(text: &str, locale: &str) -> (String, Vec<String>) {
+        let entries = HashMap::from([
+            ("name", string("Mara")),
+            ("coins", Entry::Number(3.7)),
+            ("gold", Entry::Int(21)),
+            (
+                "visited",
+                Entry::List(vec![string("docks"), string("lighthouse")]),
+            ),
+        ]);
+        let template = Template::parse(text).expect("test template should parse");
+        let mut warnings = Vec::new();
+        let rendered = template.render(|key| entries.get(key).cloned(), locale, &mut warnings);
+        (rendered, warnings)
+    }
+
+    #[test]
+    fn fills_in_values() {
+        assert_eq!(
+            render("{{ name }} has {{gold}} gold", "en"),
+            ("Mara has 21 gold".to_string(), Vec::new())
+        );
+        assert_eq!(
+            render("{{ visited }}", "en").0,
+            "docks, lighthouse".to_string()
+        );
+    }
+
+    #[test]
+    fn applies_each_filter() {
+        assert_eq!(render("{{ coins | int }}", "en").0, "3");
+        assert_eq!(render("{{ gold | int }}", "en").0, "21");
+        assert_eq!(render("{{ name | upper }}", "en").0, "MARA");
+        assert_eq!(render("{{ name | lower }}", "en").0, "mara");
+        assert_eq!(render("{{ name | default: \"stranger\" }}", "en").0, "Mara");
+        assert_eq!(
+            render("{{ visited | join: \" & \" }}", "en").0,
+            "docks & lighthouse"
+        );
+        assert_eq!(
+            render("{{ gold | plural: \"coin\", \"coins\" }}", "en").0,
+            "coins"
+        );
+        // filters apply left to right
+        assert_eq!(
+            render("{{ name | upper | lower | default: 'x' }}", "en").0,
+            "mara"
+        );
+    }
+
+    #[test]
+    fn default_fills_in_missing_keys() {
+        assert_eq!(
+            render("{{ stranger | default: \"nobody | else\" | upper }}", "en"),
+            ("NOBODY | ELSE".to_string(), Vec::new())
+        );
+        assert_eq!(render("{{ stranger | default: 5 }}", "en").0, "5");
+        let (rendered, warnings) = render("Hello {{ stranger | upper }}!", "en");
+        assert_eq!(rendered, "Hello !");
+        assert_eq!(warnings.len(), 1);
+    }
+
+    #[test]
+    fn filters_that_do_not_apply_keep_the_value() {
+        let (rendered, warnings) = render("{{ name | join: \", \" }} {{ visited | int }}", "en");
+        assert_eq!(rendered, "Mara docks, lighthouse");
+        assert_eq!(warnings.len(), 2);
+    }
+
+    #[test]
+    fn plural_follows_the_locale() {
+        let forms = "{{ gold | plural: \"one\", \"few\", \"many\" }}";
+        assert_eq!(render(forms, "en_US").0, "few");
+        // 21 takes the singular form in Russian, and the "many" form in Polish
+        assert_eq!(render(forms, "ru_RU").0, "one");
+        assert_eq!(render(forms, "pl").0, "many");
+        assert_eq!(render(forms, "ja_JP").0, "one");
+        // fractions pick the last form
+        assert_eq!(
+            render("{{ coins | plural: \"one\", \"few\", \"many\" }}", "ru").0,
+            "many"
+        );
+        // missing forms fall back to the last one given
+        assert_eq!(render("{{ gold | plural: \"coin\" }}", "en").0, "coin");
+        assert!(Template::parse(forms).unwrap().uses_plural());
+        assert!(!Template::parse("{{ gold | int }}").unwrap().uses_plural());
+    }
+
+    #[test]
+    fn plural_index_uses_the_rules_of_the_language() {
+        assert_eq!(plural_index("en", 1.0), 0);
+        assert_eq!(plural_index("en", 2.0), 1);
+        assert_eq!(plural_index("fr", 0.0), 0);
+        assert_eq!(plural_index("fr_CA", 1.5), 0);
+        assert_eq!(plural_index("ru", 22.0), 1);
+        assert_eq!(plural_index("ru", 11.0), 2);
+        assert_eq!(plural_index("pl", 21.0), 2);
+        assert_eq!(plural_index("cs", 3.0), 1);
+        assert_eq!(plural_index("ja", 5.0), 0);
+        assert_eq!(plural_index("en", 2.5), usize::MAX);
+    }
+
+    #[test]
+    fn rejects_malformed_templates() {
+        for text in [
+            "Hello {{ name",
+            "{{ name | shout }}",
+            "{{ | upper }}",
+            "{{ two keys }}",
+            "{{ outer {{ inner }} }}",
+            "{{ name | upper: 1 }}",
+            "{{ visited | join }}",
+            "{{ visited | join: 1 }}",
+            "{{ coins | plural: coin }}",
+            "{{ coins | plural: 1 }}",
+            "{{ name | default: 'unterminated }}",
+        ] {
+            assert!(
+                Template::parse(text).is_err(),
+                "{:?} should not parse",
+                text
+            );
+        }
+        assert_eq!(
+            Template::parse("{{ name | shout }}"),
+            Err(TemplateError::new(9, "unknown filter \"shout\""))
+        );
+    }
+
+    #[test]
+    fn required_keys_leave_out_defaults() {
+        let template =
+            Template::parse("{{ name }} {{ stranger | default: 'x' }} {{ gold | int }}").unwrap();
+        assert_eq!(template.required_keys(), vec!["name", "gold"]);
+    }
+}
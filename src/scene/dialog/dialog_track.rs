@@ -1,29 +1,132 @@
-use std::fmt;
+use std::{collections::HashMap, fmt};
 
 use godot::{
-    engine::{file_access::ModeFlags, global::Error, FileAccess, Json},
+    engine::{file_access::ModeFlags, global::Error, DirAccess, FileAccess, Json, ResourceLoader},
     prelude::*,
 };
 
+use super::{dialog_blackboard::Blackboard, dialog_screenplay::parse_screenplay};
+
 #[derive(Debug, Clone)]
 pub struct DialogTrack {
     pub lines: Vec<Line>,
+    /// maps label names to the index of their `label` node in [DialogTrack::lines]
+    pub labels: HashMap<String, usize>,
+    /// the file this track was loaded from (or a placeholder for internal tracks)
+    pub file: String,
+    /// the dictionary this track was parsed from, `None` for screenplays and tracks built from lines
+    pub source: Option<Dictionary>,
 }
 
 impl DialogTrack {
+    /// File extensions recognized as dialog tracks when scanning folders
+    pub const TRACK_EXTENSIONS: &'static [&'static str] = &["json", Self::SCREENPLAY_EXTENSION];
+    /// Files with this extension are parsed as screenplays, see [super::dialog_screenplay]
+    pub const SCREENPLAY_EXTENSION: &'static str = "dialog";
+
+    /// Recursively finds all dialog track files inside of a folder
+    pub fn find_track_files(folder: GString) -> Vec<GString> {
+        let mut found = Vec::new();
+        let Some(mut dir) = DirAccess::open(folder.clone()) else {
+            godot_warn!("Failed to open dialog track folder: {}", folder);
+            return found;
+        };
+        let base = folder.to_string();
+        for file in dir.get_files().as_slice() {
+            let name = file.to_string();
+            if Self::TRACK_EXTENSIONS
+                .iter()
+                .any(|ext| name.ends_with(format!(".{}", ext).as_str()))
+            {
+                found.push(Self::join_path(&base, &name).to_godot());
+            }
+        }
+        for sub_dir in dir.get_directories().as_slice() {
+            found.extend(Self::find_track_files(
+                Self::join_path(&base, &sub_dir.to_string()).to_godot(),
+            ));
+        }
+        found
+    }
+
+    fn join_path(base: &str, name: &str) -> String {
+        if base.ends_with('/') {
+            format!("{}{}", base, name)
+        } else {
+            format!("{}/{}", base, name)
+        }
+    }
+
+    /// Loads a track file, picking the parser from the file extension. `.dialog` files are screenplays, everything else is JSON
+    pub fn load_from_file(file_path: GString) -> Result<Self, DialogError> {
+        if Self::is_screenplay_file(&file_path) {
+            let text = Self::read_file(file_path.clone())?;
+            Self::load_from_screenplay(text, file_path)
+        } else {
+            Self::load_from_json(file_path)
+        }
+    }
+
+    fn is_screenplay_file(file_path: &GString) -> bool {
+        file_path
+            .to_string()
+            .ends_with(format!(".{}", Self::SCREENPLAY_EXTENSION).as_str())
+    }
+
     pub fn load_from_json(file_path: GString) -> Result<Self, DialogError> {
+        let text = Self::read_file(file_path.clone())?;
+        Self::load_from_text(text, file_path)
+    }
+
+    pub fn load_from_screenplay(text: GString, file_path: GString) -> Result<Self, DialogError> {
+        let zelf = Self::parse_screenplay_text(text, file_path)?;
+        zelf.validate()?;
+        Ok(zelf)
+    }
+
+    fn parse_screenplay_text(text: GString, file_path: GString) -> Result<Self, DialogError> {
+        let lines = parse_screenplay(text.to_string().as_str()).map_err(|err| {
+            DialogError::ScreenplayParseError {
+                file: file_path.to_string(),
+                line: err.line,
+                reason: err.reason,
+            }
+        })?;
+        Self::from_lines(lines, file_path.to_string().as_str())
+    }
+
+    pub fn load_from_text(text: GString, file_path: GString) -> Result<Self, DialogError> {
+        let dict = Self::parse_json_text(text, file_path.clone())?;
+        Self::load_from_dict(dict, file_path)
+    }
+
+    pub fn load_from_dict(dict: Dictionary, file_path: GString) -> Result<Self, DialogError> {
+        let zelf = Self::parse_dict(dict, file_path)?;
+        zelf.validate()?;
+        Ok(zelf)
+    }
+
+    /// Loads a track file without validating its jumps. Used for checking cross-track jump targets without recursing through every track they reference.
+    fn load_unvalidated(file_path: GString) -> Result<Self, DialogError> {
+        let text = Self::read_file(file_path.clone())?;
+        if Self::is_screenplay_file(&file_path) {
+            return Self::parse_screenplay_text(text, file_path);
+        }
+        let dict = Self::parse_json_text(text, file_path.clone())?;
+        Self::parse_dict(dict, file_path)
+    }
+
+    fn read_file(file_path: GString) -> Result<GString, DialogError> {
         let Some(file) = FileAccess::open(file_path.clone(), ModeFlags::READ) else {
             return Err(DialogError::CannotOpenFile {
                 file: file_path.to_string(),
                 reason: FileAccess::get_open_error(),
             });
         };
-        let text = file.get_as_text();
-        Self::load_from_text(text, file_path)
+        Ok(file.get_as_text())
     }
-    //    pub fn load_from_json(file_path: GString) -> Result<Self, DialogError> {
 
-    pub fn load_from_text(text: GString, file_path: GString) -> Result<Self, DialogError> {
+    fn parse_json_text(text: GString, file_path: GString) -> Result<Dictionary, DialogError> {
         let mut json = Json::new_gd();
         json.parse(text.clone());
         let err_msg = json.get_error_message();
@@ -49,10 +152,10 @@ impl DialogTrack {
                 convert_error: format!("{:?}", error),
             });
         }
-        Self::load_from_dict(json_dict.unwrap(), file_path)
+        Ok(json_dict.unwrap())
     }
 
-    pub fn load_from_dict(dict: Dictionary, file_path: GString) -> Result<Self, DialogError> {
+    fn parse_dict(dict: Dictionary, file_path: GString) -> Result<Self, DialogError> {
         if !dict.contains_key("nodes") {
             return Err(DialogError::InternalJsonParseError {
                 file: file_path.to_string(),
@@ -78,8 +181,19 @@ impl DialogTrack {
             });
         }
 
-        let array = node_array.unwrap();
-        let mut zelf = Self { lines: Vec::new() };
+        let lines = Self::parse_nodes(&node_array.unwrap(), &file_path, false)?;
+        let mut zelf = Self::from_lines(lines, file_path.to_string().as_str())?;
+        zelf.source = Some(dict);
+        Ok(zelf)
+    }
+
+    /// Parses an array of nodes. Consecutive `if`, `elif` and `else` nodes are grouped into a single [Line::Branch]. Labels are only allowed at the top level (`nested == false`)
+    fn parse_nodes(
+        array: &Array<Variant>,
+        file_path: &GString,
+        nested: bool,
+    ) -> Result<Vec<Line>, DialogError> {
+        let mut lines = Vec::new();
         for (index, node_var) in array.iter_shared().enumerate() {
             let Ok(node) = Dictionary::try_from_variant(&node_var) else {
                 godot_warn!("Failed to parse node as dictionary: {:?}", node_var);
@@ -92,16 +206,45 @@ impl DialogTrack {
                     reason: "Nodes require a 'type' entry to be properly parsed!".to_string(),
                 });
             }
-            let line_value: Line = match node
+            let node_error = |reason: String| DialogError::InternalJsonParseError {
+                file: file_path.to_string(),
+                error_node: Json::stringify(node.to_variant()).to_string(),
+                reason,
+            };
+            let node_type = node
                 .get_or_nil("type".to_variant())
                 .to_string()
-                .to_lowercase()
-                .as_str()
-            {
+                .to_lowercase();
+            let line_value: Line = match node_type.as_str() {
                 "text" => Self::parse_text_line(&node),
-                "choice" => Self::parse_choice_line(&node),
+                "choice" => Self::parse_choice_line(&node, file_path)?,
                 "signal" => Self::parse_signal_line(&node),
                 "action" => Self::parse_action_line(&node),
+                "label" if nested => {
+                    return Err(node_error(
+                        "Labels cannot be placed inside of branches".to_string(),
+                    ))
+                }
+                "label" => Self::parse_label_line(&node),
+                "if" => Line::Branch {
+                    branches: vec![Self::parse_branch(&node, file_path, true)?],
+                },
+                "elif" | "else" => {
+                    let Some(Line::Branch { branches }) = lines.last_mut() else {
+                        return Err(node_error(format!(
+                            "'{}' must directly follow an 'if' or 'elif' node",
+                            node_type
+                        )));
+                    };
+                    if branches.last().is_some_and(ConditionalBranch::is_else) {
+                        return Err(node_error(format!(
+                            "'{}' cannot follow an 'else' node",
+                            node_type
+                        )));
+                    }
+                    branches.push(Self::parse_branch(&node, file_path, node_type == "elif")?);
+                    continue;
+                }
                 _ => {
                     godot_warn!(
                         "Unexpected node type: '{}'",
@@ -120,10 +263,203 @@ impl DialogTrack {
                     reason: format!("Failed to parse single node at index {}", index).to_string(),
                 });
             }
-            zelf.lines.push(line_value);
+            lines.push(line_value);
         }
+        Ok(lines)
+    }
 
-        Ok(zelf)
+    /// Parses the nested `nodes` of an `if`, `elif` or `else` node. Only `else` nodes may go without a `requires` query
+    fn parse_branch(
+        node: &Dictionary,
+        file_path: &GString,
+        conditional: bool,
+    ) -> Result<ConditionalBranch, DialogError> {
+        let requires = node.get("requires").unwrap_or("".to_variant()).to_string();
+        if conditional == requires.trim().is_empty() {
+            return Err(DialogError::InternalJsonParseError {
+                file: file_path.to_string(),
+                error_node: Json::stringify(node.to_variant()).to_string(),
+                reason: if conditional {
+                    "'if' and 'elif' nodes require a 'requires' query".to_string()
+                } else {
+                    "'else' nodes cannot have a 'requires' query".to_string()
+                },
+            });
+        }
+        Ok(ConditionalBranch {
+            requires,
+            lines: Self::parse_nested_nodes(node, file_path)?,
+        })
+    }
+
+    /// Parses the optional `nodes` array nested in a branch or choice option
+    fn parse_nested_nodes(
+        node: &Dictionary,
+        file_path: &GString,
+    ) -> Result<Vec<Line>, DialogError> {
+        let Some(nodes) = node.get("nodes") else {
+            return Ok(Vec::new());
+        };
+        let Ok(array) = Array::<Variant>::try_from_variant(&nodes) else {
+            return Err(DialogError::InternalJsonParseError {
+                file: file_path.to_string(),
+                error_node: Json::stringify(node.to_variant()).to_string(),
+                reason: "'nodes' must be an array".to_string(),
+            });
+        };
+        Self::parse_nodes(&array, file_path, true)
+    }
+
+    /// Builds a track from already parsed lines, indexing its labels. Does not validate the track
+    pub fn from_lines(lines: Vec<Line>, file: &str) -> Result<Self, DialogError> {
+        let mut labels = HashMap::new();
+        for (index, line) in lines.iter().enumerate() {
+            if let Line::Label { name } = line {
+                if labels.insert(name.clone(), index).is_some() {
+                    return Err(DialogError::DuplicateLabel {
+                        file: file.to_string(),
+                        label: name.clone(),
+                    });
+                }
+            }
+        }
+        Ok(Self {
+            lines,
+            labels,
+            file: file.to_string(),
+            source: None,
+        })
+    }
+
+    fn validate(&self) -> Result<(), DialogError> {
+        self.validate_jumps()?;
+        self.validate_voices()
+    }
+
+    /// Ensures every voice clip referenced by this track exists, so missing audio shows up on load instead of during playback
+    fn validate_voices(&self) -> Result<(), DialogError> {
+        for (_, line) in self.walk() {
+            let (Line::Text { voice, .. } | Line::Choice { voice, .. }) = line else {
+                continue;
+            };
+            if !voice.is_empty() && !ResourceLoader::singleton().exists(voice.to_godot()) {
+                return Err(DialogError::MissingResource {
+                    file: self.file.clone(),
+                    path: voice.clone(),
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Ensures every `jump <label>` and `jump <file>:<label>` in this track points at a label that exists
+    fn validate_jumps(&self) -> Result<(), DialogError> {
+        for target in self.jump_targets() {
+            let target = target.map_err(|reason| DialogError::InvalidJump {
+                file: self.file.clone(),
+                reason,
+            })?;
+            match target {
+                JumpTarget::Index(_) => (), // index jumps can't be meaningfully validated here
+                JumpTarget::Label(label) => {
+                    if !self.labels.contains_key(&label) {
+                        return Err(DialogError::UnknownLabel {
+                            file: self.file.clone(),
+                            label,
+                        });
+                    }
+                }
+                JumpTarget::Track { file, label } => {
+                    let file = self.resolve_track_path(&file);
+                    let other = Self::load_unvalidated(file.to_godot())?;
+                    if let Some(label) = label {
+                        if !other.labels.contains_key(&label) {
+                            return Err(DialogError::UnknownLabel { file, label });
+                        }
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Collects the targets of every `jump` command found in action nodes and choice option actions
+    pub fn jump_targets(&self) -> Vec<Result<JumpTarget, String>> {
+        let mut targets = Vec::new();
+        for (_, line) in self.walk() {
+            let mut codes: Vec<&String> = Vec::new();
+            match line {
+                Line::Action { action } => codes.push(action),
+                Line::Choice { options, .. } => {
+                    codes.extend(options.iter().map(|opt| &opt.action));
+                }
+                _ => (),
+            }
+            for code in codes {
+                for call in Blackboard::split_commands(code) {
+                    if call.name == "jump" && call.args.len() == 1 {
+                        targets.push(JumpTarget::parse(&call.args[0]));
+                    }
+                }
+            }
+        }
+        targets
+    }
+
+    /// Every line of the track depth first, including the lines nested in branches and choice options. Each line is paired with its position, in the form of [DialogCursor::position](super::dialog_cursor::DialogCursor::position): the top level index, followed by the nested sequence and line index for each level
+    pub fn walk(&self) -> Vec<(Vec<usize>, &Line)> {
+        let mut found = Vec::new();
+        for (index, line) in self.lines.iter().enumerate() {
+            Self::walk_line(vec![index], line, &mut found);
+        }
+        found
+    }
+
+    fn walk_line<'a>(
+        position: Vec<usize>,
+        line: &'a Line,
+        found: &mut Vec<(Vec<usize>, &'a Line)>,
+    ) {
+        found.push((position.clone(), line));
+        for (nested_index, lines) in line.nested().into_iter().enumerate() {
+            for (index, nested) in lines.iter().enumerate() {
+                let mut nested_position = position.clone();
+                nested_position.extend([nested_index, index]);
+                Self::walk_line(nested_position, nested, found);
+            }
+        }
+    }
+
+    /// Whether this track was loaded from a file, rather than from internal text or a dictionary
+    pub fn is_from_file(&self) -> bool {
+        self.file.contains("://")
+    }
+
+    /// Gets the index of the line a label points at
+    pub fn get_label_index(&self, label: &str) -> Option<usize> {
+        self.labels.get(label).copied()
+    }
+
+    /// Resolves a track path relative to the directory of this track. Paths with a scheme (`res://`, `user://`) are left as is
+    pub fn resolve_track_path(&self, path: &str) -> String {
+        if path.contains("://") {
+            return path.to_string();
+        }
+        match self.file.rsplit_once('/') {
+            Some((dir, _)) if self.file.contains("://") => format!("{}/{}", dir, path),
+            _ => path.to_string(),
+        }
+    }
+
+    fn parse_label_line(node: &Dictionary) -> Line {
+        if !node.contains_key("name") {
+            return Line::None;
+        }
+        let name = node.get("name").unwrap_or("".to_variant()).to_string();
+        if name.is_empty() {
+            return Line::None;
+        }
+        Line::Label { name }
     }
     fn parse_text_line(node_data: &Dictionary) -> Line {
         if !node_data.contains_key("content".to_variant()) {
@@ -142,6 +478,11 @@ impl DialogTrack {
                 .get("requires")
                 .unwrap_or("".to_variant())
                 .to_string(),
+            id: node_data.get("id").unwrap_or("".to_variant()).to_string(),
+            voice: node_data
+                .get("voice")
+                .unwrap_or("".to_variant())
+                .to_string(),
         }
     }
     fn parse_signal_line(node_data: &Dictionary) -> Line {
@@ -175,9 +516,10 @@ impl DialogTrack {
         }
     }
 
-    fn parse_choice_line(node: &Dictionary) -> Line {
+    /// Parses a choice node. Errors from the nested branches of its options are passed on, any other problem gives [Line::None]
+    fn parse_choice_line(node: &Dictionary, file_path: &GString) -> Result<Line, DialogError> {
         if !node.contains_key("options") {
-            return Line::None;
+            return Ok(Line::None);
         }
         let vararr = node.get_or_nil("options");
         let arr: Array<Variant> = Array::try_from_variant(&vararr).unwrap_or_default();
@@ -188,17 +530,39 @@ impl DialogTrack {
                 continue;
             };
             let opt_entry = ChoiceOptionEntry::try_load_from(&dict);
-            let Some(entry) = opt_entry else {
+            let Some(mut entry) = opt_entry else {
                 godot_error!(
                     "Failed to load choice option at index {}. Data found: {}",
                     index,
                     Json::stringify(dict.to_variant()).to_string()
                 );
-                return Line::None; // no "teehee :3 I malformed my data" mfers allowed >:3
+                return Ok(Line::None); // no "teehee :3 I malformed my data" mfers allowed >:3
             };
+            entry.nodes = Self::parse_nested_nodes(&dict, file_path)?;
             choice_buffer.push(entry);
         }
-        Line::Choice {
+        let timeout = match node.get("timeout") {
+            None => None,
+            Some(seconds) => {
+                let default = node.get("default").unwrap_or(0.to_variant());
+                let (Ok(seconds), Ok(default)) = (
+                    f64::try_from_variant(&seconds),
+                    i64::try_from_variant(&default)
+                        .or(f64::try_from_variant(&default).map(|d| d as i64)),
+                ) else {
+                    godot_error!("Choice timeout and default must be numbers");
+                    return Ok(Line::None);
+                };
+                match ChoiceTimeout::new(seconds, default, choice_buffer.len()) {
+                    Ok(timeout) => Some(timeout),
+                    Err(reason) => {
+                        godot_error!("{}", reason);
+                        return Ok(Line::None);
+                    }
+                }
+            }
+        };
+        Ok(Line::Choice {
             prompt: node
                 .get("prompt".to_variant())
                 .unwrap_or("".to_variant())
@@ -208,7 +572,16 @@ impl DialogTrack {
                 .unwrap_or("".to_variant())
                 .to_string(),
             options: choice_buffer,
-        }
+            id: node
+                .get("id".to_variant())
+                .unwrap_or("".to_variant())
+                .to_string(),
+            voice: node
+                .get("voice".to_variant())
+                .unwrap_or("".to_variant())
+                .to_string(),
+            timeout,
+        })
     }
 }
 
@@ -218,11 +591,21 @@ pub enum Line {
         text: String,
         character: String,
         requires: String,
+        /// optional stable translation key, used instead of the text itself when set
+        id: String,
+        /// optional path to a voice-over audio stream played with the line
+        voice: String,
     },
     Choice {
         prompt: String,
         character: String,
         options: Vec<ChoiceOptionEntry>,
+        /// optional stable translation key for the prompt
+        id: String,
+        /// optional path to a voice-over audio stream played with the prompt
+        voice: String,
+        /// selects a default option when the player takes too long
+        timeout: Option<ChoiceTimeout>,
     },
     Action {
         action: String,
@@ -231,14 +614,169 @@ pub enum Line {
         name: String,
         args: Vec<String>,
     },
+    Label {
+        name: String,
+    },
+    /// `if` / `elif` / `else` nodes. The lines of the first branch whose query passes are played, then the lines after the branch
+    Branch {
+        branches: Vec<ConditionalBranch>,
+    },
     None,
 }
 
+impl Line {
+    /// The sequences of lines nested in this line, one per branch of a [Line::Branch] or option of a [Line::Choice]
+    pub fn nested(&self) -> Vec<&[Line]> {
+        match self {
+            Line::Branch { branches } => branches
+                .iter()
+                .map(|branch| branch.lines.as_slice())
+                .collect(),
+            Line::Choice { options, .. } => options
+                .iter()
+                .map(|option| option.nodes.as_slice())
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// A stable identity for text and choice lines, used to remember which lines the player has seen. Prefers the line `id` and falls back to the character and text
+    pub fn identity(&self) -> Option<String> {
+        let (id, character, text) = match self {
+            Line::Text {
+                id,
+                character,
+                text,
+                ..
+            } => (id, character, text),
+            Line::Choice {
+                id,
+                character,
+                prompt,
+                ..
+            } => (id, character, prompt),
+            _ => return None,
+        };
+        if id.is_empty() {
+            Some(format!("{}: {}", character, text))
+        } else {
+            Some(id.clone())
+        }
+    }
+}
+
+/// One `if`, `elif` or `else` of a [Line::Branch]
+#[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Debug)]
+pub struct ConditionalBranch {
+    /// the blackboard query, empty for `else`
+    pub requires: String,
+    pub lines: Vec<Line>,
+}
+
+impl ConditionalBranch {
+    pub fn is_else(&self) -> bool {
+        self.requires.is_empty()
+    }
+}
+
+/// The destination of a `jump` command
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum JumpTarget {
+    /// `jump 4` jumps to the line at that index. Kept for older tracks, prefer labels
+    Index(usize),
+    /// `jump intro` jumps to the label "intro" in the current track
+    Label(String),
+    /// `jump res://tracks/other.json:intro` switches to another track and continues from the label (or the start of the track if no label is given)
+    Track { file: String, label: Option<String> },
+}
+
+impl JumpTarget {
+    /// Parses a jump target. Only targets that look like a track file (`res://` or `user://` paths, or files ending in `.json` or `.dialog`) switch tracks, so labels like `act1.end` stay labels
+    pub fn parse(target: &str) -> Result<Self, String> {
+        let target = target.trim();
+        if target.is_empty() {
+            return Err("Jump target is empty".to_string());
+        }
+        if target.chars().all(|c| c.is_ascii_digit() || c == '.') {
+            return target
+                .parse::<usize>()
+                .map(Self::Index)
+                .map_err(|_| format!("Jump target index \"{}\" is not a whole number", target));
+        }
+        // splitting on the last ':' keeps the scheme of paths like "res://"
+        match target.rsplit_once(':') {
+            Some((file, label))
+                if Self::is_track_file(file) && !label.is_empty() && !label.contains('/') =>
+            {
+                Ok(Self::Track {
+                    file: file.to_string(),
+                    label: Some(label.to_string()),
+                })
+            }
+            _ if Self::is_track_file(target) => Ok(Self::Track {
+                file: target.to_string(),
+                label: None,
+            }),
+            _ => Ok(Self::Label(target.to_string())),
+        }
+    }
+
+    fn is_track_file(path: &str) -> bool {
+        path.starts_with("res://")
+            || path.starts_with("user://")
+            || path.ends_with(".json")
+            || path.ends_with(".dialog")
+    }
+}
+
+/// The time limit of a timed choice. Stored in milliseconds to keep [Line] comparable
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
+pub struct ChoiceTimeout {
+    pub msec: u64,
+    /// index of the option selected when time runs out
+    pub default: usize,
+}
+
+impl ChoiceTimeout {
+    pub fn new(seconds: f64, default: i64, option_count: usize) -> Result<Self, String> {
+        if !seconds.is_finite() || seconds <= 0.0 {
+            return Err(format!(
+                "Choice timeout must be a positive number of seconds, found {}",
+                seconds
+            ));
+        }
+        if default < 0 || default as usize >= option_count {
+            return Err(format!(
+                "Choice default option {} is out of range, the choice has {} options",
+                default, option_count
+            ));
+        }
+        Ok(Self {
+            msec: (seconds * 1000.0).round() as u64,
+            default: default as usize,
+        })
+    }
+
+    pub fn seconds(&self) -> f64 {
+        self.msec as f64 / 1000.0
+    }
+}
+
 #[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone)]
 pub struct ChoiceOptionEntry {
     pub text: String,
     pub requires: String,
     pub action: String,
+    /// optional stable translation key for the option text
+    pub id: String,
+    /// hidden once it has been chosen
+    pub once: bool,
+    /// remembered once chosen so it can be styled as already chosen
+    pub sticky: bool,
+    /// only shown when no other option is available
+    pub fallback: bool,
+    /// lines played when this option is chosen, before returning to the lines after the choice
+    pub nodes: Vec<Line>,
 }
 
 #[derive(Clone)]
@@ -257,6 +795,27 @@ pub enum DialogError {
         error_node: String,
         reason: String,
     },
+    UnknownLabel {
+        file: String,
+        label: String,
+    },
+    DuplicateLabel {
+        file: String,
+        label: String,
+    },
+    ScreenplayParseError {
+        file: String,
+        line: usize,
+        reason: String,
+    },
+    MissingResource {
+        file: String,
+        path: String,
+    },
+    InvalidJump {
+        file: String,
+        reason: String,
+    },
     Unexpected,
 }
 
@@ -288,6 +847,32 @@ impl fmt::Debug for DialogError {
                 .field("error_node", &Self::format_dict_string(error_node))
                 .field("reason", reason)
                 .finish(),
+            Self::UnknownLabel { file, label } => f
+                .debug_struct("UnknownLabel")
+                .field("file", file)
+                .field("label", label)
+                .finish(),
+            Self::DuplicateLabel { file, label } => f
+                .debug_struct("DuplicateLabel")
+                .field("file", file)
+                .field("label", label)
+                .finish(),
+            Self::ScreenplayParseError { file, line, reason } => f
+                .debug_struct("ScreenplayParseError")
+                .field("file", file)
+                .field("line", line)
+                .field("reason", reason)
+                .finish(),
+            Self::MissingResource { file, path } => f
+                .debug_struct("MissingResource")
+                .field("file", file)
+                .field("path", path)
+                .finish(),
+            Self::InvalidJump { file, reason } => f
+                .debug_struct("InvalidJump")
+                .field("file", file)
+                .field("reason", reason)
+                .finish(),
             Self::Unexpected => write!(f, "Unexpected"),
         }
     }
@@ -321,6 +906,118 @@ impl ChoiceOptionEntry {
             text: dict.get("text").unwrap().to_string(),
             requires: dict.get("requires").unwrap_or("".to_variant()).to_string(),
             action: dict.get("action").unwrap().to_string(),
+            id: dict.get("id").unwrap_or("".to_variant()).to_string(),
+            once: Self::get_flag(dict, "once"),
+            sticky: Self::get_flag(dict, "sticky"),
+            fallback: Self::get_flag(dict, "fallback"),
+            nodes: Vec::new(),
         })
     }
+
+    fn get_flag(dict: &Dictionary, key: &str) -> bool {
+        dict.get(key)
+            .and_then(|flag| bool::try_from_variant(&flag).ok())
+            .unwrap_or(false)
+    }
+
+    /// Whether choosing this option should be remembered
+    pub fn is_remembered(&self) -> bool {
+        self.once || self.sticky
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn track(file: &str, label: Option<&str>) -> JumpTarget {
+        JumpTarget::Track {
+            file: file.to_string(),
+            label: label.map(|label| label.to_string()),
+        }
+    }
+
+    #[test]
+    fn jump_targets_only_switch_tracks_for_track_files() {
+        assert_eq!(JumpTarget::parse("4"), Ok(JumpTarget::Index(4)));
+        assert_eq!(
+            JumpTarget::parse("act1.end"),
+            Ok(JumpTarget::Label("act1.end".to_string()))
+        );
+        assert_eq!(
+            JumpTarget::parse("other.json:start"),
+            Ok(track("other.json", Some("start")))
+        );
+        assert_eq!(
+            JumpTarget::parse("res://tracks/other.dialog"),
+            Ok(track("res://tracks/other.dialog", None))
+        );
+        assert_eq!(
+            JumpTarget::parse("user://mods/intro:start"),
+            Ok(track("user://mods/intro", Some("start")))
+        );
+    }
+
+    fn action(action: &str) -> Line {
+        Line::Action {
+            action: action.to_string(),
+        }
+    }
+
+    fn branch(requires: &str, lines: Vec<Line>) -> ConditionalBranch {
+        ConditionalBranch {
+            requires: requires.to_string(),
+            lines,
+        }
+    }
+
+    #[test]
+    fn walk_reports_nested_positions() {
+        let lines = vec![
+            action("a"),
+            Line::Branch {
+                branches: vec![
+                    branch("x", vec![action("b")]),
+                    branch(
+                        "",
+                        vec![
+                            action("c"),
+                            Line::Branch {
+                                branches: vec![branch("y", vec![action("d")])],
+                            },
+                        ],
+                    ),
+                ],
+            },
+            action("e"),
+        ];
+        let track = DialogTrack::from_lines(lines, "test").unwrap();
+        let positions: Vec<(Vec<usize>, Option<&str>)> = track
+            .walk()
+            .into_iter()
+            .map(|(position, line)| match line {
+                Line::Action { action } => (position, Some(action.as_str())),
+                _ => (position, None),
+            })
+            .collect();
+        assert_eq!(
+            positions,
+            vec![
+                (vec![0], Some("a")),
+                (vec![1], None),
+                (vec![1, 0, 0], Some("b")),
+                (vec![1, 1, 0], Some("c")),
+                (vec![1, 1, 1], None),
+                (vec![1, 1, 1, 0, 0], Some("d")),
+                (vec![2], Some("e")),
+            ]
+        );
+    }
+
+    #[test]
+    fn jump_targets_reject_fractional_indices() {
+        assert!(JumpTarget::parse("1.5").is_err());
+        assert!(JumpTarget::parse("1.").is_err());
+        assert!(JumpTarget::parse("  ").is_err());
+    }
 }
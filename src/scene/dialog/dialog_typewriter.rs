@@ -0,0 +1,357 @@
+//! Inline typewriter control tags for dialog text.
+//!
+//! - `[pause=0.5]` waits half a second before revealing the rest of the text
+//! - `[speed=2.0]` reveals the following text twice as fast. `[speed=1]` goes back to the normal speed
+//! - `[emit=name]` emits `text_emitted` on [super::dialog_events::DialogEvents] once the text before it is revealed
+//!
+//! Control tags are stripped from the displayed text, every other BBCode tag is kept. Characters are counted the way `RichTextLabel` counts them for `visible_characters`: formatting tags take no characters, `[lb]`, `[rb]`, `[char]` and an `[img]` take one, and tags `RichTextLabel` doesn't know are displayed as text.
+
+/// An `[emit=name]` tag, fired once `time` seconds of the reveal have passed
+#[derive(Debug, Clone, PartialEq)]
+pub struct TypewriterEvent {
+    pub time: f64,
+    pub name: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TypewriterScript {
+    /// the text to display, with control tags removed
+    pub text: String,
+    /// for each visible character, the time in seconds at which it becomes visible
+    pub reveal_times: Vec<f64>,
+    pub events: Vec<TypewriterEvent>,
+    pub words: usize,
+    /// problems with control tags, the tags are kept in the text as-is and displayed as text
+    pub warnings: Vec<String>,
+}
+
+enum Token {
+    /// text that is displayed, counted in visible characters
+    Visible(String, usize),
+    /// BBCode formatting tags that are kept but take no characters
+    Hidden(String),
+    Pause(f64),
+    Speed(f64),
+    Emit(String),
+}
+
+impl TypewriterScript {
+    /// Parses `text`, timing the reveal so that the text takes as long as it would at `words_per_minute`, before pauses and speed changes
+    pub fn parse(text: &str, words_per_minute: f32) -> Self {
+        const MINUTES_TO_SECONDS: f64 = 60.0;
+        let mut warnings = Vec::new();
+        let tokens = tokenize(text, &mut warnings);
+
+        let mut display = String::new();
+        let mut visible = String::new();
+        for token in tokens.iter() {
+            match token {
+                Token::Visible(text, _) => {
+                    display += text;
+                    visible += text;
+                }
+                Token::Hidden(tag) => display += tag,
+                _ => (),
+            }
+        }
+        let visible_count: usize = tokens
+            .iter()
+            .map(|token| match token {
+                Token::Visible(_, count) => *count,
+                _ => 0,
+            })
+            .sum();
+        let words = visible.split_whitespace().count();
+        let duration =
+            words as f64 / words_per_minute.max(f32::EPSILON) as f64 * MINUTES_TO_SECONDS;
+        let char_time = if visible_count == 0 {
+            0.0
+        } else {
+            duration / visible_count as f64
+        };
+
+        let mut time = 0.0;
+        let mut speed = 1.0;
+        let mut reveal_times = Vec::with_capacity(visible_count);
+        let mut events = Vec::new();
+        for token in tokens {
+            match token {
+                Token::Visible(_, count) => {
+                    for _ in 0..count {
+                        time += char_time / speed;
+                        reveal_times.push(time);
+                    }
+                }
+                Token::Hidden(_) => (),
+                Token::Pause(seconds) => time += seconds,
+                Token::Speed(value) => speed = value,
+                Token::Emit(name) => events.push(TypewriterEvent { time, name }),
+            }
+        }
+        Self {
+            text: display,
+            reveal_times,
+            events,
+            words,
+            warnings,
+        }
+    }
+
+    /// The time at which the last character is revealed and the last event fired
+    pub fn duration(&self) -> f64 {
+        let last_char = self.reveal_times.last().copied().unwrap_or(0.0);
+        let last_event = self.events.last().map(|event| event.time).unwrap_or(0.0);
+        last_char.max(last_event)
+    }
+
+    /// The number of characters visible after `time` seconds
+    pub fn visible_characters_at(&self, time: f64) -> usize {
+        self.reveal_times.partition_point(|reveal| *reveal <= time)
+    }
+}
+
+fn tokenize(text: &str, warnings: &mut Vec<String>) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut current = String::new();
+    let mut current_count = 0;
+    let mut rest = text;
+    while let Some(c) = rest.chars().next() {
+        let tag = if c == '[' {
+            rest.find(']').map(|end| &rest[..=end])
+        } else {
+            None
+        };
+        let Some(tag) = tag else {
+            current.push(c);
+            current_count += 1;
+            rest = &rest[c.len_utf8()..];
+            continue;
+        };
+        let name = tag_name(tag);
+        // an image takes one character, its path is not displayed
+        let tag = match (name, rest.find("[/img]")) {
+            ("img", Some(end)) => &rest[..end + "[/img]".len()],
+            _ => tag,
+        };
+        rest = &rest[tag.len()..];
+        if name == "img" || SINGLE_CHARACTER_TAGS.contains(&name) {
+            current += tag;
+            current_count += 1;
+            continue;
+        }
+        if !current.is_empty() {
+            tokens.push(Token::Visible(std::mem::take(&mut current), current_count));
+            current_count = 0;
+        }
+        tokens.push(parse_tag(tag, warnings));
+    }
+    if !current.is_empty() {
+        tokens.push(Token::Visible(current, current_count));
+    }
+    tokens
+}
+
+/// Tags that display a single character, such as `[lb]` for `[` or the Unicode control characters
+const SINGLE_CHARACTER_TAGS: &[&str] = &[
+    "lb", "rb", "char", "lrm", "rlm", "lre", "rle", "lro", "rlo", "pdf", "alm", "lri", "rli",
+    "fsi", "pdi", "zwj", "zwnj", "wj", "shy",
+];
+
+/// BBCode tags of `RichTextLabel` that take no characters, including the built-in effects
+const FORMATTING_TAGS: &[&str] = &[
+    "b",
+    "i",
+    "u",
+    "s",
+    "code",
+    "p",
+    "center",
+    "left",
+    "right",
+    "fill",
+    "indent",
+    "url",
+    "hint",
+    "font",
+    "font_size",
+    "opentype_features",
+    "lang",
+    "table",
+    "cell",
+    "ul",
+    "ol",
+    "color",
+    "bgcolor",
+    "fgcolor",
+    "outline_size",
+    "outline_color",
+    "dropcap",
+    "wave",
+    "tornado",
+    "shake",
+    "fade",
+    "rainbow",
+    "pulse",
+];
+
+/// The name of a tag without its closing slash or arguments, e.g. `color` for `[/color]` and `[color=red]`
+fn tag_name(tag: &str) -> &str {
+    let inner = tag[1..tag.len() - 1].trim_start_matches('/');
+    let end = inner.find(['=', ' ']).unwrap_or(inner.len());
+    &inner[..end]
+}
+
+/// Tags `RichTextLabel` doesn't know are displayed as they are written
+fn text_tag(tag: &str) -> Token {
+    Token::Visible(tag.to_string(), tag.chars().count())
+}
+
+fn parse_tag(tag: &str, warnings: &mut Vec<String>) -> Token {
+    let inner = &tag[1..tag.len() - 1];
+    let Some((name, value)) = inner.split_once('=') else {
+        return if FORMATTING_TAGS.contains(&tag_name(tag)) {
+            Token::Hidden(tag.to_string())
+        } else {
+            text_tag(tag)
+        };
+    };
+    let value = value.trim();
+    match name.trim() {
+        "pause" => match value.parse::<f64>() {
+            Ok(seconds) if seconds >= 0.0 => Token::Pause(seconds),
+            _ => {
+                warnings.push(format!("Invalid pause duration in \"{}\"", tag));
+                text_tag(tag)
+            }
+        },
+        "speed" => match value.parse::<f64>() {
+            Ok(speed) if speed > 0.0 => Token::Speed(speed),
+            _ => {
+                warnings.push(format!("Invalid reveal speed in \"{}\"", tag));
+                text_tag(tag)
+            }
+        },
+        "emit" if !value.is_empty() => Token::Emit(value.to_string()),
+        "emit" => {
+            warnings.push(format!("Missing event name in \"{}\"", tag));
+            text_tag(tag)
+        }
+        _ if FORMATTING_TAGS.contains(&tag_name(tag)) => Token::Hidden(tag.to_string()),
+        _ => text_tag(tag),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// at 60 words per minute a one word text takes a second to reveal
+    const ONE_WORD_PER_SECOND: f32 = 60.0;
+
+    fn assert_times(actual: &[f64], expected: &[f64]) {
+        assert_eq!(
+            actual.len(),
+            expected.len(),
+            "{:?} != {:?}",
+            actual,
+            expected
+        );
+        for (actual, expected) in actual.iter().zip(expected) {
+            assert!(
+                (actual - expected).abs() < 1e-9,
+                "{:?} != {:?}",
+                actual,
+                expected
+            );
+        }
+    }
+
+    fn visible_count(text: &str) -> usize {
+        TypewriterScript::parse(text, ONE_WORD_PER_SECOND)
+            .reveal_times
+            .len()
+    }
+
+    #[test]
+    fn reveals_characters_evenly() {
+        let script = TypewriterScript::parse("abcd", ONE_WORD_PER_SECOND);
+        assert_eq!(script.words, 1);
+        assert_times(&script.reveal_times, &[0.25, 0.5, 0.75, 1.0]);
+        assert!(script.warnings.is_empty());
+    }
+
+    #[test]
+    fn pause_speed_and_emit_tags_change_the_timing() {
+        let script = TypewriterScript::parse("ab[pause=1]cd", ONE_WORD_PER_SECOND);
+        assert_times(&script.reveal_times, &[0.25, 0.5, 1.75, 2.0]);
+        assert_eq!(script.duration(), 2.0);
+
+        let script = TypewriterScript::parse("ab[speed=2]cd[speed=1]", ONE_WORD_PER_SECOND);
+        assert_times(&script.reveal_times, &[0.25, 0.5, 0.625, 0.75]);
+
+        let script =
+            TypewriterScript::parse("ab[emit=door]cd[pause=0.5][emit=end]", ONE_WORD_PER_SECOND);
+        assert_eq!(
+            script.events,
+            vec![
+                TypewriterEvent {
+                    time: 0.5,
+                    name: "door".to_string(),
+                },
+                TypewriterEvent {
+                    time: 1.5,
+                    name: "end".to_string(),
+                },
+            ]
+        );
+        // the last event comes after the last character
+        assert_eq!(script.duration(), 1.5);
+    }
+
+    #[test]
+    fn control_tags_are_stripped_and_bbcode_is_kept() {
+        let script = TypewriterScript::parse(
+            "[b]Hi[/b] [pause=1][color=red]there[/color][emit=wave]",
+            ONE_WORD_PER_SECOND,
+        );
+        assert_eq!(script.text, "[b]Hi[/b] [color=red]there[/color]");
+        assert_eq!(script.reveal_times.len(), "Hi there".len());
+        assert_eq!(script.words, 2);
+    }
+
+    #[test]
+    fn counts_characters_like_rich_text_label() {
+        assert_eq!(visible_count("[lb]x[rb]"), 3);
+        assert_eq!(visible_count("[char=2192] go"), 4);
+        assert_eq!(visible_count("[img width=16]res://coin.png[/img] 5"), 3);
+        assert_eq!(
+            visible_count("[font_size=20][wave amp=50]é[/wave][/font_size]"),
+            1
+        );
+        // unknown tags are displayed as text
+        assert_eq!(visible_count("[ghost]a"), "[ghost]a".len());
+        assert_eq!(visible_count("[pause=soon]a"), "[pause=soon]a".len());
+        assert_eq!(visible_count("a [b"), 4);
+    }
+
+    #[test]
+    fn visible_characters_follow_the_reveal() {
+        let script = TypewriterScript::parse("ab[pause=1]cd", ONE_WORD_PER_SECOND);
+        assert_eq!(script.visible_characters_at(0.0), 0);
+        assert_eq!(script.visible_characters_at(0.25), 1);
+        assert_eq!(script.visible_characters_at(1.0), 2);
+        assert_eq!(script.visible_characters_at(script.duration()), 4);
+        assert_eq!(script.visible_characters_at(f64::INFINITY), 4);
+    }
+
+    #[test]
+    fn malformed_tag_values_are_warnings() {
+        let text = "[pause=-1]a[speed=0]b[speed=fast]c[emit= ]d";
+        let script = TypewriterScript::parse(text, ONE_WORD_PER_SECOND);
+        assert_eq!(script.warnings.len(), 4, "{:?}", script.warnings);
+        // the tags are displayed as written
+        assert_eq!(script.text, text);
+        assert_eq!(script.reveal_times.len(), text.len());
+        assert!(script.events.is_empty());
+    }
+}
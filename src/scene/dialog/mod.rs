@@ -2,12 +2,25 @@ use self::core_dialog::SqoreDialog;
 use godot::{engine::Engine, prelude::*};
 
 pub mod core_dialog;
+pub mod dialog_bark;
 pub mod dialog_blackboard;
 pub mod dialog_builder;
+pub mod dialog_character;
+pub mod dialog_cursor;
 pub mod dialog_events;
+pub mod dialog_expression;
 pub mod dialog_gui;
+pub mod dialog_history;
+pub mod dialog_lint;
+pub mod dialog_literal;
+pub mod dialog_localization;
+pub mod dialog_playthrough;
+pub mod dialog_runner;
+pub mod dialog_screenplay;
 pub mod dialog_settings;
+pub mod dialog_template;
 pub mod dialog_track;
+pub mod dialog_typewriter;
 
 pub fn register_singleton() {
     Engine::singleton().register_singleton(
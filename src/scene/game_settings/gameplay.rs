@@ -61,6 +61,18 @@ impl GameGameplaySettings {
         }
         GString::from("")
     }
+
+    /// The value of the bool, number or string option with this key, checked in that order
+    pub(crate) fn get_option_value(&self, key: GString) -> Option<Variant> {
+        if let Some(option) = self.get_option_bool(key.clone()) {
+            return Some(option.bind().value.to_variant());
+        }
+        if let Some(option) = self.get_option_number(key.clone()) {
+            return Some(option.bind().value.to_variant());
+        }
+        self.get_option_string(key)
+            .map(|option| option.bind().value.to_variant())
+    }
 }
 
 //
@@ -83,13 +83,7 @@ pub impl SaveDataBuilder {
         };
         let opt_cast = Json::parse_string(file.get_as_text());
         if let Ok(data) = Dictionary::try_from_variant(&opt_cast) {
-            for entry in data.iter_shared() {
-                let skey = GString::from_variant(&entry.0);
-                if skey.to_string().starts_with(INTERNAL_PREFIX) {
-                } else {
-                    self.data.set(skey, entry.1);
-                }
-            }
+            self.load_entries(data);
         };
 
         true
@@ -98,6 +92,11 @@ pub impl SaveDataBuilder {
     #[func]
     pub fn load_from(dict: Dictionary) -> Gd<SaveDataBuilder> {
         let mut data = SaveDataBuilder::new_alloc();
+        data.bind_mut().load_entries(dict);
+        data
+    }
+
+    fn load_entries(&mut self, dict: Dictionary) {
         for entry in dict.iter_shared() {
             let skey = GString::from_variant(&entry.0);
             if skey.to_string().starts_with(INTERNAL_PREFIX) {
@@ -105,18 +104,15 @@ pub impl SaveDataBuilder {
                 if let Ok(dict) = Dictionary::try_from_variant(&entry.1) {
                     let child = SaveDataBuilder::load_from(dict);
                     let i_key = skey.to_string().replace(INTERNAL_PREFIX, "");
-                    data.bind_mut()
-                        .child_builders
-                        .insert(i_key.to_godot(), child);
+                    self.child_builders.insert(i_key.to_godot(), child);
                 } else {
                     godot_warn!("Found SaveDataBuilder entry that is corrupted. Please ensure this JSON data is correct. Key=\"{}\"; expected dictionary value. Found: {}", skey, entry.1);
                 }
             } else {
                 // loads a simple data value
-                data.bind_mut().data.set(skey, entry.1);
+                self.data.set(skey, entry.1);
             }
         }
-        data
     }
 
     pub fn try_load_file(file_path: GString) -> Option<Gd<SaveDataBuilder>> {